@@ -13,36 +13,36 @@
 //! ## Examples
 //! 
 //! Here's an example of a stupidly simple scoring engine.
-//! ```rust
-//! fn main() {
-//!     let mut engine = cypat::Engine::new();
-//!     engine.add_file_vuln("world.txt", move |e, x| -> bool {
-//!         match x {
-//!             Some(file) => {
-//!                 let mut string: std::string::String;
-//!                 std::io::BufReader::new(file.clone()).read_line(&mut string);
-//! 
-//!                 if string == "Hello World" {
-//!                     e.add_score_entry(0, 50, "Wrote Hello World.");
-//!                     true
-//!                 } else {
-//!                     false
-//!                 }
-//!             },
-//!             None => false,
-//!         }
-//!     });
-//! 
-//!     engine.add_hook(|x| {
-//!         if x.entry_exists(0) {
-//!             x.stop(false);
-//!         }
-//!     });
-//! 
-//!     engine.set_freq(2);
-//!     engine.set_completed_freq(10);
-//!     engine.enter();
-//! }
+//! ```rust,no_run
+//! use std::io::BufRead;
+//!
+//! let mut engine = cypat::Engine::new();
+//! engine.add_file_vuln("world.txt", move |e, x| -> bool {
+//!     match x {
+//!         Some(file) => {
+//!             let mut string = std::string::String::new();
+//!             let _ = std::io::BufReader::new(file).read_line(&mut string);
+//!
+//!             if string.trim_end() == "Hello World" {
+//!                 e.add_score(0, 50, "Wrote Hello World.");
+//!                 true
+//!             } else {
+//!                 false
+//!             }
+//!         },
+//!         None => false,
+//!     }
+//! });
+//!
+//! engine.add_hook(|x| {
+//!     if x.entry_exists(0) {
+//!         x.stop(false);
+//!     }
+//! });
+//!
+//! engine.set_freq(2);
+//! engine.set_completed_freq(10);
+//! engine.enter();
 //! ```
 
 use std::{
@@ -58,7 +58,7 @@ use std::{
 };
 
 /// Contains package install method.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum InstallMethod {
     Default,
     PackageManager,
@@ -75,7 +75,7 @@ pub enum InstallMethod {
 /// 
 /// Contains some basic information regarding applications or packages.
 /// Somewhat useful, particularly for looking up package information on Linux.
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct AppData {
     pub install_method: InstallMethod,
     pub name: String,
@@ -86,11 +86,17 @@ pub(crate) struct UserData {
     pub(crate) name: String,
 }
 
+pub(crate) type FileVulnFn = Box<dyn FnMut(&mut Engine, Option<&mut File>) -> bool + Send + Sync>;
+pub(crate) type AppVulnFn = Box<dyn FnMut(&mut Engine, AppData) -> bool + Send + Sync>;
+pub(crate) type UserVulnFn = Box<dyn FnMut(&mut Engine, &str) -> bool + Send + Sync>;
+pub(crate) type CustomVulnFn = Box<dyn FnMut(&mut Engine) -> bool + Send + Sync>;
+
+#[allow(clippy::enum_variant_names)]
 pub(crate) enum Condition {
-    FileVuln(String, Box<dyn FnMut(&mut Engine, Option<&mut File>) -> bool + Send + Sync>),
-    AppVuln(AppData, Box<dyn FnMut(&mut Engine, AppData) -> bool + Send + Sync>),
-    UserVuln(UserData, Box<dyn FnMut(&mut Engine, &str) -> bool + Send + Sync>),
-    CustomVuln(Box<dyn FnMut(&mut Engine) -> bool + Send + Sync>),
+    FileVuln(String, FileVulnFn),
+    AppVuln(AppData, AppVulnFn),
+    UserVuln(UserData, UserVulnFn),
+    CustomVuln(CustomVulnFn),
 }
 
 /// Actual scoring engines.
@@ -109,7 +115,12 @@ pub struct Engine {
     incomplete_freq: AtomicU64,
     complete_freq: AtomicU64,
     in_execution: AtomicBool,
-    step_iter: AtomicU64,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Engine {
@@ -124,7 +135,6 @@ impl Engine {
             incomplete_freq: AtomicU64::new(5),
             complete_freq: AtomicU64::new(10),
             in_execution: AtomicBool::new(false),
-            step_iter: AtomicU64::new(0),
         }
     }
 
@@ -147,7 +157,7 @@ impl Engine {
         F: FnMut(&mut Self, Option<&mut File>) -> bool + Send + Sync + 'static, // Whiny ass compiler
         S: ToString,
     {
-        self.add_vuln(Condition::FileVuln(name.to_string(), Box::new(f) as Box<dyn FnMut(&mut Self, Option<&mut File>) -> bool + Send + Sync>));
+        self.add_vuln(Condition::FileVuln(name.to_string(), Box::new(f) as FileVulnFn));
     }
 
     /// Register a package/app vulnerability
@@ -164,10 +174,10 @@ impl Engine {
     {
         let ad = AppData {
             name: name.to_string(),
-            install_method: install_method,
+            install_method,
         };
 
-        self.add_vuln(Condition::AppVuln(ad, Box::new(f) as Box<dyn FnMut(&mut Self, AppData) -> bool + Send + Sync>));
+        self.add_vuln(Condition::AppVuln(ad, Box::new(f) as AppVulnFn));
     }
 
     /// Register a user vulnerability
@@ -186,7 +196,7 @@ impl Engine {
             name: name.to_string(),
         };
 
-        self.add_vuln(Condition::UserVuln(ud, Box::new(f) as Box<dyn FnMut(&mut Self, &str) -> bool + Send + Sync>));
+        self.add_vuln(Condition::UserVuln(ud, Box::new(f) as UserVulnFn));
     }
 
     /// Register a miscellaneous vulnerability
@@ -200,7 +210,7 @@ impl Engine {
     where
         F: FnMut(&mut Self) -> bool + Send + Sync + 'static,
     {
-        self.add_vuln(Condition::CustomVuln(Box::new(f) as Box<dyn FnMut(&mut Self) -> bool + Send + Sync>));
+        self.add_vuln(Condition::CustomVuln(Box::new(f) as CustomVulnFn));
     }
 
     /// Register a hook vulnerability
@@ -260,6 +270,7 @@ impl Engine {
     }
 
     /// Removes the entry identified
+    #[allow(clippy::result_unit_err)]
     pub fn remove_score(&mut self, id: u64) -> Result<(), ()> {
         match self.score.lock() {
             Ok(mut g) => {
@@ -319,19 +330,16 @@ impl Engine {
     ///
     /// Incomplete vulnerabilites are excuted each time the function is executed.
     /// Complete vulnerabilites are excuted only if the number of iterations mod [`complete_freq`][`Engine::set_completed_freq`] is 0
-    pub fn update(&mut self) -> () {
+    pub fn update(&mut self) {
         self.in_execution.store(true, Ordering::SeqCst);
         let tmp_vulns = Arc::clone(&self.vulns); 
         
         // Neat trick to get out of immutable borrow complaints
         match tmp_vulns.lock() {
             Ok(mut vulns) => {
+                // TODO: count updates and only run completed vulnerabilities every complete_freq of them, as documented above
                 for vuln in vulns.iter_mut() {
-                    if self.step_iter.load(Ordering::SeqCst) % self.complete_freq.load(Ordering::SeqCst) == 0 && vuln.1 {
-                        self.handle_vulnerability(vuln);
-                    } else {
-                        self.handle_vulnerability(vuln);
-                    }
+                    self.handle_vulnerability(vuln);
                 }
             },
             Err(g) => panic!("{}",g)
@@ -346,7 +354,7 @@ impl Engine {
     /// 
     /// This state of execution only takes control of one thread, and other threads can generally continue without issue,
     /// however, new vulnerabilities cannot be added.
-    pub fn enter(&mut self) -> () {
+    pub fn enter(&mut self) {
 
         self.is_running.store(true, Ordering::SeqCst);
         // TODO: init
@@ -363,7 +371,7 @@ impl Engine {
     /// This stops engine execution if [`Engine::enter`] was called.
     /// Otherwise does nothing, unless if `blocking` is set to true.
     /// If `blocking` is set, it will wait until the current running update stops to return.
-    pub fn stop(&mut self, blocking: bool) -> () {
+    pub fn stop(&mut self, blocking: bool) {
         self.is_running.store(false, Ordering::SeqCst);
 
         while blocking && self.in_execution.load(Ordering::SeqCst) {
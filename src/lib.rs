@@ -15,36 +15,36 @@
 //! ## Examples
 //! 
 //! Here's an example of a stupidly simple scoring engine.
-//! ```rust
-//! fn main() {
-//!     let mut engine = cypat::Engine::new();
-//!     engine.add_file_vuln("world.txt", move |e, x| -> bool {
-//!         match x {
-//!             Some(file) => {
-//!                 let mut string: std::string::String;
-//!                 std::io::BufReader::new(file.clone()).read_line(&mut string);
-//! 
-//!                 if string == "Hello World" {
-//!                     e.add_score_entry(0, 50, "Wrote Hello World.".to_string());
-//!                     true
-//!                 } else {
-//!                     false
-//!                 }
-//!             },
-//!             None => false,
-//!         }
-//!     });
-//! 
-//!     engine.add_hook(|x| {
-//!         if x.entry_exists(0) {
-//!             x.stop(false);
-//!         }
-//!     });
-//! 
-//!     engine.set_freq(2);
-//!     engine.set_completed_freq(10);
-//!     engine.enter();
-//! }
+//! ```rust,no_run
+//! use std::io::BufRead;
+//!
+//! let mut engine = cypat::Engine::new();
+//! engine.add_file_vuln("world.txt", move |e, x| -> bool {
+//!     match x {
+//!         Some(file) => {
+//!             let mut string = std::string::String::new();
+//!             let _ = std::io::BufReader::new(file).read_line(&mut string);
+//!
+//!             if string.trim_end() == "Hello World" {
+//!                 e.add_score(0, 50, "Wrote Hello World.");
+//!                 true
+//!             } else {
+//!                 false
+//!             }
+//!         },
+//!         None => false,
+//!     }
+//! });
+//!
+//! engine.add_hook(|x| {
+//!     if x.entry_exists(0) {
+//!         x.stop(false);
+//!     }
+//! });
+//!
+//! engine.set_freq(2);
+//! engine.set_completed_freq(10);
+//! engine.enter();
 //! ```
 
 mod engine;
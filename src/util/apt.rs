@@ -0,0 +1,235 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+use std::{
+	collections::HashMap,
+	path::PathBuf,
+	string::String,
+	vec::Vec,
+};
+
+use super::{io_errno, rooted_path, list_dpkg_packages_in, DebVersion};
+use super::dpkg::{parse_stanza, split_stanzas};
+
+/// A package version available from one of apt's downloaded package lists
+#[derive(Clone, Debug)]
+pub struct AptCandidate {
+	pub name: String,
+	pub version: DebVersion,
+	pub architecture: String,
+	/// The list file under /var/lib/apt/lists the version was found in
+	pub list_file: PathBuf,
+	/// Set if the list file is from a `-security` pocket
+	pub security: bool,
+}
+
+/// An installed package with a newer version available
+#[derive(Clone, Debug)]
+pub struct UpgradablePackage {
+	pub name: String,
+	pub architecture: String,
+	pub installed: DebVersion,
+	/// The newest available version
+	pub candidate: DebVersion,
+	/// Set if any newer version comes from a `-security` pocket
+	pub security: bool,
+	/// The list file the newest version was found in
+	pub list_file: PathBuf,
+}
+
+/// Check if a list file name, like `security.ubuntu.com_ubuntu_dists_jammy-security_main_binary-amd64_Packages`, is from a security pocket
+fn is_security_list(file_name: &str) -> bool {
+	let suite = file_name.split("_dists_").nth(1)
+		.and_then(|rest| rest.split('_').next())
+		.unwrap_or("");
+
+	suite.ends_with("-security") || (file_name.contains("debian-security") && file_name.contains("_updates_"))
+}
+
+/// Parse every package version in an apt Packages list
+///
+/// `security` is recorded on each candidate as is, since it depends on the file's name rather than its contents.
+pub fn parse_packages_file<T: ToString>(path: &T, security: bool) -> Result<Vec<AptCandidate>, i32> {
+	let path = PathBuf::from(path.to_string());
+	let contents = std::fs::read_to_string(&path).map_err(io_errno)?;
+
+	Ok(split_stanzas(&contents).iter()
+		.map(|s| parse_stanza(s))
+		.filter_map(|fields| {
+			Some(AptCandidate {
+				name: fields.get("Package")?.clone(),
+				version: DebVersion::parse(fields.get("Version")?).ok()?,
+				architecture: fields.get("Architecture").cloned().unwrap_or_default(),
+				list_file: path.clone(),
+				security,
+			})
+		})
+		.collect())
+}
+
+/// List every package version in apt's downloaded package lists
+pub fn list_apt_candidates() -> Result<Vec<AptCandidate>, i32> {
+	list_apt_candidates_in(&"/")
+}
+
+/// List every package version in /var/lib/apt/lists under the root directory `root`
+///
+/// Only uncompressed `*_Packages` files are read, which is how apt stores them by default.
+pub fn list_apt_candidates_in<T: ToString>(root: &T) -> Result<Vec<AptCandidate>, i32> {
+	let dir = std::fs::read_dir(rooted_path(root, "/var/lib/apt/lists")).map_err(io_errno)?;
+	let mut files: Vec<PathBuf> = dir.filter_map(|e| e.ok())
+		.map(|e| e.path())
+		.filter(|p| p.file_name().is_some_and(|n| n.to_string_lossy().ends_with("_Packages")))
+		.collect();
+	files.sort();
+
+	let mut candidates = Vec::new();
+	for file in files {
+		let security = is_security_list(&file.file_name().unwrap_or_default().to_string_lossy());
+		candidates.extend(parse_packages_file(&file.display(), security)?);
+	}
+
+	Ok(candidates)
+}
+
+/// List the installed packages that have a newer version in apt's package lists
+pub fn upgradable_packages() -> Result<Vec<UpgradablePackage>, i32> {
+	upgradable_packages_in(&"/")
+}
+
+/// List the upgradable packages under the root directory `root`, without touching the network
+///
+/// This compares the dpkg status file against the lists from the last `apt update`, so it is only as fresh as those.
+/// Pinning and phased updates are not taken into account.
+pub fn upgradable_packages_in<T: ToString>(root: &T) -> Result<Vec<UpgradablePackage>, i32> {
+	let mut available: HashMap<(String, String), Vec<AptCandidate>> = HashMap::new();
+	for candidate in list_apt_candidates_in(root)? {
+		available.entry((candidate.name.clone(), candidate.architecture.clone())).or_default().push(candidate);
+	}
+
+	let mut upgradable = Vec::new();
+	for package in list_dpkg_packages_in(root)?.into_iter().filter(|p| p.is_installed()) {
+		let installed = match DebVersion::parse(&package.version) {
+			Ok(v) => v,
+			Err(_) => continue,
+		};
+
+		let newer: Vec<&AptCandidate> = match available.get(&(package.name.clone(), package.architecture.clone())) {
+			Some(c) => c.iter().filter(|c| c.version > installed).collect(),
+			None => continue,
+		};
+
+		if let Some(best) = newer.iter().max_by(|a, b| a.version.cmp(&b.version)) {
+			upgradable.push(UpgradablePackage {
+				name: package.name,
+				architecture: package.architecture,
+				installed,
+				candidate: best.version.clone(),
+				security: newer.iter().any(|c| c.security),
+				list_file: best.list_file.clone(),
+			});
+		}
+	}
+
+	upgradable.sort_by(|a, b| a.name.cmp(&b.name));
+	Ok(upgradable)
+}
+
+/// List the installed packages with a pending security update
+pub fn security_updates() -> Result<Vec<UpgradablePackage>, i32> {
+	security_updates_in(&"/")
+}
+
+/// List the installed packages with a pending security update under the root directory `root`
+pub fn security_updates_in<T: ToString>(root: &T) -> Result<Vec<UpgradablePackage>, i32> {
+	Ok(upgradable_packages_in(root)?.into_iter().filter(|p| p.security).collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+
+	const LISTS: &str = "/var/lib/apt/lists";
+
+	#[test]
+	fn upgrades_from_package_lists() {
+		let root = test_root("apt-upgrades");
+		write_rooted(&root, "/var/lib/dpkg/status", "\
+Package: bash
+Status: install ok installed
+Architecture: amd64
+Version: 5.1-6ubuntu1
+
+Package: openssl
+Status: install ok installed
+Architecture: amd64
+Version: 3.0.2-0ubuntu1.10
+
+Package: vim
+Status: install ok installed
+Architecture: amd64
+Version: 2:8.2.3995-1ubuntu2.15
+
+Package: telnet
+Status: deinstall ok config-files
+Architecture: amd64
+Version: 0.17-44build1
+");
+		write_rooted(&root, &format!("{}/archive.ubuntu.com_ubuntu_dists_jammy-updates_main_binary-amd64_Packages", LISTS), "\
+Package: bash
+Architecture: amd64
+Version: 5.1-6ubuntu1.1
+
+Package: openssl
+Architecture: amd64
+Version: 3.0.2-0ubuntu1.15
+
+Package: vim
+Architecture: amd64
+Version: 2:8.2.3995-1ubuntu2.15
+
+Package: telnet
+Architecture: amd64
+Version: 0.17-44build2
+");
+		write_rooted(&root, &format!("{}/security.ubuntu.com_ubuntu_dists_jammy-security_main_binary-amd64_Packages", LISTS), "\
+Package: openssl
+Architecture: amd64
+Version: 3.0.2-0ubuntu1.12
+
+Package: bash
+Architecture: i386
+Version: 5.1-6ubuntu1.2
+");
+		// Compressed lists aren't read
+		write_rooted(&root, &format!("{}/archive.ubuntu.com_ubuntu_dists_jammy_main_binary-amd64_Packages.lz4", LISTS), "garbage");
+		let root_s = root.display();
+
+		let upgradable = upgradable_packages_in(&root_s).unwrap();
+		let summary: Vec<(&str, String, bool)> = upgradable.iter()
+			.map(|p| (p.name.as_str(), p.candidate.to_string(), p.security))
+			.collect();
+		assert_eq!(summary, [
+			("bash", "5.1-6ubuntu1.1".to_string(), false),
+			// The newest version is from -updates, but a newer one than installed is also in -security
+			("openssl", "3.0.2-0ubuntu1.15".to_string(), true),
+		]);
+		assert!(upgradable[1].list_file.to_string_lossy().contains("jammy-updates"));
+
+		let security: Vec<String> = security_updates_in(&root_s).unwrap().into_iter().map(|p| p.name).collect();
+		assert_eq!(security, ["openssl"]);
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn security_pockets() {
+		assert!(is_security_list("security.ubuntu.com_ubuntu_dists_jammy-security_main_binary-amd64_Packages"));
+		assert!(is_security_list("deb.debian.org_debian-security_dists_bookworm-security_main_binary-amd64_Packages"));
+		assert!(!is_security_list("archive.ubuntu.com_ubuntu_dists_jammy-updates_main_binary-amd64_Packages"));
+	}
+}
@@ -0,0 +1,654 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+use std::{
+	collections::{BTreeSet, HashMap},
+	path::{Path, PathBuf},
+	string::String,
+	vec::Vec,
+};
+
+use super::{io_errno, rooted_path};
+use super::dpkg::{parse_stanza, split_stanzas};
+
+/// Domains whose repositories belong to the distribution itself
+const OFFICIAL_DOMAINS: [&str; 3] = ["ubuntu.com", "debian.org", "canonical.com"];
+
+/// Suite suffixes that name a pocket of a release
+const POCKETS: [&str; 4] = ["security", "updates", "backports", "proposed"];
+
+/// A single repository from the APT sources, one per type, URI and suite
+#[derive(Clone, Debug)]
+pub struct AptSource {
+	pub file: PathBuf,
+	pub line: usize,
+	/// `deb` or `deb-src`
+	pub kind: String,
+	pub uri: String,
+	pub suite: String,
+	pub components: Vec<String>,
+	/// Options like `signed-by` or `trusted`, with one-line style lowercase names
+	pub options: HashMap<String, String>,
+	/// Cleared for commented out one-line entries and deb822 stanzas with `Enabled: no`
+	pub enabled: bool,
+}
+
+impl AptSource {
+	/// Parse a one-line style entry, like `deb [signed-by=/usr/share/keyrings/x.gpg] http://example.com jammy main`
+	///
+	/// Returns `Err(-1)` if the line isn't an entry.
+	pub fn parse_entry<T: ToString>(entry: &T) -> Result<AptSource, i32> {
+		let entry = entry.to_string();
+		let entry = entry.split('#').next().unwrap_or("").trim();
+
+		let (kind, rest) = entry.split_once(char::is_whitespace).ok_or(-1)?;
+		if kind != "deb" && kind != "deb-src" {
+			return Err(-1);
+		}
+
+		let mut options = HashMap::new();
+		let mut rest = rest.trim_start();
+		if let Some(opts) = rest.strip_prefix('[') {
+			let (opts, after) = opts.split_once(']').ok_or(-1)?;
+			for opt in opts.split_whitespace() {
+				let (k, v) = opt.split_once('=').ok_or(-1)?;
+				options.insert(k.trim_end_matches(['+', '-']).to_lowercase(), v.to_string());
+			}
+			rest = after;
+		}
+
+		let mut tokens = rest.split_whitespace();
+		Ok(AptSource {
+			file: PathBuf::new(),
+			line: 0,
+			kind: kind.to_string(),
+			uri: tokens.next().ok_or(-1)?.to_string(),
+			suite: tokens.next().ok_or(-1)?.to_string(),
+			components: tokens.map(|t| t.to_string()).collect(),
+			options,
+			enabled: true,
+		})
+	}
+
+	/// Parse a one-line style .list file, including entries that are commented out as disabled ones
+	pub fn parse_list_file<T: ToString>(path: &T) -> Result<Vec<AptSource>, i32> {
+		let path = PathBuf::from(path.to_string());
+		let contents = std::fs::read_to_string(&path).map_err(io_errno)?;
+		let mut sources = Vec::new();
+
+		for (idx, line) in contents.lines().enumerate() {
+			let line = line.trim();
+			let (enabled, entry) = match line.strip_prefix('#') {
+				Some(commented) => (false, commented.trim_start()),
+				None => (true, line),
+			};
+
+			if let Ok(mut source) = AptSource::parse_entry(&entry) {
+				source.file = path.clone();
+				source.line = idx + 1;
+				source.enabled = enabled;
+				sources.push(source);
+			}
+		}
+
+		Ok(sources)
+	}
+
+	/// Parse a deb822 style .sources file, expanding each stanza into one source per type, URI and suite
+	pub fn parse_sources_file<T: ToString>(path: &T) -> Result<Vec<AptSource>, i32> {
+		let path = PathBuf::from(path.to_string());
+		let contents = std::fs::read_to_string(&path).map_err(io_errno)?;
+		let mut sources = Vec::new();
+
+		for stanza in split_stanzas(&contents) {
+			let fields: HashMap<String, String> = parse_stanza(&stanza).into_iter()
+				.map(|(k, v)| (k.to_lowercase(), v))
+				.collect();
+
+			let (types, uris, suites) = match (fields.get("types"), fields.get("uris"), fields.get("suites")) {
+				(Some(t), Some(u), Some(s)) => (t, u, s),
+				_ => continue,
+			};
+
+			let enabled = fields.get("enabled").is_none_or(|v| !v.eq_ignore_ascii_case("no"));
+			let components: Vec<String> = fields.get("components")
+				.map(|c| c.split_whitespace().map(|c| c.to_string()).collect())
+				.unwrap_or_default();
+			let options: HashMap<String, String> = fields.iter()
+				.filter(|(k, _)| !["types", "uris", "suites", "components", "enabled"].contains(&k.as_str()))
+				.map(|(k, v)| (deb822_option_name(k), v.clone()))
+				.collect();
+
+			for kind in types.split_whitespace() {
+				for uri in uris.split_whitespace() {
+					for suite in suites.split_whitespace() {
+						sources.push(AptSource {
+							file: path.clone(),
+							line: 0,
+							kind: kind.to_string(),
+							uri: uri.to_string(),
+							suite: suite.to_string(),
+							components: components.clone(),
+							options: options.clone(),
+							enabled,
+						});
+					}
+				}
+			}
+		}
+
+		Ok(sources)
+	}
+
+	/// The pocket of the suite, like `security` for `jammy-security`, or [`None`] for the release itself
+	pub fn pocket(&self) -> Option<&str> {
+		self.suite.rsplit_once('-')
+			.map(|(_, p)| p)
+			.filter(|p| POCKETS.contains(p))
+	}
+
+	/// Get the host of the URI, if it has one
+	pub fn host(&self) -> Option<&str> {
+		let (_, rest) = self.uri.split_once("://")?;
+		let host = rest.split('/').next()?;
+		let host = host.rsplit('@').next()?;
+		Some(host.split(':').next().unwrap_or(host))
+	}
+
+	/// Check if the repository is trusted without a valid signature, by `trusted=yes`, `allow-insecure=yes` or `allow-weak=yes`
+	pub fn is_unsigned(&self) -> bool {
+		["trusted", "allow-insecure", "allow-weak"].iter()
+			.any(|o| self.options.get(*o).is_some_and(|v| v.eq_ignore_ascii_case("yes")))
+	}
+
+	/// Check if the repository comes from somewhere other than Ubuntu, Debian or a CD
+	pub fn is_third_party(&self) -> bool {
+		if self.uri.starts_with("cdrom:") {
+			return false;
+		}
+
+		match self.host() {
+			Some(host) => !OFFICIAL_DOMAINS.iter().any(|d| host == *d || host.ends_with(&format!(".{}", d))),
+			None => true,
+		}
+	}
+}
+
+/// Map a deb822 field name to the name of the matching one-line option
+fn deb822_option_name(field: &str) -> String {
+	match field {
+		"architectures" => "arch".to_string(),
+		"languages" => "lang".to_string(),
+		"targets" => "target".to_string(),
+		other => other.to_string(),
+	}
+}
+
+/// List the files in `dir` whose names end with `suffix`, sorted by name
+fn files_with_suffix(dir: &Path, suffix: &str) -> Vec<PathBuf> {
+	let mut files: Vec<PathBuf> = match std::fs::read_dir(dir) {
+		Ok(rd) => rd.filter_map(|e| e.ok())
+			.map(|e| e.path())
+			.filter(|p| p.file_name().is_some_and(|n| n.to_string_lossy().ends_with(suffix)))
+			.collect(),
+		Err(_) => Vec::new(),
+	};
+	files.sort();
+	files
+}
+
+/// List every source in /etc/apt/sources.list and /etc/apt/sources.list.d
+pub fn list_apt_sources() -> Result<Vec<AptSource>, i32> {
+	list_apt_sources_in(&"/")
+}
+
+/// List every source under the root directory `root`, including disabled ones
+///
+/// Missing files and directories are skipped, since a system may use only one of the two formats.
+pub fn list_apt_sources_in<T: ToString>(root: &T) -> Result<Vec<AptSource>, i32> {
+	let mut sources = match AptSource::parse_list_file(&rooted_path(root, "/etc/apt/sources.list").display()) {
+		Ok(s) => s,
+		Err(libc::ENOENT) => Vec::new(),
+		Err(e) => return Err(e),
+	};
+
+	let dir = rooted_path(root, "/etc/apt/sources.list.d");
+	for file in files_with_suffix(&dir, ".list") {
+		sources.extend(AptSource::parse_list_file(&file.display())?);
+	}
+	for file in files_with_suffix(&dir, ".sources") {
+		sources.extend(AptSource::parse_sources_file(&file.display())?);
+	}
+
+	Ok(sources)
+}
+
+/// List the suites of the enabled `deb` sources, like `jammy`, `jammy-updates` and `jammy-security`
+pub fn enabled_apt_suites() -> Result<BTreeSet<String>, i32> {
+	enabled_apt_suites_in(&"/")
+}
+
+/// List the suites of the enabled `deb` sources under the root directory `root`
+pub fn enabled_apt_suites_in<T: ToString>(root: &T) -> Result<BTreeSet<String>, i32> {
+	Ok(list_apt_sources_in(root)?.into_iter()
+		.filter(|s| s.enabled && s.kind == "deb")
+		.map(|s| s.suite)
+		.collect())
+}
+
+/// List the enabled sources that skip signature checks
+pub fn unsigned_apt_sources() -> Result<Vec<AptSource>, i32> {
+	unsigned_apt_sources_in(&"/")
+}
+
+/// List the enabled sources that skip signature checks under the root directory `root`
+pub fn unsigned_apt_sources_in<T: ToString>(root: &T) -> Result<Vec<AptSource>, i32> {
+	Ok(list_apt_sources_in(root)?.into_iter().filter(|s| s.enabled && s.is_unsigned()).collect())
+}
+
+/// List the enabled sources from third party origins
+pub fn third_party_apt_sources() -> Result<Vec<AptSource>, i32> {
+	third_party_apt_sources_in(&"/")
+}
+
+/// List the enabled sources from third party origins under the root directory `root`
+pub fn third_party_apt_sources_in<T: ToString>(root: &T) -> Result<Vec<AptSource>, i32> {
+	Ok(list_apt_sources_in(root)?.into_iter().filter(|s| s.enabled && s.is_third_party()).collect())
+}
+
+/// Split apt.conf syntax into tokens, dropping comments
+///
+/// `#clear` is kept as a token of its own, followed by the name it clears. Any other line starting with `#` is dropped,
+/// including `#include`, which isn't followed.
+fn tokenize_apt_conf(text: &str) -> Vec<String> {
+	let mut tokens = Vec::new();
+	let mut chars = text.chars().peekable();
+	let mut line_start = true;
+
+	while let Some(c) = chars.next() {
+		match c {
+			'\n' => line_start = true,
+			c if c.is_whitespace() => (),
+			'#' if line_start => {
+				let directive: String = chars.clone().take_while(|n| !n.is_whitespace()).collect();
+				if directive == "clear" {
+					chars.nth(directive.len() - 1);
+					tokens.push("#clear".to_string());
+					line_start = false;
+					continue;
+				}
+
+				for n in chars.by_ref() {
+					if n == '\n' {
+						break;
+					}
+				}
+			},
+			'/' if chars.peek() == Some(&'/') => {
+				for n in chars.by_ref() {
+					if n == '\n' {
+						break;
+					}
+				}
+				line_start = true;
+			},
+			'/' if chars.peek() == Some(&'*') => {
+				chars.next();
+				let mut prev = ' ';
+				for n in chars.by_ref() {
+					if prev == '*' && n == '/' {
+						break;
+					}
+					prev = n;
+				}
+			},
+			'"' => {
+				let mut s = String::from('"');
+				for n in chars.by_ref() {
+					if n == '"' {
+						break;
+					}
+					s.push(n);
+				}
+				tokens.push(s);
+				line_start = false;
+			},
+			'{' | '}' | ';' => {
+				tokens.push(c.to_string());
+				line_start = false;
+			},
+			c => {
+				let mut s = String::from(c);
+				while let Some(&n) = chars.peek() {
+					if n.is_whitespace() || n == '{' || n == '}' || n == ';' || n == '"' {
+						break;
+					}
+					s.push(n);
+					chars.next();
+				}
+				tokens.push(s);
+				line_start = false;
+			},
+		}
+	}
+
+	tokens
+}
+
+/// The APT configuration from /etc/apt/apt.conf.d and /etc/apt/apt.conf
+///
+/// Keys are full `::` separated names, stored lowercase since APT compares them without case.
+/// Every value a key was given is kept in order, so lists like `Unattended-Upgrade::Allowed-Origins` work too.
+#[derive(Clone, Default, Debug)]
+pub struct AptConf {
+	pub values: HashMap<String, Vec<String>>,
+}
+
+impl AptConf {
+	/// Parse apt.conf syntax, with both `A::B "v";` and nested `A { B "v"; };` forms
+	pub fn parse<T: ToString>(text: &T) -> AptConf {
+		let mut conf = AptConf::default();
+		conf.merge(&text.to_string());
+		conf
+	}
+
+	/// Parse the apt.conf formatted file at `path`
+	pub fn parse_file<T: ToString>(path: &T) -> Result<AptConf, i32> {
+		Ok(AptConf::parse(&std::fs::read_to_string(path.to_string()).map_err(io_errno)?))
+	}
+
+	/// Load the APT configuration
+	pub fn load() -> Result<AptConf, i32> {
+		AptConf::load_in(&"/")
+	}
+
+	/// Load the APT configuration under the root directory `root`
+	///
+	/// Files in apt.conf.d are read in order, skipping names APT itself ignores, like `*.dpkg-old`, then apt.conf.
+	/// Like APT, anything in apt.conf.d that isn't a regular file is skipped.
+	pub fn load_in<T: ToString>(root: &T) -> Result<AptConf, i32> {
+		let mut conf = AptConf::default();
+
+		for file in files_with_suffix(&rooted_path(root, "/etc/apt/apt.conf.d"), "").into_iter().filter(|f| f.is_file()) {
+			let name = file.file_name().unwrap_or_default().to_string_lossy().to_string();
+			let valid_chars = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+			if !valid_chars || (name.contains('.') && !name.ends_with(".conf")) {
+				continue;
+			}
+
+			conf.merge(&std::fs::read_to_string(&file).map_err(io_errno)?);
+		}
+
+		match std::fs::read_to_string(rooted_path(root, "/etc/apt/apt.conf")) {
+			Ok(text) => conf.merge(&text),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+			Err(e) => return Err(io_errno(e)),
+		}
+
+		Ok(conf)
+	}
+
+	fn merge(&mut self, text: &str) {
+		let tokens = tokenize_apt_conf(text);
+		let mut scope: Vec<String> = Vec::new();
+		let mut i = 0;
+
+		while i < tokens.len() {
+			let tok = &tokens[i];
+
+			if tok == "}" {
+				scope.pop();
+				i += 1;
+				continue;
+			}
+
+			if tok == ";" {
+				i += 1;
+				continue;
+			}
+
+			if tok == "#clear" {
+				if let Some(name) = tokens.get(i + 1).filter(|t| *t != ";") {
+					let key = scope.iter().cloned().chain(std::iter::once(name.to_lowercase())).collect::<Vec<_>>().join("::");
+					let children = format!("{}::", key);
+					self.values.retain(|k, _| *k != key && !k.starts_with(&children));
+				}
+				i += 2;
+				continue;
+			}
+
+			if let Some(value) = tok.strip_prefix('"') {
+				self.values.entry(scope.join("::")).or_default().push(value.to_string());
+				i += 1;
+				continue;
+			}
+
+			let key = scope.iter().cloned().chain(std::iter::once(tok.to_lowercase())).collect::<Vec<_>>().join("::");
+			match tokens.get(i + 1).map(|t| t.as_str()) {
+				Some("{") => {
+					scope.push(tok.to_lowercase());
+					i += 2;
+				},
+				Some(v) if v != ";" && v != "}" => {
+					self.values.entry(key).or_default().push(v.trim_start_matches('"').to_string());
+					i += 2;
+				},
+				_ => {
+					self.values.entry(key).or_default();
+					i += 1;
+				},
+			}
+		}
+	}
+
+	/// Get the last value given to `key`
+	pub fn get<T: ToString>(&self, key: &T) -> Option<String> {
+		self.values.get(&key.to_string().to_lowercase()).and_then(|v| v.last().cloned())
+	}
+
+	/// Get every value given to `key`, for list options
+	pub fn get_list<T: ToString>(&self, key: &T) -> Vec<String> {
+		self.values.get(&key.to_string().to_lowercase()).cloned().unwrap_or_default()
+	}
+
+	/// Get a boolean option the way APT reads them, accepting `true`, `yes`, `on`, `1` and their opposites
+	pub fn get_bool<T: ToString>(&self, key: &T) -> Option<bool> {
+		match self.get(key)?.to_lowercase().as_str() {
+			"true" | "yes" | "on" | "with" | "enable" | "1" => Some(true),
+			"false" | "no" | "off" | "without" | "disable" | "0" => Some(false),
+			_ => None,
+		}
+	}
+
+	/// Get an `APT::Periodic` interval in days
+	///
+	/// `always`, which runs the job every time the daily timer fires, is reported as 1.
+	fn periodic_days(&self, name: &str) -> Option<i64> {
+		let value = self.get(&format!("APT::Periodic::{}", name))?;
+		if value.eq_ignore_ascii_case("always") {
+			return Some(1);
+		}
+		value.trim_end_matches('d').parse().ok()
+	}
+
+	/// How often package lists are updated, in days, from `APT::Periodic::Update-Package-Lists`
+	pub fn update_package_lists_interval(&self) -> Option<i64> {
+		self.periodic_days("Update-Package-Lists")
+	}
+
+	/// How often upgradable packages are downloaded, in days, from `APT::Periodic::Download-Upgradeable-Packages`
+	pub fn download_upgradeable_interval(&self) -> Option<i64> {
+		self.periodic_days("Download-Upgradeable-Packages")
+	}
+
+	/// How often unattended upgrades run, in days, from `APT::Periodic::Unattended-Upgrade`
+	pub fn unattended_upgrade_interval(&self) -> Option<i64> {
+		self.periodic_days("Unattended-Upgrade")
+	}
+
+	/// Check if the package lists are refreshed and upgrades installed automatically
+	///
+	/// An interval of `0` disables the job, so both must be set to something else.
+	pub fn auto_updates_enabled(&self) -> bool {
+		let enabled = self.get_bool(&"APT::Periodic::Enable").unwrap_or(true);
+		let on = |v: Option<i64>| v.is_some_and(|d| d > 0);
+
+		enabled && on(self.update_package_lists_interval()) && on(self.unattended_upgrade_interval())
+	}
+
+	/// Check if APT is configured to accept unsigned repositories or packages system wide
+	pub fn allows_insecure(&self) -> bool {
+		["Acquire::AllowInsecureRepositories", "Acquire::AllowDowngradeToInsecureRepositories", "APT::Get::AllowUnauthenticated"].iter()
+			.any(|k| self.get_bool(k) == Some(true))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+
+	#[test]
+	fn one_line_entries() {
+		let source = AptSource::parse_entry(&"deb [arch+=amd64 trusted=yes Signed-By=/usr/share/keyrings/x.gpg] http://ppa.launchpad.net/x/ppa/ubuntu jammy main universe # the PPA").unwrap();
+		assert_eq!((source.kind.as_str(), source.uri.as_str(), source.suite.as_str()), ("deb", "http://ppa.launchpad.net/x/ppa/ubuntu", "jammy"));
+		assert_eq!(source.components, ["main", "universe"]);
+		assert_eq!(source.options["arch"], "amd64");
+		assert_eq!(source.options["signed-by"], "/usr/share/keyrings/x.gpg");
+		assert!(source.is_unsigned() && source.is_third_party());
+		assert_eq!((source.host(), source.pocket()), (Some("ppa.launchpad.net"), None));
+
+		let source = AptSource::parse_entry(&"deb-src http://user@security.ubuntu.com:80/ubuntu jammy-security main").unwrap();
+		assert_eq!((source.host(), source.pocket()), (Some("security.ubuntu.com"), Some("security")));
+		assert!(!source.is_unsigned() && !source.is_third_party());
+		assert!(!AptSource::parse_entry(&"deb cdrom:[Ubuntu 22.04]/ jammy main").unwrap().is_third_party());
+
+		for bad in ["", "# deb http://a b c", "rpm http://a b", "deb [trusted] http://a b", "deb [trusted=yes http://a b", "deb http://a"] {
+			assert!(AptSource::parse_entry(&bad).is_err(), "{:?}", bad);
+		}
+	}
+
+	#[test]
+	fn sources_from_both_formats() {
+		let root = test_root("aptconf-sources");
+		write_rooted(&root, "/etc/apt/sources.list", "\
+# See http://help.ubuntu.com/community/UpgradeNotes for how to upgrade to
+deb http://archive.ubuntu.com/ubuntu/ jammy main restricted
+# deb http://archive.ubuntu.com/ubuntu/ jammy-updates main restricted
+#deb [trusted=yes] http://evil.example.com/ubuntu jammy main
+");
+		write_rooted(&root, "/etc/apt/sources.list.d/ubuntu.sources", "\
+# Ubuntu sources have moved to this file
+Types: deb deb-src
+URIs: http://archive.ubuntu.com/ubuntu/
+Suites: noble noble-updates
+Components: main restricted
+Signed-By: /usr/share/keyrings/ubuntu-archive-keyring.gpg
+Architectures: amd64
+
+Types: deb
+URIs: http://security.ubuntu.com/ubuntu/
+Suites: noble-security
+Components: main
+Enabled: no
+
+Types: deb
+URIs: https://evil.example.com/repo
+Suites: ./
+Trusted: yes
+");
+		write_rooted(&root, "/etc/apt/sources.list.d/ignored.list.save", "deb http://a b c\n");
+
+		let sources = list_apt_sources_in(&root.display()).unwrap();
+		assert_eq!(sources.len(), 9);
+		assert_eq!((sources[1].line, sources[1].enabled, sources[1].suite.as_str()), (3, false, "jammy-updates"));
+		assert!(!sources[2].enabled && sources[2].is_unsigned());
+
+		let deb822: Vec<(&str, &str, bool)> = sources[3..].iter().map(|s| (s.kind.as_str(), s.suite.as_str(), s.enabled)).collect();
+		assert_eq!(deb822, [
+			("deb", "noble", true), ("deb", "noble-updates", true),
+			("deb-src", "noble", true), ("deb-src", "noble-updates", true),
+			("deb", "noble-security", false),
+			("deb", "./", true),
+		]);
+		assert_eq!(sources[3].options["arch"], "amd64");
+		assert_eq!(sources[3].options["signed-by"], "/usr/share/keyrings/ubuntu-archive-keyring.gpg");
+		assert_eq!(sources[3].components, ["main", "restricted"]);
+
+		let suites: Vec<String> = enabled_apt_suites_in(&root.display()).unwrap().into_iter().collect();
+		assert_eq!(suites, ["./", "jammy", "noble", "noble-updates"]);
+		let unsigned: Vec<String> = unsigned_apt_sources_in(&root.display()).unwrap().into_iter().map(|s| s.uri).collect();
+		assert_eq!(unsigned, ["https://evil.example.com/repo"]);
+		assert_eq!(third_party_apt_sources_in(&root.display()).unwrap().len(), 1);
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn apt_conf_syntax() {
+		let text = "\
+// Automatically upgrade packages from these origin patterns
+Unattended-Upgrade::Allowed-Origins {
+	\"${distro_id}:${distro_codename}\";
+	\"${distro_id}:${distro_codename}-security\"; // security
+/*	\"${distro_id}:${distro_codename}-updates\";
+	\"${distro_id}:${distro_codename}-proposed\"; */
+};
+APT {
+	Periodic {
+		Update-Package-Lists \"1\";
+		Unattended-Upgrade \"always\";
+	};
+	Get::AllowUnauthenticated \"false\";
+};
+# a comment
+Acquire::http::Proxy \"http://proxy:3128/\";
+Acquire::http::Timeout \"10\";
+#clear Acquire::http;
+Dpkg::Options { \"--force-confdef\"; \"--force-confold\"; };
+";
+		let tokens = tokenize_apt_conf("A { B \"x y\"; }; // c\n#clear A::B;\n/* d */ C;");
+		assert_eq!(tokens, ["A", "{", "B", "\"x y", ";", "}", ";", "#clear", "A::B", ";", "C", ";"]);
+
+		let conf = AptConf::parse(&text);
+		assert_eq!(conf.get_list(&"unattended-upgrade::allowed-origins"), ["${distro_id}:${distro_codename}", "${distro_id}:${distro_codename}-security"]);
+		assert_eq!(conf.get(&"APT::Periodic::Update-Package-Lists").as_deref(), Some("1"));
+		assert_eq!(conf.unattended_upgrade_interval(), Some(1));
+		assert_eq!(conf.get_bool(&"apt::get::allowunauthenticated"), Some(false));
+		assert_eq!(conf.get_list(&"Dpkg::Options"), ["--force-confdef", "--force-confold"]);
+		assert_eq!(conf.get(&"Acquire::http::Proxy"), None);
+		assert_eq!(conf.get(&"Acquire::http::Timeout"), None);
+		assert!(conf.auto_updates_enabled());
+		assert!(!conf.allows_insecure());
+
+		// Later files override earlier ones, and apt.conf comes last
+		let mut conf = conf;
+		conf.merge("APT::Periodic::Unattended-Upgrade \"0\";");
+		assert!(!conf.auto_updates_enabled());
+		conf.merge("APT::Periodic::Unattended-Upgrade \"7d\"; APT::Periodic::Enable \"0\";");
+		assert_eq!(conf.unattended_upgrade_interval(), Some(7));
+		assert!(!conf.auto_updates_enabled());
+		assert!(!AptConf::parse(&"APT::Periodic::Unattended-Upgrade \"1\";").auto_updates_enabled());
+	}
+
+	#[test]
+	fn load_conf_dir() {
+		let root = test_root("aptconf-load");
+		write_rooted(&root, "/etc/apt/apt.conf.d/20auto-upgrades", "APT::Periodic::Update-Package-Lists \"1\";\nAPT::Periodic::Unattended-Upgrade \"1\";\n");
+		write_rooted(&root, "/etc/apt/apt.conf.d/99insecure.conf", "Acquire::AllowInsecureRepositories \"true\";\n");
+		write_rooted(&root, "/etc/apt/apt.conf.d/50off.dpkg-old", "APT::Periodic::Enable \"0\";\n");
+		write_rooted(&root, "/etc/apt/apt.conf.d/subdir/10off", "APT::Periodic::Enable \"0\";\n");
+		write_rooted(&root, "/etc/apt/apt.conf", "APT::Periodic::Update-Package-Lists \"2\";\n");
+
+		let conf = AptConf::load_in(&root.display()).unwrap();
+		assert!(conf.auto_updates_enabled());
+		assert!(conf.allows_insecure());
+		assert_eq!(conf.update_package_lists_interval(), Some(2));
+		assert_eq!(conf.get(&"APT::Periodic::Enable"), None);
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+}
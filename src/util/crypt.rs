@@ -0,0 +1,869 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+use std::{
+	string::String,
+	vec::Vec,
+};
+
+use md5::Md5;
+use sha2::{Digest, Sha256, Sha512};
+
+use super::{HashAlgorithm, ShadowEntry};
+
+/// Passwords people set on practice images far too often
+///
+/// [`find_common_weak_password`] tests these, along with the username itself.
+pub const COMMON_WEAK_PASSWORDS: [&str; 32] = [
+	"password", "Password", "password1", "Password1", "Password1!", "P@ssw0rd", "P@$$w0rd", "passw0rd",
+	"123456", "1234567", "12345678", "123456789", "1234567890", "12345", "1234", "111111",
+	"qwerty", "qwerty123", "abc123", "letmein", "welcome", "iloveyou", "monkey", "dragon",
+	"sunshine", "football", "trustno1", "changeme", "admin", "root", "toor", "secret",
+];
+
+const ITOA64: &[u8; 64] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+fn atoi64(c: u8) -> Option<u32> {
+	ITOA64.iter().position(|x| *x == c).map(|p| p as u32)
+}
+
+/// Append `n` characters encoding the three bytes given, least significant six bits first
+fn b64_from_24bit(out: &mut String, b2: u8, b1: u8, b0: u8, n: usize) {
+	let mut w = ((b2 as u32) << 16) | ((b1 as u32) << 8) | b0 as u32;
+	for _ in 0..n {
+		out.push(ITOA64[(w & 0x3f) as usize] as char);
+		w >>= 6;
+	}
+}
+
+/// Hash `password` with the salt, algorithm and parameters in `setting`, producing a full crypt(3) style string
+///
+/// `setting` may be a complete hash, in which case the hash part is ignored.
+/// Supports MD5 crypt (`$1$`), SHA-256 crypt (`$5$`), SHA-512 crypt (`$6$`) and yescrypt (`$y$`).
+/// Returns `Err(-1)` for malformed settings or any other algorithm.
+pub fn crypt_password<A: ToString, B: ToString>(password: &A, setting: &B) -> Result<String, i32> {
+	let password = password.to_string();
+	let setting = setting.to_string();
+
+	match HashAlgorithm::from_hash(&setting) {
+		Some(HashAlgorithm::Md5) => md5_crypt(password.as_bytes(), &setting),
+		Some(HashAlgorithm::Sha256) => sha_crypt::<Sha256>(password.as_bytes(), &setting, "5", &SHA256_ORDER),
+		Some(HashAlgorithm::Sha512) => sha_crypt::<Sha512>(password.as_bytes(), &setting, "6", &SHA512_ORDER),
+		Some(HashAlgorithm::Yescrypt) => yescrypt::crypt(password.as_bytes(), &setting),
+		_ => Err(-1),
+	}
+}
+
+/// Check if `password` is the password hashed in `hash`
+///
+/// Leading `!` lock markers are ignored, so this answers whether the password is still the same even on locked accounts.
+/// Returns `Err(-1)` if the hash uses an unsupported algorithm, see [`crypt_password`].
+pub fn verify_password<A: ToString, B: ToString>(password: &A, hash: &B) -> Result<bool, i32> {
+	let hash = hash.to_string();
+	let hash = hash.trim_start_matches('!');
+
+	Ok(crypt_password(password, &hash)? == hash)
+}
+
+/// Check if the user named `name` has the password `candidate`, according to /etc/shadow
+pub fn user_password_is<A: ToString, B: ToString>(name: &A, candidate: &B) -> Result<bool, i32> {
+	user_password_is_in(&"/", name, candidate)
+}
+
+/// Check if the user named `name` has the password `candidate`, according to /etc/shadow under the root directory `root`
+pub fn user_password_is_in<A: ToString, B: ToString, C: ToString>(root: &A, name: &B, candidate: &C) -> Result<bool, i32> {
+	ShadowEntry::get_entry_in(root, name)?.password_matches(candidate)
+}
+
+/// Find which, if any, of `candidates` is the password of the user named `name`
+pub fn find_weak_password<T: ToString>(name: &T, candidates: &[&str]) -> Result<Option<String>, i32> {
+	find_weak_password_in(&"/", name, candidates)
+}
+
+/// Find which, if any, of `candidates` is the password of the user named `name`, using /etc/shadow under the root directory `root`
+///
+/// An empty password is always reported, as `Some("")`.
+pub fn find_weak_password_in<A: ToString, B: ToString>(root: &A, name: &B, candidates: &[&str]) -> Result<Option<String>, i32> {
+	let entry = ShadowEntry::get_entry_in(root, name)?;
+
+	if entry.password_is_empty() {
+		return Ok(Some(String::new()));
+	}
+
+	for candidate in candidates {
+		if entry.password_matches(candidate)? {
+			return Ok(Some(candidate.to_string()));
+		}
+	}
+
+	Ok(None)
+}
+
+/// Find if the user named `name` is using their own username or one of [`COMMON_WEAK_PASSWORDS`] as their password
+pub fn find_common_weak_password<T: ToString>(name: &T) -> Result<Option<String>, i32> {
+	find_common_weak_password_in(&"/", name)
+}
+
+/// Same as [`find_common_weak_password`], using /etc/shadow under the root directory `root`
+pub fn find_common_weak_password_in<A: ToString, B: ToString>(root: &A, name: &B) -> Result<Option<String>, i32> {
+	let username = name.to_string();
+	let mut candidates = vec![username.as_str()];
+	candidates.extend_from_slice(&COMMON_WEAK_PASSWORDS);
+
+	find_weak_password_in(root, name, &candidates)
+}
+
+impl ShadowEntry {
+	/// Check if `candidate` is this account's password
+	///
+	/// Accounts with no hash at all, like `*` or `!`, never match.
+	pub fn password_matches<T: ToString>(&self, candidate: &T) -> Result<bool, i32> {
+		if self.password_is_empty() {
+			return Ok(candidate.to_string().is_empty());
+		}
+
+		match self.hash_algorithm() {
+			Some(_) => verify_password(candidate, &self.password),
+			None => Ok(false),
+		}
+	}
+}
+
+/// Split the salt out of an MD5 or SHA crypt setting, returning it and the number of rounds if given
+fn split_salt<'a>(setting: &'a str, id: &str, max_salt: usize) -> Result<(&'a str, Option<usize>), i32> {
+	let rest = setting.trim_start_matches('!')
+		.strip_prefix(&format!("${}$", id))
+		.ok_or(-1)?;
+
+	let (rounds, rest) = match rest.strip_prefix("rounds=") {
+		Some(r) => {
+			let (num, rest) = r.split_once('$').ok_or(-1)?;
+			(Some(num.parse::<usize>().map_err(|_| -1)?), rest)
+		},
+		None => (None, rest),
+	};
+
+	let salt = rest.split('$').next().unwrap_or("");
+	Ok((&salt[..salt.len().min(max_salt)], rounds))
+}
+
+fn md5_crypt(pw: &[u8], setting: &str) -> Result<String, i32> {
+	let (salt, _) = split_salt(setting, "1", 8)?;
+	let salt = salt.as_bytes();
+
+	let alt = Md5::new().chain_update(pw).chain_update(salt).chain_update(pw).finalize();
+
+	let mut ctx = Md5::new().chain_update(pw).chain_update(b"$1$").chain_update(salt);
+	let mut pl = pw.len();
+	while pl > 0 {
+		ctx.update(&alt[..pl.min(16)]);
+		pl = pl.saturating_sub(16);
+	}
+
+	let mut i = pw.len();
+	while i != 0 {
+		if i & 1 != 0 {
+			ctx.update([0u8]);
+		} else {
+			ctx.update(&pw[..1]);
+		}
+		i >>= 1;
+	}
+
+	let mut fin = ctx.finalize();
+	for i in 0..1000 {
+		let mut ctx = Md5::new();
+		if i & 1 != 0 { ctx.update(pw); } else { ctx.update(fin); }
+		if i % 3 != 0 { ctx.update(salt); }
+		if i % 7 != 0 { ctx.update(pw); }
+		if i & 1 != 0 { ctx.update(fin); } else { ctx.update(pw); }
+		fin = ctx.finalize();
+	}
+
+	let mut out = format!("$1${}$", String::from_utf8_lossy(salt));
+	for (a, b, c) in [(0, 6, 12), (1, 7, 13), (2, 8, 14), (3, 9, 15), (4, 10, 5)] {
+		b64_from_24bit(&mut out, fin[a], fin[b], fin[c], 4);
+	}
+	b64_from_24bit(&mut out, 0, 0, fin[11], 2);
+
+	Ok(out)
+}
+
+/// Byte order SHA-256 crypt encodes its digest in, the last group is only two bytes wide
+const SHA256_ORDER: [(usize, usize, usize); 11] = [
+	(0, 10, 20), (21, 1, 11), (12, 22, 2), (3, 13, 23), (24, 4, 14), (15, 25, 5),
+	(6, 16, 26), (27, 7, 17), (18, 28, 8), (9, 19, 29), (usize::MAX, 31, 30),
+];
+
+/// Byte order SHA-512 crypt encodes its digest in, the last group is only one byte wide
+const SHA512_ORDER: [(usize, usize, usize); 22] = [
+	(0, 21, 42), (22, 43, 1), (44, 2, 23), (3, 24, 45), (25, 46, 4), (47, 5, 26), (6, 27, 48),
+	(28, 49, 7), (50, 8, 29), (9, 30, 51), (31, 52, 10), (53, 11, 32), (12, 33, 54), (34, 55, 13),
+	(56, 14, 35), (15, 36, 57), (37, 58, 16), (59, 17, 38), (18, 39, 60), (40, 61, 19), (62, 20, 41),
+	(usize::MAX, usize::MAX, 63),
+];
+
+/// Repeat `src` until it fills `len` bytes
+fn repeat_to(src: &[u8], len: usize) -> Vec<u8> {
+	src.iter().cycle().take(len).copied().collect()
+}
+
+fn sha_crypt<D: Digest>(pw: &[u8], setting: &str, id: &str, order: &[(usize, usize, usize)]) -> Result<String, i32> {
+	let (salt, rounds) = split_salt(setting, id, 16)?;
+	let salt = salt.as_bytes();
+	let round_count = rounds.unwrap_or(5000).clamp(1000, 999_999_999);
+
+	let b = D::new().chain_update(pw).chain_update(salt).chain_update(pw).finalize();
+	let hash_len = b.len();
+
+	let mut ctx = D::new().chain_update(pw).chain_update(salt);
+	ctx.update(repeat_to(&b, pw.len()));
+	let mut i = pw.len();
+	while i > 0 {
+		if i & 1 != 0 {
+			ctx.update(&b);
+		} else {
+			ctx.update(pw);
+		}
+		i >>= 1;
+	}
+	let a = ctx.finalize();
+
+	let mut dp = D::new();
+	for _ in 0..pw.len() {
+		dp.update(pw);
+	}
+	let p = repeat_to(&dp.finalize(), pw.len());
+
+	let mut ds = D::new();
+	for _ in 0..(16 + a[0] as usize) {
+		ds.update(salt);
+	}
+	let s = repeat_to(&ds.finalize(), salt.len());
+
+	let mut c = a;
+	for i in 0..round_count {
+		let mut ctx = D::new();
+		if i & 1 != 0 { ctx.update(&p); } else { ctx.update(&c); }
+		if i % 3 != 0 { ctx.update(&s); }
+		if i % 7 != 0 { ctx.update(&p); }
+		if i & 1 != 0 { ctx.update(&c); } else { ctx.update(&p); }
+		c = ctx.finalize();
+	}
+
+	let mut out = format!("${}$", id);
+	if rounds.is_some() {
+		out.push_str(&format!("rounds={}$", round_count));
+	}
+	out.push_str(&String::from_utf8_lossy(salt));
+	out.push('$');
+
+	let byte = |i: usize| if i < hash_len { c[i] } else { 0 };
+	for (n, (b2, b1, b0)) in order.iter().enumerate() {
+		let chars = if n + 1 < order.len() { 4 } else if hash_len == 32 { 3 } else { 2 };
+		b64_from_24bit(&mut out, byte(*b2), byte(*b1), byte(*b0), chars);
+	}
+
+	Ok(out)
+}
+
+/// A pure Rust yescrypt, following the reference implementation shipped with libxcrypt
+mod yescrypt {
+	use super::{atoi64, Digest, Sha256, ITOA64};
+
+	const YESCRYPT_WORM: u32 = 0x001;
+	const YESCRYPT_RW: u32 = 0x002;
+	const YESCRYPT_RW_FLAVOR_MASK: u32 = 0x3fc;
+	/// ROUNDS_6 | GATHER_4 | SIMPLE_2 | SBOX_12K, the only pwxform settings in use
+	const YESCRYPT_RW_DEFAULT_FLAVOR: u32 = 0x0b4;
+	const YESCRYPT_PREHASH: u32 = 0x1000_0000;
+
+	const PWX_SIMPLE: usize = 2;
+	const PWX_GATHER: usize = 4;
+	const PWX_ROUNDS: usize = 6;
+	const SWIDTH: usize = 8;
+	const PWX_WORDS: usize = PWX_GATHER * PWX_SIMPLE * 2;
+	const S_BYTES: usize = 3 * (1 << SWIDTH) * PWX_SIMPLE * 8;
+	const S_WORDS: usize = S_BYTES / 4;
+	const S_MASK: u32 = (((1 << SWIDTH) - 1) * PWX_SIMPLE * 8) as u32;
+
+	/// Refuse to allocate more than 1 GiB for V, no real hash needs anywhere near that
+	const MAX_V_BYTES: u64 = 1 << 30;
+
+	struct Params {
+		flags: u32,
+		n: u64,
+		r: u32,
+		p: u32,
+		t: u32,
+	}
+
+	fn decode64_uint32(src: &[u8], pos: &mut usize, min: u32) -> Result<u32, i32> {
+		let mut start = 0u32;
+		let mut end = 47u32;
+		let mut chars = 1;
+		let mut bits = 0;
+
+		let mut c = atoi64(*src.get(*pos).ok_or(-1)?).ok_or(-1)?;
+		*pos += 1;
+
+		let mut dst = min;
+		while c > end {
+			dst = dst.checked_add((end + 1 - start) << bits).ok_or(-1)?;
+			start = end + 1;
+			end = start + (62 - end) / 2;
+			chars += 1;
+			bits += 6;
+		}
+		dst = dst.checked_add((c - start) << bits).ok_or(-1)?;
+
+		while chars > 1 {
+			chars -= 1;
+			c = atoi64(*src.get(*pos).ok_or(-1)?).ok_or(-1)?;
+			*pos += 1;
+			bits -= 6;
+			dst = dst.checked_add(c << bits).ok_or(-1)?;
+		}
+
+		Ok(dst)
+	}
+
+	fn decode64(src: &[u8]) -> Result<Vec<u8>, i32> {
+		let mut dst = Vec::new();
+
+		for group in src.chunks(4) {
+			let mut value = 0u32;
+			let mut bits = 0;
+			for c in group {
+				value |= atoi64(*c).ok_or(-1)? << bits;
+				bits += 6;
+			}
+
+			if bits < 12 {
+				return Err(-1);
+			}
+
+			while bits >= 8 {
+				dst.push(value as u8);
+				value >>= 8;
+				bits -= 8;
+			}
+
+			if value != 0 {
+				return Err(-1);
+			}
+		}
+
+		Ok(dst)
+	}
+
+	fn encode64(src: &[u8]) -> String {
+		let mut out = String::new();
+
+		for group in src.chunks(3) {
+			let mut value = 0u32;
+			for (i, b) in group.iter().enumerate() {
+				value |= (*b as u32) << (8 * i);
+			}
+
+			for _ in 0..(group.len() * 8).div_ceil(6) {
+				out.push(ITOA64[(value & 0x3f) as usize] as char);
+				value >>= 6;
+			}
+		}
+
+		out
+	}
+
+	fn hmac_sha256(key: &[u8], msg: &[u8]) -> [u8; 32] {
+		let mut k = [0u8; 64];
+		if key.len() > 64 {
+			k[..32].copy_from_slice(&Sha256::digest(key));
+		} else {
+			k[..key.len()].copy_from_slice(key);
+		}
+
+		let inner = Sha256::new().chain_update(k.map(|b| b ^ 0x36)).chain_update(msg).finalize();
+		Sha256::new().chain_update(k.map(|b| b ^ 0x5c)).chain_update(inner).finalize().into()
+	}
+
+	/// PBKDF2-HMAC-SHA256 with a single iteration, which is all yescrypt ever uses
+	fn pbkdf2_sha256(password: &[u8], salt: &[u8], out: &mut [u8]) {
+		for (i, chunk) in out.chunks_mut(32).enumerate() {
+			let mut msg = salt.to_vec();
+			msg.extend_from_slice(&(i as u32 + 1).to_be_bytes());
+			let u = hmac_sha256(password, &msg);
+			chunk.copy_from_slice(&u[..chunk.len()]);
+		}
+	}
+
+	/// Salsa20 core, on a block kept in the SIMD shuffled order the reference implementation uses
+	fn salsa20(b: &mut [u32], rounds: usize) {
+		let mut x = [0u32; 16];
+		for i in 0..16 {
+			x[i * 5 % 16] = b[i];
+		}
+
+		macro_rules! quarter {
+			($a:expr, $b:expr, $c:expr, $d:expr) => {
+				x[$b] ^= x[$a].wrapping_add(x[$d]).rotate_left(7);
+				x[$c] ^= x[$b].wrapping_add(x[$a]).rotate_left(9);
+				x[$d] ^= x[$c].wrapping_add(x[$b]).rotate_left(13);
+				x[$a] ^= x[$d].wrapping_add(x[$c]).rotate_left(18);
+			};
+		}
+
+		for _ in (0..rounds).step_by(2) {
+			quarter!(0, 4, 8, 12);
+			quarter!(5, 9, 13, 1);
+			quarter!(10, 14, 2, 6);
+			quarter!(15, 3, 7, 11);
+			quarter!(0, 1, 2, 3);
+			quarter!(5, 6, 7, 4);
+			quarter!(10, 11, 8, 9);
+			quarter!(15, 12, 13, 14);
+		}
+
+		for i in 0..16 {
+			b[i] = b[i].wrapping_add(x[i * 5 % 16]);
+		}
+	}
+
+	fn blkxor(dst: &mut [u32], src: &[u32]) {
+		for (d, s) in dst.iter_mut().zip(src) {
+			*d ^= s;
+		}
+	}
+
+	fn blockmix_salsa8(b: &mut [u32], y: &mut [u32], r: usize) {
+		let mut x = [0u32; 16];
+		x.copy_from_slice(&b[(2 * r - 1) * 16..2 * r * 16]);
+
+		for i in 0..2 * r {
+			blkxor(&mut x, &b[i * 16..(i + 1) * 16]);
+			salsa20(&mut x, 8);
+			y[i * 16..(i + 1) * 16].copy_from_slice(&x);
+		}
+
+		for i in 0..r {
+			b[i * 16..(i + 1) * 16].copy_from_slice(&y[(i * 2) * 16..(i * 2 + 1) * 16]);
+			b[(i + r) * 16..(i + r + 1) * 16].copy_from_slice(&y[(i * 2 + 1) * 16..(i * 2 + 2) * 16]);
+		}
+	}
+
+	/// Which third of S is currently S0, S1 and S2, as word offsets, and the S2 write position
+	#[derive(Clone, Copy)]
+	struct PwxState {
+		s0: usize,
+		s1: usize,
+		s2: usize,
+		w: usize,
+	}
+
+	impl Default for PwxState {
+		fn default() -> Self {
+			PwxState { s0: S_WORDS / 3 * 2, s1: S_WORDS / 3, s2: 0, w: 0 }
+		}
+	}
+
+	/// The S-boxes pwxform works with, and where it is in rotating through them
+	struct Pwxform<'a> {
+		s: &'a mut [u32],
+		st: PwxState,
+	}
+
+	impl<'a> Pwxform<'a> {
+		fn pwxform(&mut self, b: &mut [u32; PWX_WORDS]) {
+			let PwxState { s0, s1, s2, mut w } = self.st;
+
+			for i in 0..PWX_ROUNDS {
+				for j in 0..PWX_GATHER {
+					let base = j * PWX_SIMPLE * 2;
+					let p0 = s0 + ((b[base] & S_MASK) / 8 * 2) as usize;
+					let p1 = s1 + ((b[base + 1] & S_MASK) / 8 * 2) as usize;
+
+					for k in 0..PWX_SIMPLE {
+						let s0 = ((self.s[p0 + 2 * k + 1] as u64) << 32) | self.s[p0 + 2 * k] as u64;
+						let s1 = ((self.s[p1 + 2 * k + 1] as u64) << 32) | self.s[p1 + 2 * k] as u64;
+
+						let xl = b[base + 2 * k] as u64;
+						let xh = b[base + 2 * k + 1] as u64;
+						let x = (xh * xl).wrapping_add(s0) ^ s1;
+
+						b[base + 2 * k] = x as u32;
+						b[base + 2 * k + 1] = (x >> 32) as u32;
+
+						if i != 0 && i != PWX_ROUNDS - 1 {
+							self.s[s2 + 2 * w] = x as u32;
+							self.s[s2 + 2 * w + 1] = (x >> 32) as u32;
+							w += 1;
+						}
+					}
+				}
+			}
+
+			self.st = PwxState { s0: s2, s1: s0, s2: s1, w: w & ((1 << SWIDTH) * PWX_SIMPLE - 1) };
+		}
+
+		fn blockmix(&mut self, b: &mut [u32], r: usize) {
+			let r1 = 128 * r / (PWX_WORDS * 4);
+			let mut x = [0u32; PWX_WORDS];
+			x.copy_from_slice(&b[(r1 - 1) * PWX_WORDS..r1 * PWX_WORDS]);
+
+			for i in 0..r1 {
+				if r1 > 1 {
+					blkxor(&mut x, &b[i * PWX_WORDS..(i + 1) * PWX_WORDS]);
+				}
+				self.pwxform(&mut x);
+				b[i * PWX_WORDS..(i + 1) * PWX_WORDS].copy_from_slice(&x);
+			}
+
+			let i = (r1 - 1) * PWX_WORDS * 4 / 64;
+			salsa20(&mut b[i * 16..(i + 1) * 16], 2);
+		}
+	}
+
+	fn blockmix(x: &mut [u32], y: &mut [u32], r: usize, ctx: &mut Option<Pwxform>) {
+		match ctx {
+			Some(ctx) => ctx.blockmix(x, r),
+			None => blockmix_salsa8(x, y, r),
+		}
+	}
+
+	fn integerify(x: &[u32], r: usize) -> u64 {
+		let last = &x[(2 * r - 1) * 16..];
+		((last[13] as u64) << 32) | last[0] as u64
+	}
+
+	fn p2floor(mut x: u64) -> u64 {
+		loop {
+			let y = x & (x - 1);
+			if y == 0 {
+				return x;
+			}
+			x = y;
+		}
+	}
+
+	fn wrap(x: u64, i: u64) -> u64 {
+		let n = p2floor(i);
+		(x & (n - 1)) + (i - n)
+	}
+
+	fn shuffle(b: &[u32], x: &mut [u32]) {
+		for k in 0..b.len() / 16 {
+			for i in 0..16 {
+				x[k * 16 + i] = b[k * 16 + (i * 5 % 16)];
+			}
+		}
+	}
+
+	fn unshuffle(x: &[u32], b: &mut [u32]) {
+		for k in 0..b.len() / 16 {
+			for i in 0..16 {
+				b[k * 16 + (i * 5 % 16)] = x[k * 16 + i];
+			}
+		}
+	}
+
+	fn smix1(b: &mut [u32], r: usize, n: u64, flags: u32, v: &mut [u32], ctx: &mut Option<Pwxform>) {
+		let s = 32 * r;
+		let mut x = vec![0u32; s];
+		let mut y = vec![0u32; s];
+		shuffle(&b[..s], &mut x);
+
+		for i in 0..n {
+			let iu = i as usize;
+			v[iu * s..(iu + 1) * s].copy_from_slice(&x);
+
+			if flags & YESCRYPT_RW != 0 && i > 1 {
+				let j = wrap(integerify(&x, r), i) as usize;
+				blkxor(&mut x, &v[j * s..(j + 1) * s]);
+			}
+
+			blockmix(&mut x, &mut y, r, ctx);
+		}
+
+		unshuffle(&x, &mut b[..s]);
+	}
+
+	fn smix2(b: &mut [u32], r: usize, n: u64, nloop: u64, flags: u32, v: &mut [u32], ctx: &mut Option<Pwxform>) {
+		if nloop == 0 {
+			return;
+		}
+
+		let s = 32 * r;
+		let mut x = vec![0u32; s];
+		let mut y = vec![0u32; s];
+		shuffle(&b[..s], &mut x);
+
+		for _ in 0..nloop {
+			let j = (integerify(&x, r) & (n - 1)) as usize;
+			blkxor(&mut x, &v[j * s..(j + 1) * s]);
+			if flags & YESCRYPT_RW != 0 {
+				v[j * s..(j + 1) * s].copy_from_slice(&x);
+			}
+
+			blockmix(&mut x, &mut y, r, ctx);
+		}
+
+		unshuffle(&x, &mut b[..s]);
+	}
+
+	#[allow(clippy::too_many_arguments)]
+	fn smix(b: &mut [u32], r: usize, n: u64, p: u32, t: u32, flags: u32, v: &mut [u32], mut sbox: Option<&mut [u32]>, passwd: &mut [u8]) {
+		let s = 32 * r;
+		let nchunk = n / p as u64;
+
+		let mut nloop_all = nchunk;
+		if flags & YESCRYPT_RW != 0 {
+			if t <= 1 {
+				if t != 0 {
+					nloop_all *= 2;
+				}
+				nloop_all = nloop_all.div_ceil(3);
+			} else {
+				nloop_all *= (t - 1) as u64;
+			}
+		} else if t != 0 {
+			if t == 1 {
+				nloop_all += nloop_all.div_ceil(2);
+			}
+			nloop_all *= t as u64;
+		}
+
+		let mut nloop_rw = if flags & YESCRYPT_RW != 0 { nloop_all / p as u64 } else { 0 };
+
+		let nchunk = nchunk & !1;
+		nloop_all = (nloop_all + 1) & !1;
+		nloop_rw = (nloop_rw + 1) & !1;
+
+		let mut states = vec![PwxState::default(); p as usize];
+		let mut vchunk = 0;
+		for i in 0..p as usize {
+			let np = if i < p as usize - 1 { nchunk } else { n - vchunk };
+			let bp = &mut b[i * s..(i + 1) * s];
+			let vp = &mut v[vchunk as usize * s..];
+
+			let mut ctx = match sbox.as_deref_mut() {
+				Some(sb) => {
+					let sp = &mut sb[i * S_WORDS..(i + 1) * S_WORDS];
+					smix1(bp, 1, (S_BYTES / 128) as u64, 0, sp, &mut None);
+					if i == 0 {
+						let key: Vec<u8> = bp[s - 16..].iter().flat_map(|w| w.to_le_bytes()).collect();
+						let mac = hmac_sha256(&key, passwd);
+						passwd.copy_from_slice(&mac);
+					}
+					Some(Pwxform { s: sp, st: states[i] })
+				},
+				None => None,
+			};
+
+			smix1(bp, r, np, flags, vp, &mut ctx);
+			smix2(bp, r, p2floor(np), nloop_rw, flags, vp, &mut ctx);
+			if let Some(ctx) = ctx {
+				states[i] = ctx.st;
+			}
+			vchunk += nchunk;
+		}
+
+		for i in 0..p as usize {
+			let bp = &mut b[i * s..(i + 1) * s];
+			let mut ctx = sbox.as_deref_mut().map(|sb| Pwxform { s: &mut sb[i * S_WORDS..(i + 1) * S_WORDS], st: states[i] });
+			smix2(bp, r, n, nloop_all - nloop_rw, flags & !YESCRYPT_RW, v, &mut ctx);
+		}
+	}
+
+	#[allow(clippy::too_many_arguments)]
+	fn kdf_body(passwd: &[u8], salt: &[u8], flags: u32, n: u64, r: u32, p: u32, t: u32, out: &mut [u8]) -> Result<(), i32> {
+		match flags & 0x3 {
+			0 if flags == 0 && t == 0 => (),
+			YESCRYPT_WORM if flags == YESCRYPT_WORM => (),
+			YESCRYPT_RW if flags & !(YESCRYPT_RW | YESCRYPT_RW_FLAVOR_MASK | YESCRYPT_PREHASH) == 0
+				&& flags & YESCRYPT_RW_FLAVOR_MASK == YESCRYPT_RW_DEFAULT_FLAVOR => (),
+			_ => return Err(-1),
+		}
+
+		if n < 2 || n & (n - 1) != 0 || r == 0 || p == 0 || (flags & YESCRYPT_RW != 0 && n / (p as u64) < 2) {
+			return Err(-1);
+		}
+
+		// A hash string can ask for any N, so the sizes have to be checked without wrapping around
+		let v_bytes = 128u64.checked_mul(r as u64).and_then(|b| b.checked_mul(n));
+		let b_bytes = 128u64.checked_mul(r as u64).and_then(|b| b.checked_mul(p as u64));
+		if v_bytes.is_none_or(|b| b > MAX_V_BYTES) || b_bytes.is_none_or(|b| b > MAX_V_BYTES) {
+			return Err(-1);
+		}
+		let r = r as usize;
+
+		let mut pw: Vec<u8> = passwd.to_vec();
+		if flags != 0 {
+			let key: &[u8] = if flags & YESCRYPT_PREHASH != 0 { b"yescrypt-prehash" } else { b"yescrypt" };
+			pw = hmac_sha256(key, passwd).to_vec();
+		}
+
+		let mut b_bytes = vec![0u8; 128 * r * p as usize];
+		pbkdf2_sha256(&pw, salt, &mut b_bytes);
+
+		if t != 0 || flags != 0 {
+			pw = b_bytes[..32].to_vec();
+		}
+
+		let mut b: Vec<u32> = b_bytes.chunks(4).map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect();
+		let mut v = vec![0u32; 32 * r * n as usize];
+		let mut sbox = if flags & YESCRYPT_RW != 0 { Some(vec![0u32; S_WORDS * p as usize]) } else { None };
+
+		if p == 1 || flags & YESCRYPT_RW != 0 {
+			smix(&mut b, r, n, p, t, flags, &mut v, sbox.as_deref_mut(), &mut pw);
+		} else {
+			for i in 0..p as usize {
+				smix(&mut b[i * 32 * r..(i + 1) * 32 * r], r, n, 1, t, flags, &mut v, None, &mut []);
+			}
+		}
+
+		let b_bytes: Vec<u8> = b.iter().flat_map(|w| w.to_le_bytes()).collect();
+		pbkdf2_sha256(&pw, &b_bytes, out);
+
+		if flags != 0 && flags & YESCRYPT_PREHASH == 0 {
+			let client_key = hmac_sha256(&out[..32.min(out.len())], b"Client Key");
+			let stored_key = Sha256::digest(client_key);
+			let len = out.len().min(32);
+			out[..len].copy_from_slice(&stored_key[..len]);
+		}
+
+		Ok(())
+	}
+
+	fn kdf(passwd: &[u8], salt: &[u8], params: &Params, out: &mut [u8]) -> Result<(), i32> {
+		let mut dk = [0u8; 32];
+		let mut passwd = passwd;
+
+		if params.flags & YESCRYPT_RW != 0 && params.n / params.p as u64 >= 0x100 && params.n / params.p as u64 * params.r as u64 >= 0x20000 {
+			kdf_body(passwd, salt, params.flags | YESCRYPT_PREHASH, params.n >> 6, params.r, params.p, 0, &mut dk)?;
+			passwd = &dk;
+		}
+
+		kdf_body(passwd, salt, params.flags, params.n, params.r, params.p, params.t, out)
+	}
+
+	/// Decode a `$y$` setting, returning the parameters and the prefix up to and including the salt
+	fn decode_setting(setting: &str) -> Result<(Params, &str, Vec<u8>), i32> {
+		let bytes = setting.as_bytes();
+		if !setting.starts_with("$y$") {
+			return Err(-1);
+		}
+
+		let mut pos = 3;
+		let flavor = decode64_uint32(bytes, &mut pos, 0)?;
+		let flags = if flavor < YESCRYPT_RW {
+			flavor
+		} else if flavor <= YESCRYPT_RW + (YESCRYPT_RW_FLAVOR_MASK >> 2) {
+			YESCRYPT_RW + ((flavor - YESCRYPT_RW) << 2)
+		} else {
+			return Err(-1);
+		};
+
+		let n_log2 = decode64_uint32(bytes, &mut pos, 1)?;
+		if n_log2 > 63 {
+			return Err(-1);
+		}
+		let r = decode64_uint32(bytes, &mut pos, 1)?;
+
+		let mut params = Params { flags, n: 1u64 << n_log2, r, p: 1, t: 0 };
+
+		if bytes.get(pos) != Some(&b'$') {
+			let have = decode64_uint32(bytes, &mut pos, 1)?;
+			if have & 1 != 0 {
+				params.p = decode64_uint32(bytes, &mut pos, 2)?;
+			}
+			if have & 2 != 0 {
+				params.t = decode64_uint32(bytes, &mut pos, 1)?;
+			}
+			if have & !3 != 0 {
+				// Hash upgrades and ROMs aren't supported
+				return Err(-1);
+			}
+		}
+
+		if bytes.get(pos) != Some(&b'$') {
+			return Err(-1);
+		}
+		pos += 1;
+
+		let salt_str = setting[pos..].split('$').next().unwrap_or("");
+		let salt = decode64(salt_str.as_bytes())?;
+
+		Ok((params, &setting[..pos + salt_str.len()], salt))
+	}
+
+	pub(super) fn crypt(passwd: &[u8], setting: &str) -> Result<String, i32> {
+		let (params, prefix, salt) = decode_setting(setting.trim_start_matches('!'))?;
+		let mut hash = [0u8; 32];
+
+		kdf(passwd, &salt, &params, &mut hash)?;
+
+		Ok(format!("{}${}", prefix, encode64(&hash)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+
+	#[test]
+	fn known_answers() {
+		// MD5 and SHA crypt answers are from the SHA crypt specification and glibc, yescrypt from libxcrypt
+		let cases = [
+			("$1$saltstri", "$1$saltstri$YMyguxXMBpd2TEZ.vS/3q1"),
+			("$5$saltstring", "$5$saltstring$5B8vYYiY.CVt1RlTTf8KbXBH3hsxY/GNooZaBBGWEc5"),
+			("$5$rounds=10000$saltstringsaltstring", "$5$rounds=10000$saltstringsaltst$3xv.VbSHBb41AL9AvLeujZkZRBAwqFMz2.opqey6IcA"),
+			("$6$saltstring", "$6$saltstring$svn8UoSVapNtMuq1ukKS4tPQd8iKwSMHWjl/O817G3uBnIFNjnQJuesI68u4OTLiBFdcbYEdFCoEOfaS35inz1"),
+			("$6$rounds=5000$toolongsaltstring", "$6$rounds=5000$toolongsaltstrin$iGlL7EUUfzNQx59x3ydJZ.zXPMUu1dOynSEl/vcNhLlas77qD0DzRswhhB6LdrXTz250at0syAfUXra.XrxAI1"),
+			("$y$j9T$PKwW3Bdc5d2V6W4Ccv2Yq1", "$y$j9T$PKwW3Bdc5d2V6W4Ccv2Yq1$gxCcuHCFnBvWEjlHSsH4MvwD18n2pOGOrot01aGi220"),
+		];
+
+		for (setting, expected) in cases {
+			assert_eq!(crypt_password(&"Hello world!", &setting).as_deref(), Ok(expected), "{}", setting);
+			assert_eq!(verify_password(&"Hello world!", &expected), Ok(true));
+			assert_eq!(verify_password(&"Hello world?", &expected), Ok(false));
+		}
+
+		assert_eq!(crypt_password(&"x", &"$2b$12$abc"), Err(-1));
+	}
+
+	#[test]
+	fn weak_passwords_from_shadow() {
+		let root = test_root("crypt-weak");
+		write_rooted(&root, "/etc/shadow", "\
+candace:$6$fixture1$41BQry1RyYghXvuTIizYsqf7YFh2p3hcLVx.zRmGndxGIgquv.PmJvR6qEoWfhFXL6tirJyqsflWZxCSsniZy0:19500:0:99999:7:::
+carl:!$5$fixture2$On66RHU6roL/fbzuxskuJjTcqIKnhi1CP/TlZLpc.g/:19500:0:99999:7:::
+perry:$1$fixture3$Qk7kD.WmOiJ6gyne7pbBP/:19500:0:99999:7:::
+isabella::19500:0:99999:7:::
+daemon:*:19500:0:99999:7:::
+");
+		let root = root.display().to_string();
+
+		assert_eq!(find_weak_password_in(&root, &"candace", &["secret", "letmein"]), Ok(Some("letmein".to_string())));
+		assert_eq!(find_common_weak_password_in(&root, &"candace"), Ok(Some("letmein".to_string())));
+		// Locked accounts still report the password they would unlock with
+		assert_eq!(find_common_weak_password_in(&root, &"carl"), Ok(Some("carl".to_string())));
+		assert_eq!(find_common_weak_password_in(&root, &"perry"), Ok(None));
+		assert_eq!(user_password_is_in(&root, &"perry", &"Tr0ub4dor&3"), Ok(true));
+		assert_eq!(find_weak_password_in(&root, &"isabella", &[]), Ok(Some(String::new())));
+		assert_eq!(find_common_weak_password_in(&root, &"daemon"), Ok(None));
+		assert_eq!(find_weak_password_in(&root, &"doof", &["password"]), Err(libc::ENOENT));
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn yescrypt_huge_cost_is_rejected() {
+		// N = 2^57, whose memory size wraps around a u64
+		assert_eq!(verify_password(&"x", &"$y$/k6.$abcdefgh$"), Err(-1));
+		assert_eq!(verify_password(&"x", &"$y$jk6.$abcdefgh$xx"), Err(-1));
+	}
+}
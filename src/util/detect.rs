@@ -0,0 +1,314 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+use std::{
+	collections::BTreeSet,
+	path::{Path, PathBuf},
+	string::String,
+	vec::Vec,
+};
+
+use crate::engine::{AppData, InstallMethod};
+use super::{
+	rooted_path, get_file_mode, list_users_in, LoginDefs, PackageBackend,
+	detect_package_backend, detect_package_backend_in, flatpak_app, flatpak_app_in, snap_package, snap_package_in,
+};
+
+/// The `PATH` used when looking under a root directory, where the live environment doesn't apply
+const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// Directories snapd and flatpak put launchers in, which aren't manual installs
+const EXPORT_DIRS: [&str; 3] = ["/snap/bin", "/var/lib/snapd/snap/bin", "/var/lib/flatpak/exports/bin"];
+
+/// Directories under a home directory where AppImages usually end up
+const APPIMAGE_DIRS: [&str; 5] = ["", "Applications", "Downloads", "Desktop", ".local/bin"];
+
+/// System directories holding .desktop launchers, each user's ~/.local/share/applications is searched as well
+const LAUNCHER_DIRS: [&str; 2] = ["/usr/share/applications", "/usr/local/share/applications"];
+
+/// One place an application was found by [`AppData::detect`]
+#[derive(Clone, Debug)]
+pub struct DetectedInstall {
+	pub method: InstallMethod,
+	/// The name it was found under, like a package name or a flatpak application ID
+	pub name: String,
+	pub version: Option<String>,
+	/// The file or directory of a manual install, [`None`] for anything a package manager tracks
+	pub location: Option<PathBuf>,
+}
+
+impl DetectedInstall {
+	/// Turn the install into an [`AppData`], to use with the rest of the engine
+	pub fn app_data(&self) -> AppData {
+		AppData::new(&self.name, self.method)
+	}
+}
+
+/// List the files in `dir` whose names start with `name` and end with `.AppImage`, ignoring case
+fn appimages_in(dir: &Path, name: &str) -> Vec<PathBuf> {
+	let name = name.to_lowercase();
+	std::fs::read_dir(dir).into_iter().flatten().filter_map(|e| e.ok())
+		.map(|e| e.path())
+		.filter(|p| p.is_file())
+		.filter(|p| {
+			let file = p.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+			file.starts_with(&name) && file.ends_with(".appimage")
+		})
+		.collect()
+}
+
+/// Get the program a .desktop launcher runs, from the first `Exec=` key of its `[Desktop Entry]` group
+///
+/// Leading `env` and `VAR=value` words are skipped, and quotes around the program are removed.
+fn launcher_program(text: &str) -> Option<String> {
+	let mut in_entry = false;
+
+	for line in text.lines().map(|l| l.trim()) {
+		if line.starts_with('[') {
+			in_entry = line == "[Desktop Entry]";
+			continue;
+		}
+
+		if let Some(exec) = line.strip_prefix("Exec=").filter(|_| in_entry) {
+			let program = if let Some(quoted) = exec.strip_prefix('"') {
+				quoted.split('"').next()?
+			} else {
+				exec.split_whitespace().find(|w| *w != "env" && !w.contains('='))?
+			};
+			return Some(program.to_string());
+		}
+	}
+
+	None
+}
+
+/// List the programs run by .desktop launchers in `dir` that are `name` itself or an AppImage of it
+fn launcher_targets(dir: &Path, name: &str) -> Vec<String> {
+	let name = name.to_lowercase();
+	std::fs::read_dir(dir).into_iter().flatten().filter_map(|e| e.ok())
+		.map(|e| e.path())
+		.filter(|p| p.extension().is_some_and(|e| e == "desktop"))
+		.filter_map(|p| launcher_program(&std::fs::read_to_string(p).ok()?))
+		.filter(|program| {
+			let base = program.rsplit('/').next().unwrap_or(program).to_lowercase();
+			base == name || (base.starts_with(&name) && base.ends_with(".appimage"))
+		})
+		.collect()
+}
+
+/// Find manual installs of `name` under `root`, as system paths
+fn find_manual_installs(root: &str, name: &str, path_var: &str) -> Vec<PathBuf> {
+	let system = |p: &Path| Path::new("/").join(p.strip_prefix(root).unwrap_or(p));
+	let mut found: BTreeSet<PathBuf> = BTreeSet::new();
+
+	let opt = rooted_path(&root, "/opt");
+	for entry in std::fs::read_dir(&opt).into_iter().flatten().filter_map(|e| e.ok()) {
+		let path = entry.path();
+		if entry.file_name().to_string_lossy().eq_ignore_ascii_case(name) {
+			found.insert(system(&path));
+		}
+		for candidate in [path.join(name), path.join("bin").join(name)] {
+			if candidate.is_file() {
+				found.insert(system(&candidate));
+			}
+		}
+	}
+	found.extend(appimages_in(&opt, name).iter().map(|p| system(p)));
+
+	for dir in ["/usr/local/bin", "/usr/local/sbin"] {
+		let candidate = rooted_path(&root, dir).join(name);
+		if candidate.is_file() {
+			found.insert(system(&candidate));
+		}
+	}
+
+	let mut launcher_dirs: Vec<PathBuf> = LAUNCHER_DIRS.iter().map(|d| rooted_path(&root, d)).collect();
+
+	let defs = LoginDefs::load_in(&root).unwrap_or_default();
+	for user in list_users_in(&root).unwrap_or_default().into_iter().filter(|u| u.uid == 0 || defs.is_human_uid(u.uid)) {
+		let home = rooted_path(&root, &user.home_dir);
+		let candidate = home.join(".local/bin").join(name);
+		if candidate.is_file() {
+			found.insert(system(&candidate));
+		}
+		for dir in APPIMAGE_DIRS {
+			found.extend(appimages_in(&home.join(dir), name).iter().map(|p| system(p)));
+		}
+		launcher_dirs.push(home.join(".local/share/applications"));
+	}
+
+	// Launchers find programs kept anywhere, like an AppImage in ~/tools, while bare command names are covered by PATH below
+	for dir in launcher_dirs {
+		for program in launcher_targets(&dir, name) {
+			let exported = EXPORT_DIRS.iter().any(|d| Path::new(&program).parent() == Some(Path::new(d)));
+			if program.starts_with('/') && !exported && rooted_path(&root, &program).is_file() {
+				found.insert(PathBuf::from(program));
+			}
+		}
+	}
+
+	for dir in path_var.split(':').filter(|d| d.starts_with('/') && !EXPORT_DIRS.contains(d)) {
+		let candidate = rooted_path(&root, dir).join(name);
+		let executable = get_file_mode(&candidate.display()).is_ok_and(|m| m & 0o111 != 0);
+		if candidate.is_file() && executable {
+			found.insert(system(&candidate));
+		}
+	}
+
+	let backend = detect_package_backend_in(&root);
+	found.into_iter()
+		.filter(|p| !backend.as_ref().is_some_and(|b| b.owns_file(&p.display().to_string()).unwrap_or(false)))
+		.collect()
+}
+
+/// List the manual installs of `name`, meaning anything no package manager tracks
+///
+/// This looks in /opt, /usr/local/bin, each user's ~/.local/bin, for AppImages in /opt and the usual places in home directories,
+/// at the programs .desktop launchers run, and for executables on `PATH`. Anything a package owns is left out.
+pub fn manual_installs<T: ToString>(name: &T) -> Vec<PathBuf> {
+	let path_var = std::env::var("PATH").unwrap_or(DEFAULT_PATH.to_string());
+	find_manual_installs("/", &name.to_string(), &path_var)
+}
+
+/// List the manual installs of `name` under the root directory `root`, using a default `PATH`
+pub fn manual_installs_in<A: ToString, B: ToString>(root: &A, name: &B) -> Vec<PathBuf> {
+	find_manual_installs(&root.to_string(), &name.to_string(), DEFAULT_PATH)
+}
+
+impl AppData {
+	/// Find every way the application named `name` is installed
+	///
+	/// The system package manager, snap, flatpak and the manual install locations of [`manual_installs`] are all searched,
+	/// and each install found is returned with its method. An empty list means it wasn't found anywhere.
+	pub fn detect<T: ToString>(name: &T) -> Vec<DetectedInstall> {
+		let name = name.to_string();
+		let mut installs = Vec::new();
+
+		if let Some(backend) = detect_package_backend() {
+			installs.extend(package_install(backend.as_ref(), &name));
+		}
+		if let Ok(Some(snap)) = snap_package(&name) {
+			installs.push(DetectedInstall { method: InstallMethod::Snap, name: snap.name, version: Some(snap.version), location: None });
+		}
+		if let Ok(Some(app)) = flatpak_app(&name) {
+			installs.push(DetectedInstall { method: InstallMethod::Flatpak, name: app.application, version: Some(app.version), location: None });
+		}
+		installs.extend(manual_installs(&name).into_iter().map(|p| manual_install(&name, p)));
+
+		installs
+	}
+
+	/// Find every way the application named `name` is installed under the root directory `root`
+	pub fn detect_in<A: ToString, B: ToString>(root: &A, name: &B) -> Vec<DetectedInstall> {
+		let name = name.to_string();
+		let mut installs = Vec::new();
+
+		if let Some(backend) = detect_package_backend_in(root) {
+			installs.extend(package_install(backend.as_ref(), &name));
+		}
+		if let Ok(Some(snap)) = snap_package_in(root, &name) {
+			installs.push(DetectedInstall { method: InstallMethod::Snap, name: snap.name, version: Some(snap.version), location: None });
+		}
+		if let Ok(Some(app)) = flatpak_app_in(root, &name) {
+			installs.push(DetectedInstall { method: InstallMethod::Flatpak, name: app.application, version: Some(app.version), location: None });
+		}
+		installs.extend(manual_installs_in(root, &name).into_iter().map(|p| manual_install(&name, p)));
+
+		installs
+	}
+}
+
+fn package_install(backend: &dyn PackageBackend, name: &str) -> Option<DetectedInstall> {
+	let package = backend.package(name).ok().flatten()?;
+	Some(DetectedInstall { method: InstallMethod::PackageManager, name: package.name, version: Some(package.version), location: None })
+}
+
+fn manual_install(name: &str, path: PathBuf) -> DetectedInstall {
+	DetectedInstall { method: InstallMethod::ManualInstall, name: name.to_string(), version: None, location: Some(path) }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+	use std::os::unix::fs::PermissionsExt;
+
+	#[test]
+	fn launcher_programs() {
+		assert_eq!(launcher_program("[Desktop Entry]\nName=Zenmap\nExec=zenmap %F\n").as_deref(), Some("zenmap"));
+		assert_eq!(launcher_program("[Desktop Entry]\nExec=env GDK_BACKEND=x11 /opt/tool/run --x\n").as_deref(), Some("/opt/tool/run"));
+		assert_eq!(launcher_program("[Desktop Entry]\nExec=\"/home/a/My Tools/x.AppImage\" %U\n").as_deref(), Some("/home/a/My Tools/x.AppImage"));
+		assert_eq!(launcher_program("[Desktop Action new]\nExec=other\n[Desktop Entry]\nName=x\n"), None);
+	}
+
+	#[test]
+	fn detect_every_install() {
+		let root = test_root("detect-installs");
+		write_rooted(&root, "/etc/os-release", "ID=ubuntu\nID_LIKE=debian\n");
+		write_rooted(&root, "/etc/passwd", "\
+root:x:0:0:root:/root:/bin/bash
+daemon:x:1:1:daemon:/usr/sbin:/usr/sbin/nologin
+perry:x:1000:1000::/home/perry:/bin/bash
+");
+		write_rooted(&root, "/var/lib/dpkg/status", "\
+Package: nmap
+Status: install ok installed
+Architecture: amd64
+Version: 7.94+git20230807.3be01efb1+dfsg-3build2
+");
+		write_rooted(&root, "/var/lib/dpkg/info/nmap.list", "/.\n/usr/bin/nmap\n/usr/share/applications/nmap.desktop\n");
+		write_rooted(&root, "/var/lib/snapd/state.json", r#"{"data": {"snaps": {"nmap": {"type": "app", "active": true, "current": "3432", "channel": "latest/stable"}}}}"#);
+		write_rooted(&root, "/snap/nmap/3432/meta/snap.yaml", "name: nmap\nversion: 7.95\n");
+
+		write_rooted(&root, "/usr/bin/nmap", "packaged");
+		write_rooted(&root, "/usr/local/bin/nmap", "built from source");
+		write_rooted(&root, "/opt/nmap/bin/nmap", "unpacked tarball");
+		write_rooted(&root, "/opt/nmap/share/nmap-services", "");
+		write_rooted(&root, "/home/perry/Downloads/Nmap-7.95-x86_64.AppImage", "");
+		write_rooted(&root, "/home/perry/tools/nmap-gui.AppImage", "");
+		write_rooted(&root, "/usr/sbin/nmap", "not executable");
+		write_rooted(&root, "/usr/games/nmap", "executable, but not on PATH");
+		write_rooted(&root, "/sbin/nmap", "on PATH");
+		for file in ["usr/bin/nmap", "usr/games/nmap", "sbin/nmap"] {
+			std::fs::set_permissions(root.join(file), std::fs::Permissions::from_mode(0o755)).unwrap();
+		}
+		std::fs::set_permissions(root.join("usr/sbin/nmap"), std::fs::Permissions::from_mode(0o644)).unwrap();
+
+		write_rooted(&root, "/usr/share/applications/nmap.desktop", "[Desktop Entry]\nName=Nmap\nExec=/usr/bin/nmap\n");
+		write_rooted(&root, "/usr/share/applications/nmap_snap.desktop", "[Desktop Entry]\nName=Nmap\nExec=/snap/bin/nmap\n");
+		write_rooted(&root, "/home/perry/.local/share/applications/nmap-gui.desktop", "\
+[Desktop Entry]
+Type=Application
+Name=Nmap GUI
+Exec=\"/home/perry/tools/nmap-gui.AppImage\" %U
+");
+		write_rooted(&root, "/home/perry/.local/share/applications/stale.desktop", "[Desktop Entry]\nExec=/home/perry/old/nmap\n");
+
+		let manual: Vec<String> = manual_installs_in(&root.display(), &"nmap").iter().map(|p| p.display().to_string()).collect();
+		assert_eq!(manual, [
+			"/home/perry/Downloads/Nmap-7.95-x86_64.AppImage",
+			"/home/perry/tools/nmap-gui.AppImage",
+			"/opt/nmap",
+			"/opt/nmap/bin/nmap",
+			"/sbin/nmap",
+			"/usr/local/bin/nmap",
+		]);
+
+		let installs = AppData::detect_in(&root.display(), &"nmap");
+		let methods: Vec<InstallMethod> = installs.iter().map(|i| i.method).collect();
+		assert_eq!(methods[..2], [InstallMethod::PackageManager, InstallMethod::Snap]);
+		assert!(methods[2..].iter().all(|m| *m == InstallMethod::ManualInstall));
+		assert_eq!(installs.len(), 8);
+		assert_eq!(installs[0].version.as_deref(), Some("7.94+git20230807.3be01efb1+dfsg-3build2"));
+		assert_eq!(installs[1].version.as_deref(), Some("7.95"));
+		assert_eq!(installs[0].location, None);
+		assert_eq!(installs[7].location, Some(PathBuf::from("/usr/local/bin/nmap")));
+
+		assert!(AppData::detect_in(&root.display(), &"john").is_empty());
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+}
@@ -0,0 +1,349 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+use std::{
+	collections::HashMap,
+	string::String,
+	vec::Vec,
+};
+
+use super::{io_errno, rooted_path};
+
+/// What was last requested for a package, the first word of its `Status` field
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DpkgWant {
+	Unknown,
+	Install,
+	Hold,
+	Deinstall,
+	Purge,
+}
+
+/// The state of a package on disk, the last word of its `Status` field
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DpkgState {
+	NotInstalled,
+	/// Removed, but its configuration files are still there, `rc` in `dpkg -l`
+	ConfigFiles,
+	HalfInstalled,
+	Unpacked,
+	HalfConfigured,
+	TriggersAwaited,
+	TriggersPending,
+	Installed,
+}
+
+/// A package from the dpkg status database
+#[derive(Clone, Debug)]
+pub struct DpkgPackage {
+	pub name: String,
+	pub version: String,
+	pub architecture: String,
+	pub want: DpkgWant,
+	/// Set if dpkg flagged the package as needing a reinstall, `reinstreq` in the `Status` field
+	pub reinstall_required: bool,
+	pub state: DpkgState,
+	/// Every field of the stanza, with continuation lines joined by newlines
+	pub fields: HashMap<String, String>,
+}
+
+/// Split a deb822 style file, like the dpkg status file or an apt Packages list, into its blank line separated stanzas
+pub(crate) fn split_stanzas(contents: &str) -> Vec<String> {
+	let mut stanzas = Vec::new();
+	let mut stanza = String::new();
+
+	for line in contents.lines().chain(std::iter::once("")) {
+		if !line.trim().is_empty() {
+			stanza.push_str(line);
+			stanza.push('\n');
+			continue;
+		}
+
+		if !stanza.is_empty() {
+			stanzas.push(std::mem::take(&mut stanza));
+		}
+	}
+
+	stanzas
+}
+
+/// Parse the fields of a single deb822 stanza, joining continuation lines with newlines
+pub(crate) fn parse_stanza(stanza: &str) -> HashMap<String, String> {
+	let mut fields: HashMap<String, String> = HashMap::new();
+	let mut last_key: Option<String> = None;
+
+	for line in stanza.lines() {
+		if line.starts_with('#') {
+			continue;
+		}
+
+		if line.starts_with(' ') || line.starts_with('\t') {
+			if let Some(value) = last_key.as_ref().and_then(|k| fields.get_mut(k)) {
+				if !value.is_empty() {
+					value.push('\n');
+				}
+				value.push_str(line.trim());
+			}
+			continue;
+		}
+
+		if let Some((k, v)) = line.split_once(':') {
+			fields.insert(k.trim().to_string(), v.trim().to_string());
+			last_key = Some(k.trim().to_string());
+		}
+	}
+
+	fields
+}
+
+impl DpkgPackage {
+	/// Parse a package from a single stanza of the status file
+	///
+	/// Returns `Err(-1)` if the stanza has no `Package` field or its `Status` field is malformed.
+	pub fn parse_entry<T: ToString>(stanza: &T) -> Result<DpkgPackage, i32> {
+		let fields = parse_stanza(&stanza.to_string());
+
+		let status = fields.get("Status").cloned().unwrap_or_default();
+		let status: Vec<&str> = status.split_whitespace().collect();
+		if status.len() != 3 {
+			return Err(-1);
+		}
+
+		let want = match status[0] {
+			"install" => DpkgWant::Install,
+			"hold" => DpkgWant::Hold,
+			"deinstall" => DpkgWant::Deinstall,
+			"purge" => DpkgWant::Purge,
+			_ => DpkgWant::Unknown,
+		};
+
+		let state = match status[2] {
+			"not-installed" => DpkgState::NotInstalled,
+			"config-files" => DpkgState::ConfigFiles,
+			"half-installed" => DpkgState::HalfInstalled,
+			"unpacked" => DpkgState::Unpacked,
+			"half-configured" => DpkgState::HalfConfigured,
+			"triggers-awaited" => DpkgState::TriggersAwaited,
+			"triggers-pending" => DpkgState::TriggersPending,
+			"installed" => DpkgState::Installed,
+			_ => return Err(-1),
+		};
+
+		Ok(DpkgPackage {
+			name: fields.get("Package").cloned().ok_or(-1)?,
+			version: fields.get("Version").cloned().unwrap_or_default(),
+			architecture: fields.get("Architecture").cloned().unwrap_or_default(),
+			want,
+			reinstall_required: status[1] == "reinstreq",
+			state,
+			fields,
+		})
+	}
+
+	/// Parse every package in a dpkg status formatted file
+	pub fn parse_file<T: ToString>(path: &T) -> Result<Vec<DpkgPackage>, i32> {
+		let contents = std::fs::read_to_string(path.to_string()).map_err(io_errno)?;
+
+		split_stanzas(&contents).iter()
+			.map(DpkgPackage::parse_entry)
+			.collect()
+	}
+
+	/// Check if the package is installed, which includes packages that only have triggers left to run
+	pub fn is_installed(&self) -> bool {
+		matches!(self.state, DpkgState::Installed | DpkgState::TriggersAwaited | DpkgState::TriggersPending)
+	}
+
+	/// Check if the package was removed but left its configuration files behind
+	pub fn has_config_files_only(&self) -> bool {
+		self.state == DpkgState::ConfigFiles
+	}
+
+	/// Check if the package is only partially installed or configured, usually from an interrupted install
+	pub fn is_broken(&self) -> bool {
+		self.reinstall_required || matches!(self.state, DpkgState::HalfInstalled | DpkgState::Unpacked | DpkgState::HalfConfigured)
+	}
+
+	/// Check if the package matches `name`, which may carry an architecture like `libc6:amd64`
+	fn matches(&self, name: &str) -> bool {
+		match name.split_once(':') {
+			Some((n, arch)) => self.name == n && self.architecture == arch,
+			None => self.name == name,
+		}
+	}
+}
+
+/// List every package in /var/lib/dpkg/status
+pub fn list_dpkg_packages() -> Result<Vec<DpkgPackage>, i32> {
+	list_dpkg_packages_in(&"/")
+}
+
+/// List every package in /var/lib/dpkg/status under the root directory `root`
+pub fn list_dpkg_packages_in<T: ToString>(root: &T) -> Result<Vec<DpkgPackage>, i32> {
+	DpkgPackage::parse_file(&rooted_path(root, "/var/lib/dpkg/status").display())
+}
+
+/// Get the package named exactly `name` from the dpkg status database
+pub fn dpkg_package<T: ToString>(name: &T) -> Result<Option<DpkgPackage>, i32> {
+	dpkg_package_in(&"/", name)
+}
+
+/// Get the package named exactly `name` from the dpkg status database under the root directory `root`
+///
+/// `name` may carry an architecture, like `libc6:i386`. Without one, an installed architecture is preferred.
+pub fn dpkg_package_in<A: ToString, B: ToString>(root: &A, name: &B) -> Result<Option<DpkgPackage>, i32> {
+	let name = name.to_string();
+	let mut matches: Vec<DpkgPackage> = list_dpkg_packages_in(root)?.into_iter()
+		.filter(|p| p.matches(&name))
+		.collect();
+
+	let idx = matches.iter().position(|p| p.is_installed()).unwrap_or(0);
+	Ok(if matches.is_empty() { None } else { Some(matches.swap_remove(idx)) })
+}
+
+/// Check if the package named exactly `name` is installed according to dpkg
+pub fn dpkg_package_installed<T: ToString>(name: &T) -> Result<bool, i32> {
+	dpkg_package_installed_in(&"/", name)
+}
+
+/// Check if the package named exactly `name` is installed according to dpkg under the root directory `root`
+pub fn dpkg_package_installed_in<A: ToString, B: ToString>(root: &A, name: &B) -> Result<bool, i32> {
+	Ok(dpkg_package_in(root, name)?.is_some_and(|p| p.is_installed()))
+}
+
+/// Find the package that installed the file at `path`, from the file lists in /var/lib/dpkg/info
+pub fn dpkg_file_owner<T: ToString>(path: &T) -> Result<Option<String>, i32> {
+	dpkg_file_owner_in(&"/", path)
+}
+
+/// Find the package that installed the file at `path` under the root directory `root`
+///
+/// `path` is the path on the system, not including `root`. On merged /usr systems, `/bin/x` and `/usr/bin/x` are treated as the same file.
+pub fn dpkg_file_owner_in<A: ToString, B: ToString>(root: &A, path: &B) -> Result<Option<String>, i32> {
+	let path = path.to_string();
+	let alt = match path.strip_prefix("/usr") {
+		Some(p) => p.to_string(),
+		None => format!("/usr{}", path),
+	};
+
+	let dir = std::fs::read_dir(rooted_path(root, "/var/lib/dpkg/info")).map_err(io_errno)?;
+	for entry in dir.filter_map(|e| e.ok()) {
+		let file_name = entry.file_name().to_string_lossy().to_string();
+		let package = match file_name.strip_suffix(".list") {
+			Some(p) => p,
+			None => continue,
+		};
+
+		if let Ok(list) = std::fs::read_to_string(entry.path()) {
+			if list.lines().any(|l| l == path || l == alt) {
+				return Ok(Some(package.split(':').next().unwrap_or(package).to_string()));
+			}
+		}
+	}
+
+	Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+
+	const STATUS: &str = "\
+Package: openssh-server
+Status: install ok installed
+Priority: optional
+Architecture: amd64
+Version: 1:9.6p1-3ubuntu13
+Conffiles:
+ /etc/ssh/moduli 3a7b1e2a0bd8c4c7a8a6ab6c2e6b5e7e
+ /etc/ufw/applications.d/openssh-server 486b78d54b93cc9fdc950c1d52ff479e
+Description: secure shell (SSH) server, for secure access from remote machines
+ This is the portable version of OpenSSH.
+ .
+ It provides the sshd server.
+
+Package: libc6
+Status: install ok installed
+Architecture: amd64
+Multi-Arch: same
+Version: 2.39-0ubuntu8
+
+Package: libc6
+Status: deinstall ok config-files
+Architecture: i386
+Multi-Arch: same
+Version: 2.39-0ubuntu8
+
+Package: john
+Status: install reinstreq half-installed
+Architecture: amd64
+Version: 1.9.0-2
+
+Package: nmap
+Status: hold ok triggers-pending
+Architecture: amd64
+Version: 7.94+git20230807.3be01efb1+dfsg-3
+";
+
+	#[test]
+	fn stanzas_and_continuations() {
+		let stanzas = split_stanzas(&format!("\n\n{}\n\n", STATUS));
+		assert_eq!(stanzas.len(), 5);
+
+		let fields = parse_stanza(&stanzas[0]);
+		assert_eq!(fields["Conffiles"], "/etc/ssh/moduli 3a7b1e2a0bd8c4c7a8a6ab6c2e6b5e7e\n/etc/ufw/applications.d/openssh-server 486b78d54b93cc9fdc950c1d52ff479e");
+		assert_eq!(fields["Description"], "secure shell (SSH) server, for secure access from remote machines\nThis is the portable version of OpenSSH.\n.\nIt provides the sshd server.");
+		assert_eq!(fields["Version"], "1:9.6p1-3ubuntu13");
+
+		let package = DpkgPackage::parse_entry(&stanzas[0]).unwrap();
+		assert_eq!((package.want, package.state), (DpkgWant::Install, DpkgState::Installed));
+		assert!(package.is_installed() && !package.is_broken());
+
+		let rc = DpkgPackage::parse_entry(&stanzas[2]).unwrap();
+		assert_eq!((rc.want, rc.state), (DpkgWant::Deinstall, DpkgState::ConfigFiles));
+		assert!(rc.has_config_files_only() && !rc.is_installed());
+
+		let john = DpkgPackage::parse_entry(&stanzas[3]).unwrap();
+		assert!(john.reinstall_required && john.is_broken() && !john.is_installed());
+
+		let nmap = DpkgPackage::parse_entry(&stanzas[4]).unwrap();
+		assert_eq!(nmap.want, DpkgWant::Hold);
+		assert!(nmap.is_installed());
+
+		assert_eq!(DpkgPackage::parse_entry(&"Package: x\nStatus: install ok\n").err(), Some(-1));
+		assert_eq!(DpkgPackage::parse_entry(&"Package: x\nStatus: install ok sideways\n").err(), Some(-1));
+		assert_eq!(DpkgPackage::parse_entry(&"Status: install ok installed\n").err(), Some(-1));
+	}
+
+	#[test]
+	fn lookups_by_name_and_arch() {
+		let root = test_root("dpkg-lookups");
+		write_rooted(&root, "/var/lib/dpkg/status", STATUS);
+		write_rooted(&root, "/var/lib/dpkg/info/openssh-server.list", "/.\n/usr\n/usr/sbin\n/usr/sbin/sshd\n");
+		write_rooted(&root, "/var/lib/dpkg/info/libc6:amd64.list", "/usr/lib/x86_64-linux-gnu/libc.so.6\n");
+		write_rooted(&root, "/var/lib/dpkg/info/libc6:amd64.md5sums", "/sbin/sshd\n");
+		let root = root.display().to_string();
+
+		assert_eq!(list_dpkg_packages_in(&root).unwrap().len(), 5);
+
+		let libc = dpkg_package_in(&root, &"libc6").unwrap().unwrap();
+		assert_eq!(libc.architecture, "amd64");
+		assert_eq!(dpkg_package_in(&root, &"libc6:i386").unwrap().unwrap().state, DpkgState::ConfigFiles);
+		assert!(dpkg_package_installed_in(&root, &"libc6:amd64").unwrap());
+		assert!(!dpkg_package_installed_in(&root, &"libc6:i386").unwrap());
+		assert!(dpkg_package_in(&root, &"libc6:arm64").unwrap().is_none());
+		assert!(!dpkg_package_installed_in(&root, &"john").unwrap());
+		assert!(!dpkg_package_installed_in(&root, &"hydra").unwrap());
+
+		assert_eq!(dpkg_file_owner_in(&root, &"/usr/sbin/sshd").unwrap().as_deref(), Some("openssh-server"));
+		assert_eq!(dpkg_file_owner_in(&root, &"/sbin/sshd").unwrap().as_deref(), Some("openssh-server"));
+		assert_eq!(dpkg_file_owner_in(&root, &"/lib/x86_64-linux-gnu/libc.so.6").unwrap().as_deref(), Some("libc6"));
+		assert_eq!(dpkg_file_owner_in(&root, &"/usr/bin/nc").unwrap(), None);
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+}
@@ -5,7 +5,6 @@
 */
 
 use std::{
-    ptr::{null_mut, null}, 
     result::Result, 
     str::FromStr,
     mem::MaybeUninit,
@@ -20,6 +19,9 @@ use libc::{uid_t, gid_t, stat};
 #[cfg(target_os = "linux")]
 use super::{PasswdEntry, GroupEntry};
 
+#[cfg(target_os = "windows")]
+use std::ptr::{null_mut, null};
+
 #[cfg(target_os = "windows")]
 use winapi::um::{
     fileapi::CreateFileW, 
@@ -65,7 +67,7 @@ pub fn get_file_owner_uid<T: ToString>(f: &T) -> Result<uid_t, i32> {
 
     unsafe {
         let mut s = MaybeUninit::zeroed().assume_init();
-        if stat(filename.as_ptr() as *const i8, &mut s) == 0 {
+        if stat(filename.as_ptr(), &mut s) == 0 {
             Ok(s.st_uid)
         } else {
             Err(errno())
@@ -83,7 +85,7 @@ pub fn get_file_owner_gid<T: ToString>(f: &T) -> Result<gid_t, i32> {
 
     unsafe {
         let mut s = MaybeUninit::zeroed().assume_init();
-        if stat(filename.as_ptr() as *const i8, &mut s) == 0 {
+        if stat(filename.as_ptr(), &mut s) == 0 {
             Ok(s.st_gid)
         } else {
             Err(errno())
@@ -91,6 +93,24 @@ pub fn get_file_owner_gid<T: ToString>(f: &T) -> Result<gid_t, i32> {
     }
 }
 
+/// Gets the permission bits of the file, including the setuid, setgid and sticky bits
+#[cfg(target_os = "linux")]
+pub fn get_file_mode<T: ToString>(f: &T) -> Result<u32, i32> {
+    let filename = match CString::new(f.to_string()) {
+        Ok(s) => s,
+        _ => return Err(-1)
+    };
+
+    unsafe {
+        let mut s = MaybeUninit::zeroed().assume_init();
+        if stat(filename.as_ptr(), &mut s) == 0 {
+            Ok(s.st_mode & 0o7777)
+        } else {
+            Err(errno())
+        }
+    }
+}
+
 /// Get the group owner of the file
 #[cfg(target_os = "linux")]
 pub fn get_file_group<A: ToString, B: FromStr>(f: &A) -> Result<B, i32> {
@@ -0,0 +1,1042 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+//! # Firewalls
+//!
+//! Parsers for UFW's configuration and rules, `iptables-save` output and `nft list ruleset` output,
+//! with [`Firewall`] putting them together to answer whether a firewall is on and what it lets in.
+//!
+//! Checking a port walks the rules the way the kernel would for a new connection from some remote host,
+//! so rules that depend on the source address, the interface or anything else not known up front are treated as not matching.
+
+use std::{
+	collections::HashMap,
+	process::{Command, Stdio},
+	string::String,
+	vec::Vec,
+};
+
+use super::{io_errno, rooted_path};
+use super::network::Protocol;
+use super::service::service_enabled_in;
+
+/// Deepest chain of jumps followed before giving up, in case of loops
+const MAX_JUMP_DEPTH: usize = 32;
+
+/// What happens to packets nothing else matched
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Policy {
+	Accept,
+	Drop,
+	Reject,
+}
+
+impl Policy {
+	/// Parse a policy in any case, like `ACCEPT` from iptables or `drop` from nft
+	pub fn parse(s: &str) -> Option<Policy> {
+		match s.to_lowercase().as_str() {
+			"accept" | "allow" => Some(Policy::Accept),
+			"drop" | "deny" => Some(Policy::Drop),
+			"reject" => Some(Policy::Reject),
+			_ => None,
+		}
+	}
+}
+
+/// Check if `port` is in a port list like `22`, `80,443`, `6000:6007`, `60000-61000` or `any`
+fn port_in_spec(port: u16, spec: &str) -> bool {
+	spec.split(',')
+		.map(|p| p.trim())
+		.filter(|p| !p.is_empty())
+		.any(|p| {
+			if p == "any" {
+				return true;
+			}
+
+			match p.split_once([':', '-']) {
+				Some((low, high)) => match (low.parse::<u16>(), high.parse::<u16>()) {
+					(Ok(low), Ok(high)) => (low..=high).contains(&port),
+					_ => false,
+				},
+				None => p.parse() == Ok(port),
+			}
+		})
+}
+
+fn protocol_name(protocol: Protocol) -> &'static str {
+	match protocol {
+		Protocol::Tcp => "tcp",
+		Protocol::Udp => "udp",
+		Protocol::Raw => "raw",
+	}
+}
+
+/// Parse `KEY=value` lines as UFW's shell style configuration files have them, stripping quotes
+fn parse_vars(text: &str) -> HashMap<String, String> {
+	text.lines()
+		.map(|l| l.trim())
+		.filter(|l| !l.is_empty() && !l.starts_with('#'))
+		.filter_map(|l| l.split_once('='))
+		.map(|(k, v)| (k.trim().to_string(), v.trim().trim_matches(['"', '\'']).to_string()))
+		.collect()
+}
+
+/// One rule added with `ufw allow`, `ufw deny` and the like, from the `### tuple ###` comments in user.rules
+#[derive(Clone, Debug)]
+pub struct UfwRule {
+	/// The action, where `limit` counts as [`Policy::Accept`]
+	pub action: Policy,
+	/// Set for `ufw limit` rules
+	pub limit: bool,
+	/// `tcp`, `udp` or `any`
+	pub protocol: String,
+	/// The destination port list, or `any`
+	pub dport: String,
+	pub dst: String,
+	pub sport: String,
+	pub src: String,
+	/// The application profile, like `OpenSSH`, if the rule was added by name
+	pub app: Option<String>,
+	/// Set for rules of incoming traffic, cleared for outgoing
+	pub incoming: bool,
+	/// The interface the rule is limited to, if any
+	pub interface: Option<String>,
+	/// Set for `ufw route` rules, which filter forwarded traffic
+	pub route: bool,
+	/// Set if the rule came from user6.rules
+	pub ipv6: bool,
+}
+
+impl UfwRule {
+	/// Parse a `### tuple ### allow tcp 22 0.0.0.0/0 any 0.0.0.0/0 in` line
+	///
+	/// Returns `Err(-1)` if the line isn't a valid tuple.
+	pub fn parse_tuple(line: &str, ipv6: bool) -> Result<UfwRule, i32> {
+		let fields: Vec<&str> = line.strip_prefix("### tuple ###").ok_or(-1)?
+			.split_whitespace()
+			.filter(|f| !f.starts_with("comment="))
+			.collect();
+
+		let (app, direction) = match fields.len() {
+			7 => (None, fields[6]),
+			9 => (Some(fields[6]).filter(|a| *a != "-").map(|a| a.replace("%20", " ")), fields[8]),
+			_ => return Err(-1),
+		};
+
+		let (route, action) = match fields[0].strip_prefix("route:") {
+			Some(a) => (true, a),
+			None => (false, fields[0]),
+		};
+		// Logging rules have the log level after the action, like `allow_log-all`
+		let action = action.split('_').next().unwrap_or(action);
+		let limit = action == "limit";
+
+		let (incoming, interface) = match direction.split_once('_') {
+			Some((d, i)) => (d == "in", Some(i.split('!').next().unwrap_or(i).to_string())),
+			None => (direction == "in", None),
+		};
+
+		Ok(UfwRule {
+			action: if limit { Policy::Accept } else { Policy::parse(action).ok_or(-1)? },
+			limit,
+			protocol: fields[1].to_string(),
+			dport: fields[2].to_string(),
+			dst: fields[3].to_string(),
+			sport: fields[4].to_string(),
+			src: fields[5].to_string(),
+			app,
+			incoming,
+			interface,
+			route,
+			ipv6,
+		})
+	}
+
+	/// Parse every rule in a user.rules or user6.rules file
+	pub fn parse_rules<T: ToString>(text: &T, ipv6: bool) -> Vec<UfwRule> {
+		text.to_string().lines()
+			.filter_map(|l| UfwRule::parse_tuple(l.trim(), ipv6).ok())
+			.collect()
+	}
+
+	/// Check if the rule applies to a new incoming connection to `port` over `protocol` from anywhere
+	pub fn matches(&self, port: u16, protocol: Protocol) -> bool {
+		let any_address = |a: &str| a == "0.0.0.0/0" || a == "::/0" || a == "any";
+
+		self.incoming && !self.route && self.interface.is_none()
+			&& (self.protocol == "any" || self.protocol == protocol_name(protocol))
+			&& port_in_spec(port, &self.dport)
+			&& self.sport == "any"
+			&& any_address(&self.src)
+			&& any_address(&self.dst)
+	}
+}
+
+/// UFW's configuration and rules
+#[derive(Clone, Debug)]
+pub struct Ufw {
+	/// `ENABLED` in /etc/ufw/ufw.conf
+	pub enabled: bool,
+	pub log_level: String,
+	/// `IPV6` in /etc/default/ufw
+	pub ipv6: bool,
+	pub default_input: Policy,
+	pub default_output: Policy,
+	pub default_forward: Policy,
+	/// The rules from user.rules followed by those from user6.rules
+	pub rules: Vec<UfwRule>,
+}
+
+impl Ufw {
+	/// Read UFW's configuration and rules
+	pub fn load() -> Result<Ufw, i32> {
+		Ufw::load_in(&"/")
+	}
+
+	/// Read UFW's configuration and rules under the root directory `root`
+	///
+	/// Returns `Err(ENOENT)` if /etc/ufw/ufw.conf doesn't exist, meaning UFW isn't installed.
+	/// If /etc/default/ufw is missing, UFW's own defaults are used.
+	pub fn load_in<T: ToString>(root: &T) -> Result<Ufw, i32> {
+		let conf = parse_vars(&std::fs::read_to_string(rooted_path(root, "/etc/ufw/ufw.conf")).map_err(io_errno)?);
+		let defaults = parse_vars(&std::fs::read_to_string(rooted_path(root, "/etc/default/ufw")).unwrap_or_default());
+		let policy = |key: &str, default: Policy| defaults.get(key).and_then(|p| Policy::parse(p)).unwrap_or(default);
+
+		let mut rules = Vec::new();
+		for (file, ipv6) in [("/etc/ufw/user.rules", false), ("/etc/ufw/user6.rules", true)] {
+			if let Ok(text) = std::fs::read_to_string(rooted_path(root, file)) {
+				rules.extend(UfwRule::parse_rules(&text, ipv6));
+			}
+		}
+
+		Ok(Ufw {
+			enabled: conf.get("ENABLED").is_some_and(|e| e.eq_ignore_ascii_case("yes")),
+			log_level: conf.get("LOGLEVEL").cloned().unwrap_or_default(),
+			ipv6: defaults.get("IPV6").is_none_or(|v| v.eq_ignore_ascii_case("yes")),
+			default_input: policy("DEFAULT_INPUT_POLICY", Policy::Drop),
+			default_output: policy("DEFAULT_OUTPUT_POLICY", Policy::Accept),
+			default_forward: policy("DEFAULT_FORWARD_POLICY", Policy::Drop),
+			rules,
+		})
+	}
+
+	/// Check if a new incoming connection to `port` over `protocol` from anywhere gets through
+	///
+	/// The first matching rule decides, falling back to the default incoming policy. A disabled UFW lets everything through.
+	/// The fixed rules in before.rules, like the one for DHCP, aren't taken into account.
+	pub fn port_allowed(&self, port: u16, protocol: Protocol) -> bool {
+		if !self.enabled {
+			return true;
+		}
+
+		match self.rules.iter().find(|r| r.matches(port, protocol)) {
+			Some(rule) => rule.action == Policy::Accept,
+			None => self.default_input == Policy::Accept,
+		}
+	}
+}
+
+/// Split a line into words, keeping double quoted strings together without the quotes
+fn split_words(line: &str) -> Vec<String> {
+	let mut words = Vec::new();
+	let mut word = String::new();
+	let mut quoted = false;
+	let mut in_word = false;
+
+	for c in line.chars() {
+		match c {
+			'"' => {
+				quoted = !quoted;
+				in_word = true;
+			},
+			c if c.is_whitespace() && !quoted => {
+				if in_word {
+					words.push(std::mem::take(&mut word));
+					in_word = false;
+				}
+			},
+			c => {
+				word.push(c);
+				in_word = true;
+			},
+		}
+	}
+	if in_word {
+		words.push(word);
+	}
+
+	words
+}
+
+/// A chain declaration from `iptables-save`
+#[derive(Clone, Debug)]
+pub struct IptablesChain {
+	pub table: String,
+	pub name: String,
+	/// The policy of a built in chain, [`None`] for user defined chains
+	pub policy: Option<Policy>,
+}
+
+/// A rule from `iptables-save`
+#[derive(Clone, Debug)]
+pub struct IptablesRule {
+	pub table: String,
+	pub chain: String,
+	/// Everything after `-A <chain>`, split into words
+	pub args: Vec<String>,
+}
+
+impl IptablesRule {
+	/// Get the values of the first option called `name`, like `--dport`
+	pub fn option(&self, name: &str) -> Option<Vec<&str>> {
+		let start = self.args.iter().position(|a| a == name)? + 1;
+		Some(self.args[start..].iter().take_while(|a| !a.starts_with('-')).map(|a| a.as_str()).collect())
+	}
+
+	/// The chain or built in target the rule jumps to, if any
+	pub fn target(&self) -> Option<&str> {
+		["-j", "--jump", "-g", "--goto"].iter().find_map(|o| self.option(o)?.first().copied())
+	}
+
+	/// Check if the rule's conditions hold for a new connection to `port` over `protocol` from a remote host
+	fn matches(&self, port: u16, protocol: Protocol) -> bool {
+		if self.args.iter().any(|a| a == "!") {
+			return false;
+		}
+
+		let mut i = 0;
+		while i < self.args.len() {
+			let option = self.args[i].as_str();
+			let values: Vec<&str> = self.args[i + 1..].iter().take_while(|a| !a.starts_with('-')).map(|a| a.as_str()).collect();
+			i += 1 + values.len();
+			let first = values.first().copied().unwrap_or("");
+
+			let holds = match option {
+				"-p" | "--protocol" => first == "all" || first == protocol_name(protocol),
+				"--dport" | "--dports" | "--destination-port" | "--destination-ports" => port_in_spec(port, first),
+				"-s" | "--source" | "-d" | "--destination" => first == "0.0.0.0/0" || first == "::/0",
+				"--ctstate" | "--state" => first.split(',').any(|s| s == "NEW"),
+				"--dst-type" => first == "LOCAL",
+				"--update" | "--rcheck" => false,
+				"-m" | "--match" | "-j" | "--jump" | "-g" | "--goto" | "--comment" | "--limit" | "--limit-burst"
+				| "--log-prefix" | "--log-level" | "--reject-with" | "--syn" | "--set" | "--name" | "--mask"
+				| "--rsource" | "--seconds" | "--hitcount" => true,
+				_ => false,
+			};
+
+			if !holds {
+				return false;
+			}
+		}
+
+		true
+	}
+}
+
+/// The rules from `iptables-save` or `ip6tables-save`, or a file they were saved to like /etc/iptables/rules.v4
+#[derive(Clone, Debug, Default)]
+pub struct IptablesRuleset {
+	pub chains: Vec<IptablesChain>,
+	pub rules: Vec<IptablesRule>,
+}
+
+impl IptablesRuleset {
+	/// Parse `iptables-save` output
+	pub fn parse<T: ToString>(text: &T) -> IptablesRuleset {
+		let mut ruleset = IptablesRuleset::default();
+		let mut table = String::new();
+
+		for line in text.to_string().lines().map(|l| l.trim()) {
+			if let Some(name) = line.strip_prefix('*') {
+				table = name.to_string();
+			} else if let Some(chain) = line.strip_prefix(':') {
+				let fields: Vec<&str> = chain.split_whitespace().collect();
+				if let Some(name) = fields.first() {
+					ruleset.chains.push(IptablesChain {
+						table: table.clone(),
+						name: name.to_string(),
+						policy: fields.get(1).and_then(|p| Policy::parse(p)),
+					});
+				}
+			} else if line.starts_with("-A ") || line.starts_with("--append ") {
+				let words = split_words(line);
+				if let Some(chain) = words.get(1) {
+					ruleset.rules.push(IptablesRule { table: table.clone(), chain: chain.clone(), args: words[2..].to_vec() });
+				}
+			}
+		}
+
+		ruleset
+	}
+
+	/// Run `iptables-save`, returning `Err(-1)` if it fails
+	pub fn load() -> Result<IptablesRuleset, i32> {
+		IptablesRuleset::run("iptables-save")
+	}
+
+	/// Run `ip6tables-save`, returning `Err(-1)` if it fails
+	pub fn load6() -> Result<IptablesRuleset, i32> {
+		IptablesRuleset::run("ip6tables-save")
+	}
+
+	fn run(command: &str) -> Result<IptablesRuleset, i32> {
+		let output = Command::new(command).stderr(Stdio::null()).stdout(Stdio::piped()).output().map_err(io_errno)?;
+		if !output.status.success() {
+			return Err(-1);
+		}
+		Ok(IptablesRuleset::parse(&String::from_utf8_lossy(&output.stdout)))
+	}
+
+	/// Get the policy of the built in chain `chain` in `table`
+	pub fn policy(&self, table: &str, chain: &str) -> Option<Policy> {
+		self.chains.iter().find(|c| c.table == table && c.name == chain).and_then(|c| c.policy)
+	}
+
+	/// List the rules of `chain` in `table`, in order
+	pub fn chain_rules<'a>(&'a self, table: &'a str, chain: &'a str) -> impl Iterator<Item = &'a IptablesRule> {
+		self.rules.iter().filter(move |r| r.table == table && r.chain == chain)
+	}
+
+	/// Check if anything filters incoming traffic, meaning INPUT has rules or doesn't accept by default
+	pub fn is_active(&self) -> bool {
+		self.policy("filter", "INPUT").is_some_and(|p| p != Policy::Accept) || self.chain_rules("filter", "INPUT").next().is_some()
+	}
+
+	/// Walk `chain`, returning the verdict, or [`None`] if it returns or runs out of rules
+	fn walk(&self, chain: &str, port: u16, protocol: Protocol, depth: usize) -> Option<bool> {
+		if depth > MAX_JUMP_DEPTH {
+			return None;
+		}
+
+		for rule in self.chain_rules("filter", chain).filter(|r| r.matches(port, protocol)) {
+			match rule.target() {
+				Some("ACCEPT") => return Some(true),
+				Some("DROP") | Some("REJECT") => return Some(false),
+				Some("RETURN") => return None,
+				Some(target) if self.chains.iter().any(|c| c.table == "filter" && c.name == target) => {
+					if let Some(verdict) = self.walk(target, port, protocol, depth + 1) {
+						return Some(verdict);
+					}
+				},
+				_ => (),
+			}
+		}
+
+		None
+	}
+
+	/// Check if a new incoming connection to `port` over `protocol` from a remote host gets through the filter table's INPUT chain
+	pub fn port_allowed(&self, port: u16, protocol: Protocol) -> bool {
+		self.walk("INPUT", port, protocol, 0)
+			.unwrap_or(self.policy("filter", "INPUT").is_none_or(|p| p == Policy::Accept))
+	}
+}
+
+/// A chain from an nftables ruleset
+#[derive(Clone, Debug)]
+pub struct NftChain {
+	/// The table's family, like `inet` or `ip`
+	pub family: String,
+	pub table: String,
+	pub name: String,
+	/// The hook of a base chain, like `input`, [`None`] for regular chains
+	pub hook: Option<String>,
+	pub policy: Option<Policy>,
+	/// Each rule as written, without `# handle` comments
+	pub rules: Vec<String>,
+}
+
+/// What an nftables rule does with a packet it matches
+enum NftVerdict {
+	Accept,
+	Drop,
+	Jump(String),
+	Return,
+	Continue,
+}
+
+impl NftChain {
+	/// Check if the chain filters incoming traffic
+	pub fn is_input(&self) -> bool {
+		self.hook.as_deref() == Some("input") && ["inet", "ip", "ip6"].contains(&self.family.as_str())
+	}
+
+	/// Work out what a rule does with a new connection to `port` over `protocol` from a remote host
+	fn rule_verdict(rule: &str, port: u16, protocol: Protocol) -> NftVerdict {
+		// Sets like `{ 22, 80 }` become one word, so they can be checked like any other value
+		let mut words: Vec<String> = Vec::new();
+		let mut set: Option<String> = None;
+		for word in split_words(&rule.replace('{', " { ").replace('}', " } ")) {
+			match (word.as_str(), set.as_mut()) {
+				("{", _) => set = Some(String::new()),
+				("}", Some(_)) => words.extend(set.take()),
+				(w, Some(s)) => s.push_str(w),
+				(_, None) => words.push(word),
+			}
+		}
+
+		let name = protocol_name(protocol);
+		let mut i = 0;
+		while i < words.len() {
+			let word = words[i].as_str();
+			let next = |n: usize| words.get(i + n).map(|w| w.as_str()).unwrap_or("");
+
+			let (holds, used) = match word {
+				"accept" => return NftVerdict::Accept,
+				"drop" | "reject" => return NftVerdict::Drop,
+				"return" => return NftVerdict::Return,
+				"jump" | "goto" => return NftVerdict::Jump(next(1).to_string()),
+				"counter" => (true, if next(1) == "packets" { 5 } else { 1 }),
+				"tcp" | "udp" if next(1) == "dport" => (word == name && port_in_spec(port, next(2)), 3),
+				"th" if next(1) == "dport" => (port_in_spec(port, next(2)), 3),
+				"meta" if next(1) == "l4proto" => (next(2).split(',').any(|p| p == name), 3),
+				"ip" if next(1) == "protocol" => (next(2).split(',').any(|p| p == name), 3),
+				"ip6" if next(1) == "nexthdr" => (next(2).split(',').any(|p| p == name), 3),
+				"ct" if next(1) == "state" => (next(2).split(',').any(|s| s == "new"), 3),
+				"fib" if next(1) == "daddr" && next(2) == "type" => (next(3) == "local", 4),
+				_ => (false, 1),
+			};
+
+			if !holds {
+				return NftVerdict::Continue;
+			}
+			i += used;
+		}
+
+		NftVerdict::Continue
+	}
+}
+
+/// An nftables ruleset, from `nft list ruleset` or a file like /etc/nftables.conf
+///
+/// Only filtering chains are kept track of. Named sets, maps and `define` variables aren't expanded.
+#[derive(Clone, Debug, Default)]
+pub struct NftRuleset {
+	pub chains: Vec<NftChain>,
+}
+
+impl NftRuleset {
+	/// Parse `nft list ruleset` output, or an nftables.conf file in the same nested format
+	pub fn parse<T: ToString>(text: &T) -> NftRuleset {
+		let mut ruleset = NftRuleset::default();
+		let mut table: Option<(String, String)> = None;
+		let mut in_chain = false;
+		let mut depth = 0usize;
+
+		for line in text.to_string().lines() {
+			let line = match line.find('#') {
+				Some(i) => line[..i].trim(),
+				None => line.trim(),
+			};
+			if line.is_empty() {
+				continue;
+			}
+
+			let opens = line.matches('{').count();
+			let closes = line.matches('}').count();
+			let words = split_words(line.trim_end_matches('{'));
+
+			if opens > closes {
+				match (depth, words.first().map(|w| w.as_str())) {
+					(0, Some("table")) => {
+						table = match words.len() {
+							3 => Some((words[1].clone(), words[2].clone())),
+							2 => Some(("ip".to_string(), words[1].clone())),
+							_ => None,
+						};
+					},
+					(1, Some("chain")) if table.is_some() && words.len() >= 2 => {
+						let (family, table) = table.clone().unwrap_or_default();
+						ruleset.chains.push(NftChain { family, table, name: words[1].clone(), hook: None, policy: None, rules: Vec::new() });
+						in_chain = true;
+					},
+					_ => (),
+				}
+			} else if depth == 2 && in_chain && opens == closes {
+				if let Some(chain) = ruleset.chains.last_mut() {
+					for statement in line.split(';').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+						let words = split_words(statement);
+						match words.first().map(|w| w.as_str()) {
+							Some("type") => chain.hook = words.iter().position(|w| w == "hook").and_then(|i| words.get(i + 1)).cloned(),
+							Some("policy") => chain.policy = words.get(1).and_then(|p| Policy::parse(p)),
+							Some("comment") => (),
+							_ => chain.rules.push(statement.to_string()),
+						}
+					}
+				}
+			}
+
+			depth = (depth + opens).saturating_sub(closes);
+			if depth < 2 {
+				in_chain = false;
+			}
+			if depth == 0 {
+				table = None;
+			}
+		}
+
+		ruleset
+	}
+
+	/// Run `nft list ruleset`, returning `Err(-1)` if it fails
+	pub fn load() -> Result<NftRuleset, i32> {
+		let output = Command::new("nft").args(["list", "ruleset"]).stderr(Stdio::null()).stdout(Stdio::piped()).output().map_err(io_errno)?;
+		if !output.status.success() {
+			return Err(-1);
+		}
+		Ok(NftRuleset::parse(&String::from_utf8_lossy(&output.stdout)))
+	}
+
+	/// List the base chains filtering incoming traffic
+	pub fn input_chains(&self) -> impl Iterator<Item = &NftChain> {
+		self.chains.iter().filter(|c| c.is_input())
+	}
+
+	/// Check if anything filters incoming traffic, meaning an input chain has rules or doesn't accept by default
+	pub fn is_active(&self) -> bool {
+		self.input_chains().any(|c| !c.rules.is_empty() || c.policy.is_some_and(|p| p != Policy::Accept))
+	}
+
+	/// Walk `chain`, returning the verdict, or [`None`] if it returns or runs out of rules
+	fn walk(&self, chain: &NftChain, port: u16, protocol: Protocol, depth: usize) -> Option<bool> {
+		if depth > MAX_JUMP_DEPTH {
+			return None;
+		}
+
+		for rule in &chain.rules {
+			match NftChain::rule_verdict(rule, port, protocol) {
+				NftVerdict::Accept => return Some(true),
+				NftVerdict::Drop => return Some(false),
+				NftVerdict::Return => return None,
+				NftVerdict::Jump(target) => {
+					let target = self.chains.iter().find(|c| c.family == chain.family && c.table == chain.table && c.name == target);
+					if let Some(verdict) = target.and_then(|t| self.walk(t, port, protocol, depth + 1)) {
+						return Some(verdict);
+					}
+				},
+				NftVerdict::Continue => (),
+			}
+		}
+
+		None
+	}
+
+	/// Check if a new incoming connection to `port` over `protocol` from a remote host gets through every input chain
+	pub fn port_allowed(&self, port: u16, protocol: Protocol) -> bool {
+		self.input_chains().all(|c| {
+			self.walk(c, port, protocol, 0).unwrap_or(c.policy.is_none_or(|p| p == Policy::Accept))
+		})
+	}
+}
+
+/// Everything that can filter traffic on the system
+#[derive(Clone, Debug, Default)]
+pub struct Firewall {
+	pub ufw: Option<Ufw>,
+	pub iptables: Option<IptablesRuleset>,
+	pub ip6tables: Option<IptablesRuleset>,
+	pub nftables: Option<NftRuleset>,
+}
+
+impl Firewall {
+	/// Read UFW's files and the rules loaded in the kernel, through `iptables-save`, `ip6tables-save` and `nft list ruleset`
+	///
+	/// Anything that isn't installed or can't be read, which includes the kernel's rules when not root, is left as [`None`].
+	pub fn load() -> Firewall {
+		Firewall {
+			ufw: Ufw::load().ok(),
+			iptables: IptablesRuleset::load().ok(),
+			ip6tables: IptablesRuleset::load6().ok(),
+			nftables: NftRuleset::load().ok(),
+		}
+	}
+
+	/// Read the firewall configuration under the root directory `root`, as it would be loaded at boot
+	///
+	/// Besides UFW, this reads the rules iptables-persistent or the iptables service restore,
+	/// and the nftables service's configuration, but only if their service is enabled.
+	pub fn load_in<T: ToString>(root: &T) -> Firewall {
+		let read = |service: &str, files: &[&str]| -> Option<String> {
+			if !service_enabled_in(root, &service) {
+				return None;
+			}
+			files.iter().find_map(|f| std::fs::read_to_string(rooted_path(root, f)).ok())
+		};
+		let iptables_service = if service_enabled_in(root, &"netfilter-persistent") { "netfilter-persistent" } else { "iptables" };
+		let ip6tables_service = if service_enabled_in(root, &"netfilter-persistent") { "netfilter-persistent" } else { "ip6tables" };
+
+		Firewall {
+			ufw: Ufw::load_in(root).ok(),
+			iptables: read(iptables_service, &["/etc/iptables/rules.v4", "/etc/sysconfig/iptables"]).map(|t| IptablesRuleset::parse(&t)),
+			ip6tables: read(ip6tables_service, &["/etc/iptables/rules.v6", "/etc/sysconfig/ip6tables"]).map(|t| IptablesRuleset::parse(&t)),
+			nftables: read("nftables", &["/etc/nftables.conf", "/etc/sysconfig/nftables.conf"]).map(|t| NftRuleset::parse(&t)),
+		}
+	}
+
+	/// Check if any firewall filters incoming traffic
+	pub fn is_enabled(&self) -> bool {
+		self.ufw.as_ref().is_some_and(|u| u.enabled)
+			|| self.iptables.as_ref().is_some_and(|i| i.is_active())
+			|| self.ip6tables.as_ref().is_some_and(|i| i.is_active())
+			|| self.nftables.as_ref().is_some_and(|n| n.is_active())
+	}
+
+	/// Get the default policy for incoming traffic
+	///
+	/// An enabled UFW's policy comes first, then that of the first nftables input chain, then that of iptables' INPUT chain.
+	/// Returns [`None`] if no firewall is enabled.
+	pub fn default_input_policy(&self) -> Option<Policy> {
+		if let Some(ufw) = self.ufw.as_ref().filter(|u| u.enabled) {
+			return Some(ufw.default_input);
+		}
+		if let Some(policy) = self.nftables.as_ref().filter(|n| n.is_active()).and_then(|n| n.input_chains().next()).map(|c| c.policy.unwrap_or(Policy::Accept)) {
+			return Some(policy);
+		}
+		self.iptables.as_ref().filter(|i| i.is_active()).and_then(|i| i.policy("filter", "INPUT"))
+	}
+
+	/// Check if a new incoming connection to `port` over `protocol` from a remote host gets through
+	///
+	/// Active iptables, ip6tables and nftables rules are walked when there are any, since UFW's rules end up there once loaded,
+	/// and the connection has to get through all of them, so a port only open over one of IPv4 and IPv6 doesn't count.
+	/// Otherwise an enabled UFW's rules decide. With no firewall enabled, everything gets through.
+	pub fn port_allowed(&self, port: u16, protocol: Protocol) -> bool {
+		let iptables: Vec<&IptablesRuleset> = [&self.iptables, &self.ip6tables].into_iter().flatten().filter(|i| i.is_active()).collect();
+		let nftables = self.nftables.as_ref().filter(|n| n.is_active());
+
+		if !iptables.is_empty() || nftables.is_some() {
+			return iptables.iter().all(|i| i.port_allowed(port, protocol)) && nftables.is_none_or(|n| n.port_allowed(port, protocol));
+		}
+
+		self.ufw.as_ref().is_none_or(|u| u.port_allowed(port, protocol))
+	}
+}
+
+/// Check if any firewall filters incoming traffic
+pub fn firewall_enabled() -> bool {
+	Firewall::load().is_enabled()
+}
+
+/// Check if any firewall under the root directory `root` filters incoming traffic at boot
+pub fn firewall_enabled_in<T: ToString>(root: &T) -> bool {
+	Firewall::load_in(root).is_enabled()
+}
+
+/// Check if the firewall lets in new connections to `port` over `protocol`
+pub fn firewall_allows_port(port: u16, protocol: Protocol) -> bool {
+	Firewall::load().port_allowed(port, protocol)
+}
+
+/// Check if the firewall under the root directory `root` lets in new connections to `port` over `protocol`
+pub fn firewall_allows_port_in<T: ToString>(root: &T, port: u16, protocol: Protocol) -> bool {
+	Firewall::load_in(root).port_allowed(port, protocol)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+
+	const USER_RULES: &str = "\
+*filter
+:ufw-user-input - [0:0]
+:ufw-user-output - [0:0]
+:ufw-user-forward - [0:0]
+
+### RULES ###
+
+### tuple ### limit tcp 22 0.0.0.0/0 any 0.0.0.0/0 in
+-A ufw-user-input -p tcp --dport 22 -m conntrack --ctstate NEW -m recent --set
+-A ufw-user-input -p tcp --dport 22 -m conntrack --ctstate NEW -m recent --update --seconds 30 --hitcount 6 -j ufw-user-limit
+-A ufw-user-input -p tcp --dport 22 -j ufw-user-limit-accept
+
+### tuple ### allow tcp 80,443 0.0.0.0/0 any 0.0.0.0/0 Apache%20Full - in
+-A ufw-user-input -p tcp -m multiport --dports 80,443 -j ACCEPT -m comment --comment 'dapp_Apache%20Full'
+
+### tuple ### deny_log-all tcp 23 0.0.0.0/0 any 0.0.0.0/0 in
+-A ufw-user-input -p tcp --dport 23 -j ufw-user-logging-input
+-A ufw-user-input -p tcp --dport 23 -j DROP
+
+### tuple ### allow tcp 3306 0.0.0.0/0 any 10.0.0.0/8 in comment=6d7973716c
+-A ufw-user-input -p tcp --dport 3306 -s 10.0.0.0/8 -j ACCEPT
+
+### tuple ### allow udp 5353 0.0.0.0/0 any 0.0.0.0/0 in_wlan0!out_eth0
+### tuple ### route:allow tcp 8080 0.0.0.0/0 any 0.0.0.0/0 in
+### tuple ### deny any any 0.0.0.0/0 any 0.0.0.0/0 out
+### tuple ### allow any 53 0.0.0.0/0 any 0.0.0.0/0 in
+
+### END RULES ###
+COMMIT
+";
+
+	const USER6_RULES: &str = "\
+*filter
+### tuple ### limit tcp 22 ::/0 any ::/0 in
+### tuple ### reject tcp 631 ::/0 any ::/0 in
+COMMIT
+";
+
+	#[test]
+	fn ufw_tuples() {
+		let rules = UfwRule::parse_rules(&USER_RULES, false);
+		assert_eq!(rules.len(), 8);
+
+		let ssh = &rules[0];
+		assert!(ssh.limit && ssh.incoming && !ssh.route && !ssh.ipv6);
+		assert_eq!((ssh.action, ssh.protocol.as_str(), ssh.dport.as_str(), ssh.app.as_deref()), (Policy::Accept, "tcp", "22", None));
+
+		let apache = &rules[1];
+		assert_eq!(apache.app.as_deref(), Some("Apache Full"));
+		assert!(apache.matches(443, Protocol::Tcp) && !apache.matches(443, Protocol::Udp));
+
+		let telnet = &rules[2];
+		assert!(!telnet.limit);
+		assert_eq!(telnet.action, Policy::Drop);
+
+		let mysql = &rules[3];
+		assert_eq!(mysql.src, "10.0.0.0/8");
+		assert!(!mysql.matches(3306, Protocol::Tcp));
+
+		let mdns = &rules[4];
+		assert_eq!(mdns.interface.as_deref(), Some("wlan0"));
+		assert!(!mdns.matches(5353, Protocol::Udp));
+
+		assert!(rules[5].route && !rules[5].matches(8080, Protocol::Tcp));
+		assert!(!rules[6].incoming && rules[6].action == Policy::Drop);
+		assert!(rules[7].matches(53, Protocol::Udp) && rules[7].matches(53, Protocol::Tcp));
+
+		let v6 = UfwRule::parse_rules(&USER6_RULES, true);
+		assert_eq!(v6.len(), 2);
+		assert!(v6.iter().all(|r| r.ipv6));
+		assert_eq!(v6[1].action, Policy::Reject);
+
+		for bad in [
+			"### tuple ### allow tcp 22 0.0.0.0/0 any in",
+			"### tuple ### permit tcp 22 0.0.0.0/0 any 0.0.0.0/0 in",
+			"-A ufw-user-input -p tcp --dport 22 -j ACCEPT",
+			"### tuple ###",
+		] {
+			assert!(UfwRule::parse_tuple(bad, false).is_err(), "{}", bad);
+		}
+	}
+
+	#[test]
+	fn ufw_config() {
+		let root = test_root("firewall-ufw");
+		let root_s = root.display();
+		assert_eq!(Ufw::load_in(&root_s).map(|u| u.enabled), Err(libc::ENOENT));
+
+		write_rooted(&root, "/etc/ufw/ufw.conf", "# /etc/ufw/ufw.conf\n\nENABLED=yes\nLOGLEVEL=low\n");
+		let defaults = Ufw::load_in(&root_s).unwrap();
+		assert!(defaults.enabled && defaults.ipv6 && defaults.rules.is_empty());
+		assert_eq!((defaults.default_input, defaults.default_output, defaults.default_forward), (Policy::Drop, Policy::Accept, Policy::Drop));
+		assert!(!defaults.port_allowed(22, Protocol::Tcp));
+
+		write_rooted(&root, "/etc/default/ufw", "\
+# /etc/default/ufw
+IPV6=yes
+DEFAULT_INPUT_POLICY=\"REJECT\"
+DEFAULT_OUTPUT_POLICY=\"ACCEPT\"
+DEFAULT_FORWARD_POLICY='DROP'
+DEFAULT_APPLICATION_POLICY=\"SKIP\"
+");
+		write_rooted(&root, "/etc/ufw/user.rules", USER_RULES);
+		write_rooted(&root, "/etc/ufw/user6.rules", USER6_RULES);
+		let ufw = Ufw::load_in(&root_s).unwrap();
+		assert_eq!(ufw.log_level, "low");
+		assert_eq!(ufw.default_input, Policy::Reject);
+		assert_eq!(ufw.rules.len(), 10);
+		assert_eq!(ufw.rules.iter().filter(|r| r.ipv6).count(), 2);
+
+		assert!(ufw.port_allowed(22, Protocol::Tcp));
+		assert!(ufw.port_allowed(80, Protocol::Tcp));
+		assert!(ufw.port_allowed(53, Protocol::Udp));
+		for (port, protocol) in [(23, Protocol::Tcp), (3306, Protocol::Tcp), (5353, Protocol::Udp), (8080, Protocol::Tcp), (631, Protocol::Tcp)] {
+			assert!(!ufw.port_allowed(port, protocol), "{}", port);
+		}
+
+		write_rooted(&root, "/etc/ufw/ufw.conf", "ENABLED=no\n");
+		let disabled = Ufw::load_in(&root_s).unwrap();
+		assert!(!disabled.enabled && disabled.port_allowed(23, Protocol::Tcp));
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+
+	// Trimmed down from `iptables-save` with UFW enabled and `ufw limit ssh`, `ufw allow 80,443/tcp`, `ufw deny 23/tcp` and `ufw allow 53/udp`
+	const UFW_IPTABLES: &str = "\
+# Generated by iptables-save v1.8.7 on Sat Oct 17 12:00:00 2026
+*filter
+:INPUT DROP [0:0]
+:FORWARD DROP [0:0]
+:OUTPUT ACCEPT [0:0]
+:ufw-after-input - [0:0]
+:ufw-before-input - [0:0]
+:ufw-not-local - [0:0]
+:ufw-skip-to-policy-input - [0:0]
+:ufw-user-input - [0:0]
+:ufw-user-limit - [0:0]
+:ufw-user-limit-accept - [0:0]
+-A INPUT -j ufw-before-input
+-A INPUT -j ufw-after-input
+-A ufw-after-input -p udp -m udp --dport 137 -j ufw-skip-to-policy-input
+-A ufw-after-input -p tcp -m tcp --dport 445 -j ufw-skip-to-policy-input
+-A ufw-before-input -i lo -j ACCEPT
+-A ufw-before-input -m conntrack --ctstate RELATED,ESTABLISHED -j ACCEPT
+-A ufw-before-input -m conntrack --ctstate INVALID -j DROP
+-A ufw-before-input -p icmp -m icmp --icmp-type 8 -j ACCEPT
+-A ufw-before-input -p udp -m udp --sport 67 --dport 68 -j ACCEPT
+-A ufw-before-input -j ufw-not-local
+-A ufw-before-input -d 224.0.0.251/32 -p udp -m udp --dport 5353 -j ACCEPT
+-A ufw-before-input -j ufw-user-input
+-A ufw-not-local -m addrtype --dst-type LOCAL -j RETURN
+-A ufw-not-local -m addrtype --dst-type MULTICAST -j RETURN
+-A ufw-not-local -m limit --limit 3/min --limit-burst 10 -j LOG --log-prefix \"[UFW BLOCK] \"
+-A ufw-not-local -j DROP
+-A ufw-skip-to-policy-input -j DROP
+-A ufw-user-input -p tcp -m tcp --dport 22 -m conntrack --ctstate NEW -m recent --set --name DEFAULT --mask 255.255.255.255 --rsource
+-A ufw-user-input -p tcp -m tcp --dport 22 -m conntrack --ctstate NEW -m recent --update --seconds 30 --hitcount 6 --name DEFAULT --mask 255.255.255.255 --rsource -j ufw-user-limit
+-A ufw-user-input -p tcp -m tcp --dport 22 -j ufw-user-limit-accept
+-A ufw-user-input -p tcp -m multiport --dports 80,443 -j ACCEPT
+-A ufw-user-input -p tcp -m tcp --dport 23 -j DROP
+-A ufw-user-input -s 10.0.0.0/8 -p tcp -m tcp --dport 3306 -j ACCEPT
+-A ufw-user-input -p udp -m udp --dport 53 -j ACCEPT
+-A ufw-user-limit -m limit --limit 3/min -j LOG --log-prefix \"[UFW LIMIT BLOCK] \"
+-A ufw-user-limit -j REJECT --reject-with icmp-port-unreachable
+-A ufw-user-limit-accept -j ACCEPT
+COMMIT
+*nat
+:PREROUTING ACCEPT [0:0]
+:POSTROUTING ACCEPT [0:0]
+-A PREROUTING -p tcp -m tcp --dport 8080 -j ACCEPT
+COMMIT
+# Completed on Sat Oct 17 12:00:00 2026
+";
+
+	const UFW_IP6TABLES: &str = "\
+*filter
+:INPUT DROP [0:0]
+:FORWARD DROP [0:0]
+:OUTPUT ACCEPT [0:0]
+:ufw6-user-input - [0:0]
+-A INPUT -j ufw6-user-input
+-A ufw6-user-input -p tcp -m tcp --dport 22 -j ACCEPT
+COMMIT
+";
+
+	#[test]
+	fn iptables_from_ufw() {
+		let ruleset = IptablesRuleset::parse(&UFW_IPTABLES);
+		assert_eq!(ruleset.chains.len(), 12);
+		assert_eq!(ruleset.policy("filter", "INPUT"), Some(Policy::Drop));
+		assert_eq!(ruleset.policy("filter", "ufw-user-input"), None);
+		assert_eq!(ruleset.chain_rules("filter", "ufw-user-input").count(), 7);
+		assert!(ruleset.is_active());
+
+		let log = ruleset.chain_rules("filter", "ufw-user-limit").next().unwrap();
+		assert_eq!(log.target(), Some("LOG"));
+		assert_eq!(log.option("--log-prefix"), Some(vec!["[UFW LIMIT BLOCK] "]));
+
+		// The `--update` rule only fires for hosts over the limit, so new connections fall through to the accept
+		assert!(ruleset.port_allowed(22, Protocol::Tcp));
+		assert!(ruleset.port_allowed(80, Protocol::Tcp));
+		assert!(ruleset.port_allowed(443, Protocol::Tcp));
+		assert!(ruleset.port_allowed(53, Protocol::Udp));
+		for (port, protocol) in [
+			(53, Protocol::Tcp), (23, Protocol::Tcp), (3306, Protocol::Tcp), (137, Protocol::Udp),
+			(68, Protocol::Udp), (5353, Protocol::Udp), (8080, Protocol::Tcp),
+		] {
+			assert!(!ruleset.port_allowed(port, protocol), "{}", port);
+		}
+
+		let open = IptablesRuleset::parse(&"*filter\n:INPUT ACCEPT [0:0]\n:FORWARD ACCEPT [0:0]\n:OUTPUT ACCEPT [0:0]\nCOMMIT\n");
+		assert!(!open.is_active() && open.port_allowed(23, Protocol::Tcp));
+
+		let firewall = Firewall { iptables: Some(ruleset.clone()), ip6tables: Some(IptablesRuleset::parse(&UFW_IP6TABLES)), ..Default::default() };
+		assert!(firewall.is_enabled());
+		assert!(firewall.port_allowed(22, Protocol::Tcp));
+		assert!(!firewall.port_allowed(80, Protocol::Tcp));
+
+		let firewall = Firewall { iptables: Some(ruleset), ip6tables: Some(open), ..Default::default() };
+		assert!(firewall.port_allowed(80, Protocol::Tcp));
+		assert_eq!(firewall.default_input_policy(), Some(Policy::Drop));
+	}
+
+	const NFTABLES: &str = "\
+#!/usr/sbin/nft -f
+
+flush ruleset
+
+table inet filter {
+	chain input {
+		type filter hook input priority filter; policy drop;
+		ct state established,related accept # handle 4
+		ct state invalid drop
+		iif \"lo\" accept
+		tcp dport { 22, 80 } accept
+		udp dport 53 counter packets 12 bytes 840 accept
+		jump services
+		tcp dport 25 accept
+	}
+
+	chain services {
+		tcp dport 8000-8080 accept
+		tcp dport 25 return
+		tcp dport { 8081, 8443 } ip saddr 10.0.0.0/8 accept
+	}
+
+	chain forward {
+		type filter hook forward priority filter; policy drop;
+		tcp dport 3000 accept
+	}
+}
+
+table ip extra {
+	chain input {
+		type filter hook input priority 10; policy accept;
+		tcp dport 80 drop
+	}
+}
+
+table ip nat {
+	chain prerouting {
+		type nat hook prerouting priority dstnat; policy accept;
+		tcp dport 2222 accept
+	}
+}
+";
+
+	#[test]
+	fn nftables_ruleset() {
+		let ruleset = NftRuleset::parse(&NFTABLES);
+		assert_eq!(ruleset.chains.len(), 5);
+		assert_eq!(ruleset.input_chains().count(), 2);
+		assert!(ruleset.is_active());
+
+		let input = &ruleset.chains[0];
+		assert_eq!((input.family.as_str(), input.table.as_str(), input.hook.as_deref(), input.policy), ("inet", "filter", Some("input"), Some(Policy::Drop)));
+		assert_eq!(input.rules[0], "ct state established,related accept");
+		assert_eq!(input.rules.len(), 7);
+		assert_eq!(ruleset.chains[1].hook, None);
+
+		assert!(ruleset.port_allowed(22, Protocol::Tcp));
+		assert!(ruleset.port_allowed(53, Protocol::Udp));
+		assert!(ruleset.port_allowed(8000, Protocol::Tcp));
+		assert!(ruleset.port_allowed(25, Protocol::Tcp));
+		for (port, protocol) in [
+			(80, Protocol::Tcp), (22, Protocol::Udp), (53, Protocol::Tcp), (8081, Protocol::Tcp),
+			(3000, Protocol::Tcp), (2222, Protocol::Tcp),
+		] {
+			assert!(!ruleset.port_allowed(port, protocol), "{}", port);
+		}
+
+		let firewall = Firewall { nftables: Some(ruleset), ..Default::default() };
+		assert_eq!(firewall.default_input_policy(), Some(Policy::Drop));
+		assert!(firewall.port_allowed(22, Protocol::Tcp) && !firewall.port_allowed(80, Protocol::Tcp));
+
+		let empty = NftRuleset::parse(&"table inet filter {\n\tchain input {\n\t\ttype filter hook input priority 0; policy accept;\n\t}\n}\n");
+		assert!(!empty.is_active() && empty.port_allowed(23, Protocol::Tcp));
+	}
+}
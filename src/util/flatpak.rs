@@ -0,0 +1,205 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+use std::{
+	path::Path,
+	process::{Command, Stdio},
+	string::String,
+	vec::Vec,
+};
+
+use super::{rooted_path, list_human_users_in};
+
+/// An installed flatpak application or runtime
+#[derive(Clone, Debug, Default)]
+pub struct FlatpakRef {
+	/// The application ID, like `org.mozilla.firefox`
+	pub application: String,
+	/// The human readable name, or empty if it couldn't be found
+	pub name: String,
+	/// Empty if it couldn't be found
+	pub version: String,
+	pub branch: String,
+	pub arch: String,
+	/// The remote it was installed from, which is empty when read from disk
+	pub origin: String,
+	/// `system` or `user`
+	pub installation: String,
+	/// Set for runtimes, cleared for applications
+	pub runtime: bool,
+}
+
+impl FlatpakRef {
+	/// Parse the output of `flatpak list --columns=application,name,version,ref,origin,installation,options`
+	///
+	/// `flatpak list` prints short refs like `org.mozilla.firefox/x86_64/stable`, so whether a ref is a runtime
+	/// comes from the options column. Full refs starting with `app/` or `runtime/` are accepted too.
+	pub fn parse_list<T: ToString>(output: &T) -> Vec<FlatpakRef> {
+		output.to_string().lines()
+			.filter_map(|l| {
+				let cols: Vec<&str> = l.split('\t').collect();
+				if cols.len() < 7 {
+					return None;
+				}
+
+				let parts: Vec<&str> = cols[3].split('/').collect();
+				let (kind, arch, branch) = match parts.as_slice() {
+					[_, arch, branch] => (None, arch, branch),
+					[kind, _, arch, branch] => (Some(*kind), arch, branch),
+					_ => return None,
+				};
+
+				Some(FlatpakRef {
+					application: cols[0].to_string(),
+					name: cols[1].to_string(),
+					version: cols[2].to_string(),
+					branch: branch.to_string(),
+					arch: arch.to_string(),
+					origin: cols[4].to_string(),
+					installation: cols[5].to_string(),
+					runtime: kind == Some("runtime") || cols[6].split(',').any(|o| o == "runtime"),
+				})
+			})
+			.collect()
+	}
+
+	/// Check if `name` is the application ID or the human readable name
+	pub fn matches<T: ToString>(&self, name: &T) -> bool {
+		let name = name.to_string();
+		self.application == name || self.name == name
+	}
+}
+
+/// Get the text of the first `<tag>` element, or the value of `attr` on the first `<tag ...>` element, in AppStream XML
+fn xml_first(xml: &str, tag: &str, attr: Option<&str>) -> Option<String> {
+	match attr {
+		Some(attr) => {
+			let start = xml.find(&format!("<{} ", tag))?;
+			let elem = &xml[start..start + xml[start..].find('>')?];
+			let value_start = elem.find(&format!("{}=\"", attr))? + attr.len() + 2;
+			Some(elem[value_start..].split('"').next()?.to_string())
+		},
+		None => {
+			let open = format!("<{}>", tag);
+			let start = xml.find(&open)? + open.len();
+			Some(xml[start..].split('<').next()?.trim().to_string())
+		},
+	}
+}
+
+/// Read every deployed ref in one flatpak installation directory, like /var/lib/flatpak
+fn read_installation(dir: &Path, installation: &str) -> Vec<FlatpakRef> {
+	let mut refs = Vec::new();
+
+	for (kind, runtime) in [("app", false), ("runtime", true)] {
+		let kind_dir = dir.join(kind);
+		let ids = match std::fs::read_dir(&kind_dir) {
+			Ok(rd) => rd.filter_map(|e| e.ok()).map(|e| e.file_name().to_string_lossy().to_string()).collect::<Vec<_>>(),
+			Err(_) => continue,
+		};
+
+		for id in ids {
+			let arches = std::fs::read_dir(kind_dir.join(&id)).into_iter().flatten().filter_map(|e| e.ok())
+				.filter(|e| e.path().is_dir() && e.file_name() != "current");
+
+			for arch in arches {
+				let branches = std::fs::read_dir(arch.path()).into_iter().flatten().filter_map(|e| e.ok());
+
+				for branch in branches {
+					let active = branch.path().join("active");
+					if !active.exists() {
+						continue;
+					}
+
+					let share = active.join("files/share");
+					let metainfo = ["metainfo", "appdata"].iter()
+						.flat_map(|d| [format!("{}/{}.metainfo.xml", d, id), format!("{}/{}.appdata.xml", d, id)])
+						.find_map(|f| std::fs::read_to_string(share.join(f)).ok())
+						.unwrap_or_default();
+
+					refs.push(FlatpakRef {
+						application: id.clone(),
+						name: xml_first(&metainfo, "name", None).unwrap_or_default(),
+						version: xml_first(&metainfo, "release", Some("version")).unwrap_or_default(),
+						branch: branch.file_name().to_string_lossy().to_string(),
+						arch: arch.file_name().to_string_lossy().to_string(),
+						origin: String::new(),
+						installation: installation.to_string(),
+						runtime,
+					});
+				}
+			}
+		}
+	}
+
+	refs
+}
+
+/// List the installed flatpaks with `flatpak list`, falling back to reading the installations from disk if the command fails
+pub fn list_flatpaks() -> Result<Vec<FlatpakRef>, i32> {
+	match Command::new("flatpak").args(["list", "--columns=application,name,version,ref,origin,installation,options"])
+		.stderr(Stdio::null()).stdout(Stdio::piped()).output()
+	{
+		Ok(output) if output.status.success() => Ok(FlatpakRef::parse_list(&String::from_utf8_lossy(&output.stdout))),
+		_ => list_flatpaks_in(&"/"),
+	}
+}
+
+/// List the installed flatpaks under the root directory `root`, reading /var/lib/flatpak and each human user's ~/.local/share/flatpak
+///
+/// Versions come from the AppStream metadata each application ships, so runtimes and some applications won't have one.
+pub fn list_flatpaks_in<T: ToString>(root: &T) -> Result<Vec<FlatpakRef>, i32> {
+	let mut refs = read_installation(&rooted_path(root, "/var/lib/flatpak"), "system");
+
+	for user in list_human_users_in(root).unwrap_or_default() {
+		let dir = rooted_path(root, &user.home_dir).join(".local/share/flatpak");
+		refs.extend(read_installation(&dir, "user"));
+	}
+
+	refs.sort_by(|a, b| a.application.cmp(&b.application));
+	Ok(refs)
+}
+
+/// Get the installed flatpak application whose ID or name is exactly `name`
+pub fn flatpak_app<T: ToString>(name: &T) -> Result<Option<FlatpakRef>, i32> {
+	Ok(list_flatpaks()?.into_iter().find(|f| !f.runtime && f.matches(name)))
+}
+
+/// Get the installed flatpak application whose ID or name is exactly `name` under the root directory `root`
+pub fn flatpak_app_in<A: ToString, B: ToString>(root: &A, name: &B) -> Result<Option<FlatpakRef>, i32> {
+	Ok(list_flatpaks_in(root)?.into_iter().find(|f| !f.runtime && f.matches(name)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_flatpak_list() {
+		let output = "\
+org.mozilla.firefox\tFirefox\t128.0.3\torg.mozilla.firefox/x86_64/stable\tflathub\tsystem\tsystem,current
+org.freedesktop.Platform\tFreedesktop Platform\t23.08.22\torg.freedesktop.Platform/x86_64/23.08\tflathub\tsystem\tsystem,runtime
+org.videolan.VLC\tVLC\t3.0.21\tapp/org.videolan.VLC/aarch64/stable\tflathub\tuser\tuser,current
+";
+		let refs = FlatpakRef::parse_list(&output);
+		assert_eq!(refs.len(), 3);
+
+		assert_eq!(refs[0].application, "org.mozilla.firefox");
+		assert_eq!(refs[0].name, "Firefox");
+		assert_eq!(refs[0].version, "128.0.3");
+		assert_eq!((refs[0].arch.as_str(), refs[0].branch.as_str()), ("x86_64", "stable"));
+		assert_eq!(refs[0].origin, "flathub");
+		assert!(!refs[0].runtime);
+		assert!(refs[0].matches(&"Firefox"));
+
+		assert!(refs[1].runtime);
+		assert_eq!(refs[1].branch, "23.08");
+
+		assert_eq!(refs[2].installation, "user");
+		assert_eq!(refs[2].arch, "aarch64");
+		assert!(!refs[2].runtime);
+	}
+}
@@ -0,0 +1,148 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+use std::{
+	collections::HashMap,
+	string::String,
+};
+
+use libc::uid_t;
+
+use super::{io_errno, rooted_path};
+
+/// The settings in /etc/login.defs
+///
+/// The settings most often checked are parsed into typed fields, which are [`None`] when unset or unparsable.
+/// Every setting, known or not, is also kept as is in `values`.
+#[derive(Clone, Default)]
+pub struct LoginDefs {
+	pub pass_max_days: Option<i64>,
+	pub pass_min_days: Option<i64>,
+	pub pass_warn_age: Option<i64>,
+	pub encrypt_method: Option<String>,
+	/// The default umask, parsed from octal
+	pub umask: Option<u32>,
+	pub uid_min: Option<uid_t>,
+	pub uid_max: Option<uid_t>,
+	pub values: HashMap<String, String>,
+}
+
+impl LoginDefs {
+	/// Parse the contents of a login.defs file
+	///
+	/// Each line is a key and a value separated by whitespace, blank lines and `#` comments are skipped.
+	/// If a key is given more than once, the last one wins.
+	pub fn parse<T: ToString>(text: &T) -> LoginDefs {
+		let mut defs = LoginDefs::default();
+
+		for line in text.to_string().lines() {
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') {
+				continue;
+			}
+
+			let mut tokens = line.splitn(2, char::is_whitespace);
+			let key = tokens.next().unwrap_or("");
+			let value = tokens.next().unwrap_or("").trim().trim_matches('"');
+			defs.values.insert(key.to_string(), value.to_string());
+		}
+
+		defs.pass_max_days = defs.get(&"PASS_MAX_DAYS").and_then(|v| v.parse().ok());
+		defs.pass_min_days = defs.get(&"PASS_MIN_DAYS").and_then(|v| v.parse().ok());
+		defs.pass_warn_age = defs.get(&"PASS_WARN_AGE").and_then(|v| v.parse().ok());
+		defs.encrypt_method = defs.get(&"ENCRYPT_METHOD");
+		defs.umask = defs.get(&"UMASK").and_then(|v| u32::from_str_radix(&v, 8).ok());
+		defs.uid_min = defs.get(&"UID_MIN").and_then(|v| v.parse().ok());
+		defs.uid_max = defs.get(&"UID_MAX").and_then(|v| v.parse().ok());
+
+		defs
+	}
+
+	/// Parse the login.defs formatted file at `path`
+	pub fn parse_file<T: ToString>(path: &T) -> Result<LoginDefs, i32> {
+		Ok(LoginDefs::parse(&std::fs::read_to_string(path.to_string()).map_err(io_errno)?))
+	}
+
+	/// Load /etc/login.defs
+	pub fn load() -> Result<LoginDefs, i32> {
+		LoginDefs::load_in(&"/")
+	}
+
+	/// Load /etc/login.defs under the root directory `root`
+	pub fn load_in<T: ToString>(root: &T) -> Result<LoginDefs, i32> {
+		LoginDefs::parse_file(&rooted_path(root, "/etc/login.defs").display())
+	}
+
+	/// Get the raw value of any setting
+	pub fn get<T: ToString>(&self, key: &T) -> Option<String> {
+		self.values.get(&key.to_string()).cloned()
+	}
+
+	/// Get the range of UIDs given to human accounts, using the shadow-utils defaults of 1000 and 60000 for unset bounds
+	pub fn human_uid_range(&self) -> (uid_t, uid_t) {
+		(self.uid_min.unwrap_or(1000), self.uid_max.unwrap_or(60000))
+	}
+
+	/// Check if `uid` is in the human account range
+	pub fn is_human_uid(&self, uid: uid_t) -> bool {
+		let (min, max) = self.human_uid_range();
+		uid >= min && uid <= max
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+
+	#[test]
+	fn parse_login_defs() {
+		let root = test_root("logindefs");
+		write_rooted(&root, "/etc/login.defs", "\
+#
+# /etc/login.defs - Configuration control definitions for the login package.
+#
+MAIL_DIR        /var/mail
+#PASS_MAX_DAYS	30
+
+PASS_MAX_DAYS	99999
+PASS_MIN_DAYS	0
+PASS_WARN_AGE	7
+PASS_MAX_DAYS	90
+UMASK		027
+UID_MIN			 1000
+UID_MAX			60000
+ENCRYPT_METHOD \"YESCRYPT\"
+");
+
+		let defs = LoginDefs::load_in(&root.display()).unwrap();
+		assert_eq!(defs.pass_max_days, Some(90));
+		assert_eq!(defs.pass_min_days, Some(0));
+		assert_eq!(defs.pass_warn_age, Some(7));
+		assert_eq!(defs.umask, Some(0o027));
+		assert_eq!((defs.uid_min, defs.uid_max), (Some(1000), Some(60000)));
+		assert_eq!(defs.encrypt_method.as_deref(), Some("YESCRYPT"));
+		assert_eq!(defs.get(&"MAIL_DIR").as_deref(), Some("/var/mail"));
+		assert_eq!(defs.values.len(), 8);
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn missing_and_bad_values() {
+		let defs = LoginDefs::parse(&"UID_MAX 59999\nPASS_MIN_DAYS soon\nUMASK 099\n");
+		assert_eq!(defs.pass_max_days, None);
+		assert_eq!(defs.pass_min_days, None);
+		assert_eq!(defs.umask, None);
+		assert_eq!(defs.uid_min, None);
+		assert_eq!(defs.human_uid_range(), (1000, 59999));
+		assert!(defs.is_human_uid(1000) && defs.is_human_uid(59999));
+		assert!(!defs.is_human_uid(999) && !defs.is_human_uid(60000));
+
+		assert_eq!(LoginDefs::default().human_uid_range(), (1000, 60000));
+		assert_eq!(LoginDefs::load_in(&"/nonexistent-root").err(), Some(libc::ENOENT));
+	}
+}
@@ -0,0 +1,410 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+use std::{
+	io::{Read, Seek, SeekFrom},
+	net::{IpAddr, Ipv4Addr, Ipv6Addr},
+	string::String,
+	vec::Vec,
+};
+
+use libc::uid_t;
+
+use super::{io_errno, rooted_path, list_users_in};
+
+/// Size of a glibc `struct utmp` record on disk
+const UTMP_SIZE: usize = 384;
+
+/// Size of a `struct lastlog` record on disk
+const LASTLOG_SIZE: usize = 292;
+
+/// The kind of a utmp record, from `ut_type`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UtmpType {
+	Empty,
+	RunLevel,
+	BootTime,
+	NewTime,
+	OldTime,
+	InitProcess,
+	LoginProcess,
+	/// A user logged in
+	UserProcess,
+	/// A session ended
+	DeadProcess,
+	Accounting,
+	Unknown(i16),
+}
+
+impl UtmpType {
+	fn from_raw(t: i16) -> UtmpType {
+		match t {
+			0 => UtmpType::Empty,
+			1 => UtmpType::RunLevel,
+			2 => UtmpType::BootTime,
+			3 => UtmpType::NewTime,
+			4 => UtmpType::OldTime,
+			5 => UtmpType::InitProcess,
+			6 => UtmpType::LoginProcess,
+			7 => UtmpType::UserProcess,
+			8 => UtmpType::DeadProcess,
+			9 => UtmpType::Accounting,
+			t => UtmpType::Unknown(t),
+		}
+	}
+}
+
+/// Read a NUL padded string field
+fn c_field(bytes: &[u8]) -> String {
+	let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+	String::from_utf8_lossy(&bytes[..end]).to_string()
+}
+
+fn i16_at(bytes: &[u8], off: usize) -> i16 {
+	i16::from_le_bytes([bytes[off], bytes[off + 1]])
+}
+
+fn i32_at(bytes: &[u8], off: usize) -> i32 {
+	i32::from_le_bytes([bytes[off], bytes[off + 1], bytes[off + 2], bytes[off + 3]])
+}
+
+/// A record from utmp, wtmp or btmp
+#[derive(Clone, Debug)]
+pub struct UtmpRecord {
+	pub record_type: UtmpType,
+	pub pid: i32,
+	/// The terminal, like `pts/0` or `tty1`
+	pub line: String,
+	pub id: String,
+	pub user: String,
+	pub host: String,
+	pub session: i32,
+	/// Seconds since the epoch
+	pub time: i64,
+	pub usec: i32,
+	pub addr: Option<IpAddr>,
+}
+
+impl UtmpRecord {
+	/// Parse a single record in the glibc layout used on x86_64 and aarch64
+	///
+	/// Returns `Err(-1)` if `bytes` is shorter than a record.
+	pub fn parse(bytes: &[u8]) -> Result<UtmpRecord, i32> {
+		if bytes.len() < UTMP_SIZE {
+			return Err(-1);
+		}
+
+		let words = [i32_at(bytes, 348), i32_at(bytes, 352), i32_at(bytes, 356), i32_at(bytes, 360)];
+		let addr = if words == [0; 4] {
+			None
+		} else if words[1..] == [0; 3] {
+			Some(IpAddr::V4(Ipv4Addr::new(bytes[348], bytes[349], bytes[350], bytes[351])))
+		} else {
+			let mut octets = [0u8; 16];
+			octets.copy_from_slice(&bytes[348..364]);
+			Some(IpAddr::V6(Ipv6Addr::from(octets)))
+		};
+
+		Ok(UtmpRecord {
+			record_type: UtmpType::from_raw(i16_at(bytes, 0)),
+			pid: i32_at(bytes, 4),
+			line: c_field(&bytes[8..40]),
+			id: c_field(&bytes[40..44]),
+			user: c_field(&bytes[44..76]),
+			host: c_field(&bytes[76..332]),
+			session: i32_at(bytes, 336),
+			time: i32_at(bytes, 340) as u32 as i64,
+			usec: i32_at(bytes, 344),
+			addr,
+		})
+	}
+
+	/// Parse every record in a utmp formatted file, like /var/log/wtmp
+	///
+	/// A partial record at the end, as left by a crash mid-write, is ignored.
+	pub fn parse_file<T: ToString>(path: &T) -> Result<Vec<UtmpRecord>, i32> {
+		let contents = std::fs::read(path.to_string()).map_err(io_errno)?;
+
+		contents.chunks_exact(UTMP_SIZE)
+			.map(UtmpRecord::parse)
+			.collect()
+	}
+
+	/// Check if the record came from `host`, by hostname or address
+	pub fn is_from<T: ToString>(&self, host: &T) -> bool {
+		let host = host.to_string();
+		self.host == host || self.addr.is_some_and(|a| a.to_string() == host)
+	}
+}
+
+/// A user's most recent login, as recorded in /var/log/lastlog
+#[derive(Clone, Debug)]
+pub struct LastlogEntry {
+	pub uid: uid_t,
+	/// Seconds since the epoch
+	pub time: i64,
+	pub line: String,
+	pub host: String,
+}
+
+impl LastlogEntry {
+	/// Parse a single lastlog record for the user with UID `uid`
+	///
+	/// Returns `Err(-1)` if `bytes` is shorter than a record.
+	pub fn parse(uid: uid_t, bytes: &[u8]) -> Result<LastlogEntry, i32> {
+		if bytes.len() < LASTLOG_SIZE {
+			return Err(-1);
+		}
+
+		Ok(LastlogEntry {
+			uid,
+			time: i32_at(bytes, 0) as u32 as i64,
+			line: c_field(&bytes[4..36]),
+			host: c_field(&bytes[36..292]),
+		})
+	}
+
+	/// Read the record of the user with UID `uid` from a lastlog file, without touching the rest of it
+	///
+	/// The file is sparse and indexed by UID, so a high UID like nobody's puts its record over a terabyte in.
+	/// Returns `Ok(None)` if the user never logged in.
+	pub fn read_file<T: ToString>(path: &T, uid: uid_t) -> Result<Option<LastlogEntry>, i32> {
+		let mut file = std::fs::File::open(path.to_string()).map_err(io_errno)?;
+		let mut bytes = [0u8; LASTLOG_SIZE];
+
+		file.seek(SeekFrom::Start(uid as u64 * LASTLOG_SIZE as u64)).map_err(io_errno)?;
+		match file.read_exact(&mut bytes) {
+			Ok(()) => (),
+			Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+			Err(e) => return Err(io_errno(e)),
+		}
+
+		Ok(Some(LastlogEntry::parse(uid, &bytes)?).filter(|e| e.time != 0))
+	}
+
+	/// Parse every record in a lastlog file, which is indexed by UID
+	///
+	/// Users who never logged in have an empty record and are skipped.
+	/// This reads the whole file, so [`LastlogEntry::read_file`] is better when the UIDs are known.
+	pub fn parse_file<T: ToString>(path: &T) -> Result<Vec<LastlogEntry>, i32> {
+		let contents = std::fs::read(path.to_string()).map_err(io_errno)?;
+		let mut entries = Vec::new();
+
+		for (uid, chunk) in contents.chunks_exact(LASTLOG_SIZE).enumerate() {
+			let entry = LastlogEntry::parse(uid as uid_t, chunk)?;
+			if entry.time != 0 {
+				entries.push(entry);
+			}
+		}
+
+		Ok(entries)
+	}
+}
+
+/// List the records of current sessions in /run/utmp
+pub fn list_utmp() -> Result<Vec<UtmpRecord>, i32> {
+	list_utmp_in(&"/")
+}
+
+/// List the records in /run/utmp under the root directory `root`
+pub fn list_utmp_in<T: ToString>(root: &T) -> Result<Vec<UtmpRecord>, i32> {
+	UtmpRecord::parse_file(&rooted_path(root, "/run/utmp").display())
+}
+
+/// List the login history in /var/log/wtmp
+pub fn list_wtmp() -> Result<Vec<UtmpRecord>, i32> {
+	list_wtmp_in(&"/")
+}
+
+/// List the login history in /var/log/wtmp under the root directory `root`
+pub fn list_wtmp_in<T: ToString>(root: &T) -> Result<Vec<UtmpRecord>, i32> {
+	UtmpRecord::parse_file(&rooted_path(root, "/var/log/wtmp").display())
+}
+
+/// List the failed logins in /var/log/btmp
+pub fn list_btmp() -> Result<Vec<UtmpRecord>, i32> {
+	list_btmp_in(&"/")
+}
+
+/// List the failed logins in /var/log/btmp under the root directory `root`
+pub fn list_btmp_in<T: ToString>(root: &T) -> Result<Vec<UtmpRecord>, i32> {
+	UtmpRecord::parse_file(&rooted_path(root, "/var/log/btmp").display())
+}
+
+/// List every user's most recent login in /var/log/lastlog
+pub fn list_lastlog() -> Result<Vec<LastlogEntry>, i32> {
+	list_lastlog_in(&"/")
+}
+
+/// List every user's most recent login in /var/log/lastlog under the root directory `root`, in UID order
+///
+/// Only the records of users in /etc/passwd are read.
+pub fn list_lastlog_in<T: ToString>(root: &T) -> Result<Vec<LastlogEntry>, i32> {
+	let path = rooted_path(root, "/var/log/lastlog").display().to_string();
+	let mut uids: Vec<uid_t> = list_users_in(root)?.into_iter().map(|u| u.uid).collect();
+	uids.sort();
+	uids.dedup();
+
+	let mut entries = Vec::new();
+	for uid in uids {
+		entries.extend(LastlogEntry::read_file(&path, uid)?);
+	}
+
+	Ok(entries)
+}
+
+/// Find the most recent login of the user named `name`
+pub fn last_login<T: ToString>(name: &T) -> Result<Option<LastlogEntry>, i32> {
+	last_login_in(&"/", name)
+}
+
+/// Find the most recent login of the user named `name` under the root directory `root`
+///
+/// Both wtmp and lastlog are checked, since either may have been rotated or cleared, and the later login wins.
+/// Returns `Ok(None)` if the user has never logged in, and `ENOENT` if the user doesn't exist.
+pub fn last_login_in<A: ToString, B: ToString>(root: &A, name: &B) -> Result<Option<LastlogEntry>, i32> {
+	let name = name.to_string();
+	let user = list_users_in(root)?
+		.into_iter()
+		.find(|u| u.username == name)
+		.ok_or(libc::ENOENT)?;
+
+	let from_wtmp = match list_wtmp_in(root) {
+		Ok(records) => records.into_iter()
+			.filter(|r| r.record_type == UtmpType::UserProcess && r.user == name)
+			.max_by_key(|r| r.time)
+			.map(|r| LastlogEntry { uid: user.uid, time: r.time, line: r.line, host: r.host }),
+		Err(libc::ENOENT) => None,
+		Err(e) => return Err(e),
+	};
+
+	let from_lastlog = match LastlogEntry::read_file(&rooted_path(root, "/var/log/lastlog").display(), user.uid) {
+		Ok(entry) => entry,
+		Err(libc::ENOENT) => None,
+		Err(e) => return Err(e),
+	};
+
+	Ok(from_wtmp.into_iter().chain(from_lastlog).max_by_key(|e| e.time))
+}
+
+/// List the failed logins from `host`, matched by hostname or address
+pub fn failed_logins_from<T: ToString>(host: &T) -> Result<Vec<UtmpRecord>, i32> {
+	failed_logins_from_in(&"/", host)
+}
+
+/// List the failed logins from `host` under the root directory `root`
+pub fn failed_logins_from_in<A: ToString, B: ToString>(root: &A, host: &B) -> Result<Vec<UtmpRecord>, i32> {
+	Ok(list_btmp_in(root)?.into_iter().filter(|r| r.is_from(host)).collect())
+}
+
+/// List the failed logins for the user named `name`
+pub fn failed_logins_for<T: ToString>(name: &T) -> Result<Vec<UtmpRecord>, i32> {
+	failed_logins_for_in(&"/", name)
+}
+
+/// List the failed logins for the user named `name` under the root directory `root`
+pub fn failed_logins_for_in<A: ToString, B: ToString>(root: &A, name: &B) -> Result<Vec<UtmpRecord>, i32> {
+	let name = name.to_string();
+	Ok(list_btmp_in(root)?.into_iter().filter(|r| r.user == name).collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+
+	fn utmp_record(record_type: i16, line: &str, user: &str, host: &str, time: i32, addr: &[u8]) -> Vec<u8> {
+		let mut bytes = vec![0u8; UTMP_SIZE];
+		bytes[0..2].copy_from_slice(&record_type.to_le_bytes());
+		bytes[4..8].copy_from_slice(&1234i32.to_le_bytes());
+		bytes[8..8 + line.len()].copy_from_slice(line.as_bytes());
+		bytes[44..44 + user.len()].copy_from_slice(user.as_bytes());
+		bytes[76..76 + host.len()].copy_from_slice(host.as_bytes());
+		bytes[340..344].copy_from_slice(&time.to_le_bytes());
+		bytes[348..348 + addr.len()].copy_from_slice(addr);
+		bytes
+	}
+
+	fn lastlog_record(time: i32, line: &str, host: &str) -> Vec<u8> {
+		let mut bytes = vec![0u8; LASTLOG_SIZE];
+		bytes[0..4].copy_from_slice(&time.to_le_bytes());
+		bytes[4..4 + line.len()].copy_from_slice(line.as_bytes());
+		bytes[36..36 + host.len()].copy_from_slice(host.as_bytes());
+		bytes
+	}
+
+	/// Write a sparse lastlog file with a record for each `(uid, record)`
+	fn write_lastlog(path: &std::path::Path, records: &[(uid_t, Vec<u8>)]) {
+		let mut file = std::fs::File::create(path).unwrap();
+		for (uid, record) in records {
+			file.seek(SeekFrom::Start(*uid as u64 * LASTLOG_SIZE as u64)).unwrap();
+			std::io::Write::write_all(&mut file, record).unwrap();
+		}
+	}
+
+	#[test]
+	fn utmp_records() {
+		let mut bytes = utmp_record(7, "pts/0", "alice", "10.0.0.5", 1_700_000_000, &[10, 0, 0, 5]);
+		let v6: Ipv6Addr = "2001:db8::1".parse().unwrap();
+		bytes.extend(utmp_record(8, "pts/1", "", "", 1_700_000_100, &v6.octets()));
+		// A torn write at the end
+		bytes.extend([0u8; 100]);
+
+		let root = test_root("logins-utmp");
+		write_rooted(&root, "/var/log/wtmp", &bytes);
+
+		let records = list_wtmp_in(&root.display()).unwrap();
+		assert_eq!(records.len(), 2);
+		assert_eq!(records[0].record_type, UtmpType::UserProcess);
+		assert_eq!(records[0].pid, 1234);
+		assert_eq!(records[0].line, "pts/0");
+		assert_eq!(records[0].user, "alice");
+		assert_eq!(records[0].time, 1_700_000_000);
+		assert!(records[0].is_from(&"10.0.0.5"));
+		assert_eq!(records[1].record_type, UtmpType::DeadProcess);
+		assert_eq!(records[1].addr, Some(IpAddr::V6(v6)));
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn lastlog_is_read_by_uid() {
+		let root = test_root("logins-lastlog");
+		write_rooted(&root, "/etc/passwd", "\
+root:x:0:0:root:/root:/bin/bash
+alice:x:1000:1000::/home/alice:/bin/bash
+bob:x:1001:1001::/home/bob:/bin/bash
+nobody:x:4000000000:4000000000::/nonexistent:/usr/sbin/nologin
+");
+		write_rooted(&root, "/var/log/wtmp", utmp_record(7, "tty1", "alice", "", 1_700_000_500, &[]));
+		std::fs::create_dir_all(root.join("var/log")).unwrap();
+		write_lastlog(&root.join("var/log/lastlog"), &[
+			(1000, lastlog_record(1_700_000_000, "pts/0", "10.0.0.5")),
+			(1001, lastlog_record(1_700_000_900, "pts/2", "example.com")),
+			// Over a terabyte into the file, which would take forever to read in full
+			(4_000_000_000, lastlog_record(1_600_000_000, "pts/9", "")),
+		]);
+		let root_s = root.display();
+
+		let entries = list_lastlog_in(&root_s).unwrap();
+		let uids: Vec<uid_t> = entries.iter().map(|e| e.uid).collect();
+		assert_eq!(uids, [1000, 1001, 4_000_000_000]);
+		assert_eq!(entries[1].host, "example.com");
+
+		// wtmp has a later login for alice than lastlog
+		let alice = last_login_in(&root_s, &"alice").unwrap().unwrap();
+		assert_eq!((alice.time, alice.line.as_str()), (1_700_000_500, "tty1"));
+
+		let bob = last_login_in(&root_s, &"bob").unwrap().unwrap();
+		assert_eq!((bob.time, bob.line.as_str()), (1_700_000_900, "pts/2"));
+
+		assert_eq!(last_login_in(&root_s, &"root").unwrap().map(|e| e.time), None);
+		assert_eq!(last_login_in(&root_s, &"nobody").unwrap().map(|e| e.time), Some(1_600_000_000));
+		assert_eq!(last_login_in(&root_s, &"carol").map(|e| e.is_some()), Err(libc::ENOENT));
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+}
@@ -9,9 +9,97 @@
 mod user;
 mod program;
 mod filesystem;
+mod roster;
+mod version;
+#[cfg(target_os = "linux")]
+mod shadow;
+#[cfg(target_os = "linux")]
+mod crypt;
+#[cfg(target_os = "linux")]
+mod sudoers;
+#[cfg(target_os = "linux")]
+mod logindefs;
+#[cfg(target_os = "linux")]
+mod shells;
+#[cfg(target_os = "linux")]
+mod ssh;
+#[cfg(target_os = "linux")]
+mod logins;
+#[cfg(target_os = "linux")]
+mod dpkg;
+#[cfg(target_os = "linux")]
+mod apt;
+#[cfg(target_os = "linux")]
+mod aptconf;
+#[cfg(target_os = "linux")]
+mod packages;
+#[cfg(target_os = "linux")]
+mod snap;
+#[cfg(target_os = "linux")]
+mod flatpak;
+#[cfg(target_os = "linux")]
+mod detect;
+#[cfg(target_os = "linux")]
+mod sysctl;
+#[cfg(target_os = "linux")]
+mod prohibited;
+#[cfg(target_os = "linux")]
+mod pam;
+#[cfg(target_os = "linux")]
+mod service;
+#[cfg(target_os = "linux")]
+mod process;
+#[cfg(target_os = "linux")]
+mod network;
+#[cfg(target_os = "linux")]
+mod firewall;
 pub use user::*;
 pub use program::*;
 pub use filesystem::*;
+pub use roster::*;
+pub use version::*;
+#[cfg(target_os = "linux")]
+pub use shadow::*;
+#[cfg(target_os = "linux")]
+pub use crypt::*;
+#[cfg(target_os = "linux")]
+pub use sudoers::*;
+#[cfg(target_os = "linux")]
+pub use logindefs::*;
+#[cfg(target_os = "linux")]
+pub use shells::*;
+#[cfg(target_os = "linux")]
+pub use ssh::*;
+#[cfg(target_os = "linux")]
+pub use logins::*;
+#[cfg(target_os = "linux")]
+pub use dpkg::*;
+#[cfg(target_os = "linux")]
+pub use apt::*;
+#[cfg(target_os = "linux")]
+pub use aptconf::*;
+#[cfg(target_os = "linux")]
+pub use packages::*;
+#[cfg(target_os = "linux")]
+pub use snap::*;
+#[cfg(target_os = "linux")]
+pub use flatpak::*;
+#[cfg(target_os = "linux")]
+pub use detect::*;
+#[cfg(target_os = "linux")]
+pub use sysctl::*;
+#[cfg(target_os = "linux")]
+pub use prohibited::*;
+#[cfg(target_os = "linux")]
+pub use pam::*;
+#[cfg(target_os = "linux")]
+pub use service::*;
+#[cfg(target_os = "linux")]
+pub use process::*;
+#[cfg(target_os = "linux")]
+pub use network::*;
+#[cfg(target_os = "linux")]
+pub use firewall::*;
 
 #[cfg(target_os = "linux")]
 pub use libc::{uid_t, gid_t};
@@ -19,4 +107,31 @@ pub use libc::{uid_t, gid_t};
 /// Get the OS error, effectively the same as the errno macro in C
 pub fn errno() -> i32 {
     std::io::Error::last_os_error().raw_os_error().unwrap_or(0)
-}
\ No newline at end of file
+}
+
+/// Get the OS error code out of an [`std::io::Error`], or -1 if it doesn't carry one
+pub(crate) fn io_errno(e: std::io::Error) -> i32 {
+    e.raw_os_error().unwrap_or(-1)
+}
+
+/// Join an absolute system path onto `root`, so `/etc/passwd` under `/mnt/image` becomes `/mnt/image/etc/passwd`
+pub(crate) fn rooted_path<T: ToString>(root: &T, path: &str) -> std::path::PathBuf {
+    std::path::Path::new(&root.to_string()).join(path.trim_start_matches('/'))
+}
+
+/// Make an empty directory under the temporary directory to build a fake root in, unique to the test `name`
+#[cfg(test)]
+pub(crate) fn test_root(name: &str) -> std::path::PathBuf {
+    let root = std::env::temp_dir().join(format!("cypat-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    root
+}
+
+/// Write `contents` to the system path `path` under `root`, creating any missing directories
+#[cfg(test)]
+pub(crate) fn write_rooted<C: AsRef<[u8]>>(root: &std::path::Path, path: &str, contents: C) {
+    let path = rooted_path(&root.display(), path);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, contents).unwrap();
+}
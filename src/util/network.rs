@@ -0,0 +1,413 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+//! # Sockets
+//!
+//! Parsers for the socket tables in /proc/net, and the mapping from sockets to the processes holding them through /proc/<pid>/fd,
+//! which together answer what is listening on a port and who opened it.
+//! Like [`process`](super::process), everything has an `_in` variant reading `<root>/proc`.
+
+use std::{
+	collections::HashMap,
+	net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+	vec::Vec,
+};
+
+use libc::uid_t;
+
+use super::{io_errno, rooted_path};
+use super::process::{read_process_in, ProcessInfo};
+
+/// A socket's transport protocol
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Protocol {
+	Tcp,
+	Udp,
+	Raw,
+}
+
+impl Protocol {
+	/// The tables under /proc/net holding sockets of this protocol, IPv4 first
+	fn tables(&self) -> [&'static str; 2] {
+		match self {
+			Protocol::Tcp => ["tcp", "tcp6"],
+			Protocol::Udp => ["udp", "udp6"],
+			Protocol::Raw => ["raw", "raw6"],
+		}
+	}
+}
+
+/// The state of a socket, using the kernel's TCP state numbers
+///
+/// UDP and raw sockets only use [`SocketState::Established`] for connected sockets and [`SocketState::Close`] otherwise.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SocketState {
+	Established,
+	SynSent,
+	SynRecv,
+	FinWait1,
+	FinWait2,
+	TimeWait,
+	Close,
+	CloseWait,
+	LastAck,
+	Listen,
+	Closing,
+	NewSynRecv,
+	Unknown(u8),
+}
+
+impl SocketState {
+	fn from_code(code: u8) -> SocketState {
+		match code {
+			0x01 => SocketState::Established,
+			0x02 => SocketState::SynSent,
+			0x03 => SocketState::SynRecv,
+			0x04 => SocketState::FinWait1,
+			0x05 => SocketState::FinWait2,
+			0x06 => SocketState::TimeWait,
+			0x07 => SocketState::Close,
+			0x08 => SocketState::CloseWait,
+			0x09 => SocketState::LastAck,
+			0x0A => SocketState::Listen,
+			0x0B => SocketState::Closing,
+			0x0C => SocketState::NewSynRecv,
+			other => SocketState::Unknown(other),
+		}
+	}
+}
+
+/// One socket from a /proc/net table
+#[derive(Clone, Debug)]
+pub struct SocketEntry {
+	pub protocol: Protocol,
+	/// The local address and port, where raw sockets have the IP protocol number as the port
+	pub local: SocketAddr,
+	pub remote: SocketAddr,
+	pub state: SocketState,
+	/// The UID of the user that opened the socket
+	pub uid: uid_t,
+	/// The socket's inode, which links it to the file descriptors in /proc/<pid>/fd
+	pub inode: u64,
+}
+
+/// Parse an address from a /proc/net table, like `0100007F:0016`
+///
+/// The address is printed as the raw 32 bit words in the machine's byte order, while the port is in normal order.
+fn parse_address(s: &str) -> Option<SocketAddr> {
+	let (addr, port) = s.split_once(':')?;
+	let port = u16::from_str_radix(port, 16).ok()?;
+	if addr.len() % 8 != 0 {
+		return None;
+	}
+
+	let words: Vec<[u8; 4]> = (0..addr.len() / 8)
+		.map(|i| addr.get(i * 8..i * 8 + 8).and_then(|w| u32::from_str_radix(w, 16).ok()).map(|w| w.to_ne_bytes()))
+		.collect::<Option<_>>()?;
+	let bytes: Vec<u8> = words.concat();
+
+	let ip = match bytes.len() {
+		4 => IpAddr::V4(Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3])),
+		16 => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(bytes.as_slice()).ok()?)),
+		_ => return None,
+	};
+
+	Some(SocketAddr::new(ip, port))
+}
+
+impl SocketEntry {
+	/// Parse one line of a /proc/net/tcp, udp or raw table, or their IPv6 versions
+	///
+	/// Returns `Err(-1)` if the line is malformed, which includes the header.
+	pub fn parse_line(protocol: Protocol, line: &str) -> Result<SocketEntry, i32> {
+		let fields: Vec<&str> = line.split_whitespace().collect();
+		if fields.len() < 10 {
+			return Err(-1);
+		}
+
+		Ok(SocketEntry {
+			protocol,
+			local: parse_address(fields[1]).ok_or(-1)?,
+			remote: parse_address(fields[2]).ok_or(-1)?,
+			state: SocketState::from_code(u8::from_str_radix(fields[3], 16).map_err(|_| -1)?),
+			uid: fields[7].parse().map_err(|_| -1)?,
+			inode: fields[9].parse().map_err(|_| -1)?,
+		})
+	}
+
+	/// Parse a whole /proc/net table, skipping the header and anything malformed
+	pub fn parse_table<T: ToString>(protocol: Protocol, text: &T) -> Vec<SocketEntry> {
+		text.to_string().lines()
+			.filter_map(|l| SocketEntry::parse_line(protocol, l).ok())
+			.collect()
+	}
+
+	/// The local port
+	pub fn port(&self) -> u16 {
+		self.local.port()
+	}
+
+	/// Check if the socket is waiting for connections or datagrams
+	///
+	/// That means a listening TCP socket, or a UDP or raw socket that isn't connected to a remote address.
+	pub fn is_listening(&self) -> bool {
+		match self.protocol {
+			Protocol::Tcp => self.state == SocketState::Listen,
+			Protocol::Udp | Protocol::Raw => self.state == SocketState::Close && self.remote.ip().is_unspecified(),
+		}
+	}
+
+	/// Check if the socket only accepts connections from the machine itself
+	pub fn is_loopback(&self) -> bool {
+		match self.local.ip() {
+			IpAddr::V4(ip) => ip.is_loopback(),
+			IpAddr::V6(ip) => ip.is_loopback() || ip.to_ipv4_mapped().is_some_and(|v4| v4.is_loopback()),
+		}
+	}
+}
+
+/// A listening socket and the processes holding it open
+#[derive(Clone, Debug)]
+pub struct Listener {
+	pub socket: SocketEntry,
+	/// Empty if the owners can't be read, which needs root for other users' processes
+	pub processes: Vec<ProcessInfo>,
+}
+
+/// List every socket of `protocol`
+pub fn list_sockets(protocol: Protocol) -> Result<Vec<SocketEntry>, i32> {
+	list_sockets_in(&"/", protocol)
+}
+
+/// List every socket of `protocol`, IPv4 and IPv6, from /proc/net under the root directory `root`
+///
+/// A missing IPv6 table, as on a kernel without IPv6, is skipped. Returns `Err(ENOENT)` if neither table exists.
+pub fn list_sockets_in<T: ToString>(root: &T, protocol: Protocol) -> Result<Vec<SocketEntry>, i32> {
+	let mut sockets = Vec::new();
+	let mut found = false;
+
+	for table in protocol.tables() {
+		match std::fs::read_to_string(rooted_path(root, &format!("/proc/net/{}", table))) {
+			Ok(text) => {
+				found = true;
+				sockets.extend(SocketEntry::parse_table(protocol, &text));
+			},
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+			Err(e) => return Err(io_errno(e)),
+		}
+	}
+
+	if found { Ok(sockets) } else { Err(libc::ENOENT) }
+}
+
+/// Map socket inodes to the PIDs holding them open
+pub fn socket_owners() -> Result<HashMap<u64, Vec<u32>>, i32> {
+	socket_owners_in(&"/")
+}
+
+/// Map socket inodes to the PIDs holding them open, reading /proc/<pid>/fd under the root directory `root`
+///
+/// Processes whose file descriptors can't be read are skipped.
+pub fn socket_owners_in<T: ToString>(root: &T) -> Result<HashMap<u64, Vec<u32>>, i32> {
+	let proc = std::fs::read_dir(rooted_path(root, "/proc")).map_err(io_errno)?;
+	let mut owners: HashMap<u64, Vec<u32>> = HashMap::new();
+
+	for entry in proc.filter_map(|e| e.ok()) {
+		let pid: u32 = match entry.file_name().to_string_lossy().parse() {
+			Ok(p) => p,
+			Err(_) => continue,
+		};
+
+		for fd in std::fs::read_dir(entry.path().join("fd")).into_iter().flatten().filter_map(|e| e.ok()) {
+			let target = match std::fs::read_link(fd.path()) {
+				Ok(t) => t.to_string_lossy().to_string(),
+				Err(_) => continue,
+			};
+
+			if let Some(inode) = target.strip_prefix("socket:[").and_then(|t| t.strip_suffix(']')).and_then(|i| i.parse().ok()) {
+				let pids = owners.entry(inode).or_default();
+				if !pids.contains(&pid) {
+					pids.push(pid);
+				}
+			}
+		}
+	}
+
+	Ok(owners)
+}
+
+/// List every listening TCP, UDP and raw socket with the processes holding it
+pub fn listeners() -> Result<Vec<Listener>, i32> {
+	listeners_in(&"/")
+}
+
+/// List every listening socket under the root directory `root` with the processes holding it, sorted by protocol and port
+pub fn listeners_in<T: ToString>(root: &T) -> Result<Vec<Listener>, i32> {
+	let owners = socket_owners_in(root)?;
+	let mut listeners = Vec::new();
+
+	for protocol in [Protocol::Tcp, Protocol::Udp, Protocol::Raw] {
+		let sockets = match list_sockets_in(root, protocol) {
+			Ok(s) => s,
+			Err(e) if e == libc::ENOENT => continue,
+			Err(e) => return Err(e),
+		};
+
+		for socket in sockets.into_iter().filter(|s| s.is_listening()) {
+			let processes = owners.get(&socket.inode).into_iter().flatten()
+				.filter_map(|pid| read_process_in(root, *pid).ok())
+				.collect();
+			listeners.push(Listener { socket, processes });
+		}
+	}
+
+	listeners.sort_by_key(|l| (l.socket.protocol as u8, l.socket.port()));
+	Ok(listeners)
+}
+
+/// Check if anything is listening on `port` over `protocol`, on any address
+pub fn port_is_listening(port: u16, protocol: Protocol) -> Result<bool, i32> {
+	port_is_listening_in(&"/", port, protocol)
+}
+
+/// Check if anything is listening on `port` over `protocol` under the root directory `root`
+pub fn port_is_listening_in<T: ToString>(root: &T, port: u16, protocol: Protocol) -> Result<bool, i32> {
+	Ok(list_sockets_in(root, protocol)?.iter().any(|s| s.is_listening() && s.port() == port))
+}
+
+/// List the processes listening on `port` over `protocol`
+pub fn port_owners(port: u16, protocol: Protocol) -> Result<Vec<ProcessInfo>, i32> {
+	port_owners_in(&"/", port, protocol)
+}
+
+/// List the processes listening on `port` over `protocol` under the root directory `root`
+pub fn port_owners_in<T: ToString>(root: &T, port: u16, protocol: Protocol) -> Result<Vec<ProcessInfo>, i32> {
+	let mut processes: Vec<ProcessInfo> = Vec::new();
+
+	for listener in listeners_in(root)?.into_iter().filter(|l| l.socket.protocol == protocol && l.socket.port() == port) {
+		for process in listener.processes {
+			if !processes.iter().any(|p| p.pid == process.pid) {
+				processes.push(process);
+			}
+		}
+	}
+
+	Ok(processes)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+	use std::path::Path;
+
+	// Captured on x86_64, where the kernel prints addresses as little endian words
+	const TCP: &str = "\
+  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:0277 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 23456 1 0000000000000000 100 0 0 10 0
+   1: 00000000:0016 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 19876 1 0000000000000000 100 0 0 10 0
+   2: 0F02000A:0016 0202000A:C350 01 00000000:00000000 02:000A7B2A 00000000     0        0 40000 4 0000000000000000 20 4 29 10 -1
+";
+	const TCP6: &str = "\
+  sl  local_address                         remote_address                        st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 00000000000000000000000000000000:0016 00000000000000000000000000000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 19877 1 0000000000000000 100 0 0 10 0
+   1: 00000000000000000000000001000000:0277 00000000000000000000000000000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 23457 1 0000000000000000 100 0 0 10 0
+";
+	const UDP: &str = "\
+   sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops
+  123: 3500007F:0035 00000000:0000 07 00000000:00000000 00:00000000 00000000   991        0 15000 2 0000000000000000 0
+  456: 0F02000A:D431 08080808:0035 01 00000000:00000000 00:00000000 00000000  1000        0 15001 2 0000000000000000 0
+";
+
+	fn add_process(root: &Path, pid: u32, name: &str, sockets: &[u64]) {
+		write_rooted(root, &format!("/proc/{}/stat", pid), format!("{} ({}) S 1 {} {} 0 -1 4194560 0 0 0 0 0 0 0 0 20 0 1 0\n", pid, name, pid, pid));
+		write_rooted(root, &format!("/proc/{}/status", pid), format!("Name:\t{}\nUid:\t0\t0\t0\t0\nGid:\t0\t0\t0\t0\n", name));
+		std::fs::create_dir_all(root.join(format!("proc/{}/fd", pid))).unwrap();
+		for (fd, inode) in sockets.iter().enumerate() {
+			std::os::unix::fs::symlink(format!("socket:[{}]", inode), root.join(format!("proc/{}/fd/{}", pid, fd + 3))).unwrap();
+		}
+		std::os::unix::fs::symlink("/dev/null", root.join(format!("proc/{}/fd/0", pid))).unwrap();
+	}
+
+	#[cfg(target_endian = "little")]
+	#[test]
+	fn addresses_and_lines() {
+		assert_eq!(parse_address("0100007F:0016"), Some("127.0.0.1:22".parse().unwrap()));
+		assert_eq!(parse_address("0F02000A:C350"), Some("10.0.2.15:50000".parse().unwrap()));
+		assert_eq!(parse_address("00000000000000000000000001000000:0277"), Some("[::1]:631".parse().unwrap()));
+		assert_eq!(parse_address("0000000000000000FFFF00000100007F:0050"), Some("[::ffff:127.0.0.1]:80".parse().unwrap()));
+		assert_eq!(parse_address("B80D0120000000000000000001000000:0016"), Some("[2001:db8::1]:22".parse().unwrap()));
+		for bad in ["0100007F", "0100007F:XYZ", "0100:0016", "GG00007F:0016", "0100007F00:0016"] {
+			assert_eq!(parse_address(bad), None, "{}", bad);
+		}
+
+		let lines: Vec<&str> = TCP.lines().collect();
+		assert_eq!(SocketEntry::parse_line(Protocol::Tcp, lines[0]).map(|s| s.inode), Err(-1));
+		let established = SocketEntry::parse_line(Protocol::Tcp, lines[3]).unwrap();
+		assert_eq!(established.local, "10.0.2.15:22".parse().unwrap());
+		assert_eq!(established.remote, "10.0.2.2:50000".parse().unwrap());
+		assert_eq!((established.state, established.uid, established.inode), (SocketState::Established, 0, 40000));
+		assert!(!established.is_listening() && !established.is_loopback());
+
+		let cups = SocketEntry::parse_line(Protocol::Tcp, lines[1]).unwrap();
+		assert!(cups.is_listening() && cups.is_loopback());
+		assert_eq!(cups.port(), 631);
+
+		// UDP sockets sit in TCP_CLOSE (07) while waiting for datagrams
+		let udp = SocketEntry::parse_table(Protocol::Udp, &UDP);
+		assert_eq!(udp.len(), 2);
+		assert_eq!((udp[0].state, udp[0].uid, udp[0].port()), (SocketState::Close, 991, 53));
+		assert!(udp[0].is_listening() && udp[0].is_loopback());
+		assert_eq!(udp[1].state, SocketState::Established);
+		assert!(!udp[1].is_listening());
+	}
+
+	#[cfg(target_endian = "little")]
+	#[test]
+	fn listeners_with_owners() {
+		let root = test_root("network-listeners");
+		write_rooted(&root, "/proc/net/tcp", TCP);
+		write_rooted(&root, "/proc/net/tcp6", TCP6);
+		write_rooted(&root, "/proc/net/udp", UDP);
+		add_process(&root, 1, "systemd", &[19876]);
+		add_process(&root, 812, "sshd", &[19876, 19877, 40000]);
+		add_process(&root, 900, "cupsd", &[23456, 23457]);
+		add_process(&root, 650, "systemd-resolve", &[15000]);
+		let root_s = root.display();
+
+		let owners = socket_owners_in(&root_s).unwrap();
+		let mut ssh_owners = owners[&19876].clone();
+		ssh_owners.sort();
+		assert_eq!(ssh_owners, [1, 812]);
+		assert_eq!(owners[&40000], [812]);
+
+		let listeners = listeners_in(&root_s).unwrap();
+		let summary: Vec<(Protocol, String, Vec<String>)> = listeners.iter()
+			.map(|l| {
+				let mut names: Vec<String> = l.processes.iter().map(|p| p.name.clone()).collect();
+				names.sort();
+				(l.socket.protocol, l.socket.local.to_string(), names)
+			})
+			.collect();
+		assert_eq!(summary, [
+			(Protocol::Tcp, "0.0.0.0:22".to_string(), vec!["sshd".to_string(), "systemd".to_string()]),
+			(Protocol::Tcp, "[::]:22".to_string(), vec!["sshd".to_string()]),
+			(Protocol::Tcp, "127.0.0.1:631".to_string(), vec!["cupsd".to_string()]),
+			(Protocol::Tcp, "[::1]:631".to_string(), vec!["cupsd".to_string()]),
+			(Protocol::Udp, "127.0.0.53:53".to_string(), vec!["systemd-resolve".to_string()]),
+		]);
+
+		assert!(port_is_listening_in(&root_s, 22, Protocol::Tcp).unwrap());
+		assert!(!port_is_listening_in(&root_s, 50000, Protocol::Tcp).unwrap());
+		assert!(port_is_listening_in(&root_s, 53, Protocol::Udp).unwrap());
+		assert_eq!(port_is_listening_in(&root_s, 1, Protocol::Raw), Err(libc::ENOENT));
+		let mut pids: Vec<u32> = port_owners_in(&root_s, 22, Protocol::Tcp).unwrap().iter().map(|p| p.pid).collect();
+		pids.sort();
+		assert_eq!(pids, [1, 812]);
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+}
@@ -0,0 +1,381 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+use std::{
+	collections::HashMap,
+	process::{Command, Stdio},
+	string::String,
+	vec::Vec,
+};
+
+use super::{io_errno, rooted_path, dpkg_file_owner_in, dpkg_package_in, list_dpkg_packages_in};
+
+/// An installed package, as reported by any [`PackageBackend`]
+#[derive(Clone, Debug)]
+pub struct InstalledPackage {
+	pub name: String,
+	/// The full version, including any epoch and release, like `1:2.39-1`
+	pub version: String,
+	pub architecture: String,
+}
+
+/// A system package manager's database of installed packages
+pub trait PackageBackend {
+	/// A short name for the backend, like `dpkg`
+	fn name(&self) -> &'static str;
+
+	/// List every installed package
+	fn list_installed(&self) -> Result<Vec<InstalledPackage>, i32>;
+
+	/// Get the installed package named exactly `name`
+	fn package(&self, name: &str) -> Result<Option<InstalledPackage>, i32> {
+		Ok(self.list_installed()?.into_iter().find(|p| p.name == name))
+	}
+
+	/// Check if the package named exactly `name` is installed
+	fn is_installed(&self, name: &str) -> Result<bool, i32> {
+		Ok(self.package(name)?.is_some())
+	}
+
+	/// Check if the file at `path`, a path on the system rather than under any root, belongs to an installed package
+	fn owns_file(&self, path: &str) -> Result<bool, i32>;
+}
+
+/// The dpkg database used by Debian, Ubuntu and Mint, read from /var/lib/dpkg/status
+pub struct DpkgBackend {
+	root: String,
+}
+
+impl DpkgBackend {
+	/// Create a backend reading the live system
+	pub fn new() -> DpkgBackend {
+		DpkgBackend::new_in(&"/")
+	}
+
+	/// Create a backend reading under the root directory `root`
+	pub fn new_in<T: ToString>(root: &T) -> DpkgBackend {
+		DpkgBackend { root: root.to_string() }
+	}
+}
+
+impl Default for DpkgBackend {
+	fn default() -> Self {
+		DpkgBackend::new()
+	}
+}
+
+impl PackageBackend for DpkgBackend {
+	fn name(&self) -> &'static str {
+		"dpkg"
+	}
+
+	fn list_installed(&self) -> Result<Vec<InstalledPackage>, i32> {
+		Ok(list_dpkg_packages_in(&self.root)?.into_iter()
+			.filter(|p| p.is_installed())
+			.map(|p| InstalledPackage { name: p.name, version: p.version, architecture: p.architecture })
+			.collect())
+	}
+
+	fn package(&self, name: &str) -> Result<Option<InstalledPackage>, i32> {
+		Ok(dpkg_package_in(&self.root, &name)?
+			.filter(|p| p.is_installed())
+			.map(|p| InstalledPackage { name: p.name, version: p.version, architecture: p.architecture }))
+	}
+
+	fn owns_file(&self, path: &str) -> Result<bool, i32> {
+		Ok(dpkg_file_owner_in(&self.root, &path)?.is_some())
+	}
+}
+
+/// The pacman local database used by Arch and its derivatives, read from /var/lib/pacman/local
+pub struct PacmanBackend {
+	root: String,
+}
+
+impl PacmanBackend {
+	/// Create a backend reading the live system
+	pub fn new() -> PacmanBackend {
+		PacmanBackend::new_in(&"/")
+	}
+
+	/// Create a backend reading under the root directory `root`
+	pub fn new_in<T: ToString>(root: &T) -> PacmanBackend {
+		PacmanBackend { root: root.to_string() }
+	}
+
+	/// Parse a `desc` file, where each `%FIELD%` header is followed by its values, one per line, up to a blank line
+	fn parse_desc(text: &str) -> HashMap<String, Vec<String>> {
+		let mut fields: HashMap<String, Vec<String>> = HashMap::new();
+		let mut current: Option<String> = None;
+
+		for line in text.lines() {
+			if line.is_empty() {
+				current = None;
+			} else if let Some(field) = line.strip_prefix('%').and_then(|l| l.strip_suffix('%')) {
+				current = Some(field.to_string());
+				fields.entry(field.to_string()).or_default();
+			} else if let Some(field) = current.as_ref() {
+				fields.entry(field.clone()).or_default().push(line.to_string());
+			}
+		}
+
+		fields
+	}
+}
+
+impl Default for PacmanBackend {
+	fn default() -> Self {
+		PacmanBackend::new()
+	}
+}
+
+impl PackageBackend for PacmanBackend {
+	fn name(&self) -> &'static str {
+		"pacman"
+	}
+
+	fn list_installed(&self) -> Result<Vec<InstalledPackage>, i32> {
+		let dir = std::fs::read_dir(rooted_path(&self.root, "/var/lib/pacman/local")).map_err(io_errno)?;
+		let mut packages = Vec::new();
+
+		for entry in dir.filter_map(|e| e.ok()) {
+			let text = match std::fs::read_to_string(entry.path().join("desc")) {
+				Ok(t) => t,
+				Err(_) => continue,
+			};
+
+			let fields = PacmanBackend::parse_desc(&text);
+			let first = |k: &str| fields.get(k).and_then(|v| v.first()).cloned();
+			if let (Some(name), Some(version)) = (first("NAME"), first("VERSION")) {
+				packages.push(InstalledPackage { name, version, architecture: first("ARCH").unwrap_or_default() });
+			}
+		}
+
+		packages.sort_by(|a, b| a.name.cmp(&b.name));
+		Ok(packages)
+	}
+
+	fn owns_file(&self, path: &str) -> Result<bool, i32> {
+		let dir = std::fs::read_dir(rooted_path(&self.root, "/var/lib/pacman/local")).map_err(io_errno)?;
+		let relative = path.trim_start_matches('/');
+
+		for entry in dir.filter_map(|e| e.ok()) {
+			if let Ok(text) = std::fs::read_to_string(entry.path().join("files")) {
+				if PacmanBackend::parse_desc(&text).get("FILES").is_some_and(|f| f.iter().any(|l| l == relative)) {
+					return Ok(true);
+				}
+			}
+		}
+
+		Ok(false)
+	}
+}
+
+/// The RPM database used by Fedora, RHEL and openSUSE, queried through the `rpm` command
+///
+/// The database itself is SQLite or Berkeley DB depending on the release, so `rpm --root` does the reading.
+pub struct RpmBackend {
+	root: String,
+}
+
+impl RpmBackend {
+	/// Create a backend reading the live system
+	pub fn new() -> RpmBackend {
+		RpmBackend::new_in(&"/")
+	}
+
+	/// Create a backend reading under the root directory `root`
+	pub fn new_in<T: ToString>(root: &T) -> RpmBackend {
+		RpmBackend { root: root.to_string() }
+	}
+}
+
+impl Default for RpmBackend {
+	fn default() -> Self {
+		RpmBackend::new()
+	}
+}
+
+impl PackageBackend for RpmBackend {
+	fn name(&self) -> &'static str {
+		"rpm"
+	}
+
+	fn list_installed(&self) -> Result<Vec<InstalledPackage>, i32> {
+		let output = Command::new("rpm")
+			.args(["--root", self.root.as_str(), "-qa", "--qf", "%{NAME}\\t%{EPOCH}\\t%{VERSION}-%{RELEASE}\\t%{ARCH}\\n"])
+			.stderr(Stdio::null()).stdout(Stdio::piped())
+			.output().map_err(io_errno)?;
+
+		if !output.status.success() {
+			return Err(-1);
+		}
+
+		Ok(String::from_utf8_lossy(&output.stdout).lines()
+			.filter_map(|l| {
+				let cols: Vec<&str> = l.split('\t').collect();
+				if cols.len() != 4 {
+					return None;
+				}
+
+				let version = match cols[1] {
+					"(none)" | "" => cols[2].to_string(),
+					epoch => format!("{}:{}", epoch, cols[2]),
+				};
+				Some(InstalledPackage { name: cols[0].to_string(), version, architecture: cols[3].to_string() })
+			})
+			.collect())
+	}
+
+	fn owns_file(&self, path: &str) -> Result<bool, i32> {
+		let status = Command::new("rpm")
+			.args(["--root", self.root.as_str(), "-qf", path])
+			.stderr(Stdio::null()).stdout(Stdio::null())
+			.status().map_err(io_errno)?;
+
+		Ok(status.success())
+	}
+}
+
+/// Read the fields of /etc/os-release, falling back to /usr/lib/os-release
+pub fn read_os_release() -> Result<HashMap<String, String>, i32> {
+	read_os_release_in(&"/")
+}
+
+/// Read the fields of os-release under the root directory `root`
+pub fn read_os_release_in<T: ToString>(root: &T) -> Result<HashMap<String, String>, i32> {
+	let text = std::fs::read_to_string(rooted_path(root, "/etc/os-release"))
+		.or_else(|_| std::fs::read_to_string(rooted_path(root, "/usr/lib/os-release")))
+		.map_err(io_errno)?;
+
+	Ok(text.lines()
+		.map(|l| l.trim())
+		.filter(|l| !l.is_empty() && !l.starts_with('#'))
+		.filter_map(|l| l.split_once('='))
+		.map(|(k, v)| (k.to_string(), v.trim_matches(['"', '\'']).to_string()))
+		.collect())
+}
+
+/// Pick the package backend for the live system
+pub fn detect_package_backend() -> Option<Box<dyn PackageBackend>> {
+	detect_package_backend_in(&"/")
+}
+
+/// Pick the package backend for the system under the root directory `root`
+///
+/// The distribution's `ID` and `ID_LIKE` in os-release decide. If those are missing or unrecognized, whichever
+/// database directory exists is used. Returns [`None`] if nothing fits.
+pub fn detect_package_backend_in<T: ToString>(root: &T) -> Option<Box<dyn PackageBackend>> {
+	let os = read_os_release_in(root).unwrap_or_default();
+	let ids: Vec<String> = os.get("ID").into_iter()
+		.chain(os.get("ID_LIKE"))
+		.flat_map(|v| v.split_whitespace().map(|s| s.to_lowercase()).collect::<Vec<_>>())
+		.collect();
+
+	for id in &ids {
+		match id.as_str() {
+			"debian" | "ubuntu" => return Some(Box::new(DpkgBackend::new_in(root))),
+			"arch" => return Some(Box::new(PacmanBackend::new_in(root))),
+			"fedora" | "rhel" | "centos" | "suse" | "opensuse" => return Some(Box::new(RpmBackend::new_in(root))),
+			_ => (),
+		}
+	}
+
+	if rooted_path(root, "/var/lib/dpkg/status").exists() {
+		Some(Box::new(DpkgBackend::new_in(root)))
+	} else if rooted_path(root, "/var/lib/pacman/local").is_dir() {
+		Some(Box::new(PacmanBackend::new_in(root)))
+	} else if rooted_path(root, "/var/lib/rpm").is_dir() || rooted_path(root, "/usr/lib/sysimage/rpm").is_dir() {
+		Some(Box::new(RpmBackend::new_in(root)))
+	} else {
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+
+	#[test]
+	fn pacman_database() {
+		let desc = "\
+%NAME%
+openssh
+
+%VERSION%
+9.7p1-2
+
+%DEPENDS%
+glibc
+krb5
+openssl
+
+%ARCH%
+x86_64
+";
+		let fields = PacmanBackend::parse_desc(desc);
+		assert_eq!(fields["NAME"], ["openssh"]);
+		assert_eq!(fields["DEPENDS"], ["glibc", "krb5", "openssl"]);
+		assert!(!fields.contains_key("LICENSE"));
+
+		let root = test_root("packages-pacman");
+		write_rooted(&root, "/var/lib/pacman/local/openssh-9.7p1-2/desc", desc);
+		write_rooted(&root, "/var/lib/pacman/local/openssh-9.7p1-2/files", "%FILES%\nusr/\nusr/bin/\nusr/bin/ssh\n\n%BACKUP%\netc/ssh/sshd_config\t0b1d\n");
+		write_rooted(&root, "/var/lib/pacman/local/nmap-7.95-1/desc", "%NAME%\nnmap\n\n%VERSION%\n7.95-1\n");
+		write_rooted(&root, "/var/lib/pacman/local/ALPM_DB_VERSION", "9\n");
+
+		let backend = PacmanBackend::new_in(&root.display());
+		let installed: Vec<(String, String, String)> = backend.list_installed().unwrap().into_iter().map(|p| (p.name, p.version, p.architecture)).collect();
+		assert_eq!(installed, [
+			("nmap".to_string(), "7.95-1".to_string(), String::new()),
+			("openssh".to_string(), "9.7p1-2".to_string(), "x86_64".to_string()),
+		]);
+		assert!(backend.is_installed("nmap").unwrap() && !backend.is_installed("john").unwrap());
+		assert!(backend.owns_file("/usr/bin/ssh").unwrap());
+		assert!(!backend.owns_file("/etc/ssh/sshd_config").unwrap());
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn os_release_and_backend() {
+		let root = test_root("packages-os-release");
+		write_rooted(&root, "/usr/lib/os-release", "\
+# Linux Mint
+NAME=\"Linux Mint\"
+VERSION='21.3 (Virginia)'
+ID=linuxmint
+ID_LIKE=\"ubuntu debian\"
+PRETTY_NAME=\"Linux Mint 21.3\"
+");
+
+		let os = read_os_release_in(&root.display()).unwrap();
+		assert_eq!(os["NAME"], "Linux Mint");
+		assert_eq!(os["VERSION"], "21.3 (Virginia)");
+		assert_eq!(os["ID_LIKE"], "ubuntu debian");
+		assert_eq!(detect_package_backend_in(&root.display()).map(|b| b.name()), Some("dpkg"));
+
+		// /etc/os-release wins over /usr/lib/os-release
+		write_rooted(&root, "/etc/os-release", "NAME=\"Manjaro Linux\"\nID=manjaro\nID_LIKE=arch\n");
+		assert_eq!(read_os_release_in(&root.display()).unwrap()["ID"], "manjaro");
+		assert_eq!(detect_package_backend_in(&root.display()).map(|b| b.name()), Some("pacman"));
+
+		write_rooted(&root, "/etc/os-release", "ID=\"opensuse-tumbleweed\"\nID_LIKE=\"opensuse suse\"\n");
+		assert_eq!(detect_package_backend_in(&root.display()).map(|b| b.name()), Some("rpm"));
+
+		// Unknown distributions fall back to whichever database is there
+		write_rooted(&root, "/etc/os-release", "ID=homebrew-linux\n");
+		assert!(detect_package_backend_in(&root.display()).is_none());
+		write_rooted(&root, "/var/lib/pacman/local/ALPM_DB_VERSION", "9\n");
+		assert_eq!(detect_package_backend_in(&root.display()).map(|b| b.name()), Some("pacman"));
+		write_rooted(&root, "/var/lib/dpkg/status", "");
+		assert_eq!(detect_package_backend_in(&root.display()).map(|b| b.name()), Some("dpkg"));
+
+		std::fs::remove_dir_all(&root).unwrap();
+		assert_eq!(read_os_release_in(&root.display()).err(), Some(libc::ENOENT));
+	}
+}
@@ -0,0 +1,546 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+//! # PAM configuration
+//!
+//! Parsers for PAM service stacks in /etc/pam.d, and the `key = value` files under /etc/security
+//! that some modules read, such as pwquality.conf and faillock.conf.
+//! [`PamPolicy`] puts them together to answer the usual password policy questions.
+
+use std::{
+	collections::HashMap,
+	path::PathBuf,
+	string::String,
+	vec::Vec,
+};
+
+use super::{io_errno, rooted_path};
+
+/// Deepest chain of includes followed before giving up, in case of loops
+const MAX_INCLUDE_DEPTH: usize = 32;
+
+/// The management group a PAM entry belongs to
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PamType {
+	Auth,
+	Account,
+	Password,
+	Session,
+}
+
+impl PamType {
+	fn parse(s: &str) -> Option<PamType> {
+		match s.to_lowercase().as_str() {
+			"auth" => Some(PamType::Auth),
+			"account" => Some(PamType::Account),
+			"password" => Some(PamType::Password),
+			"session" => Some(PamType::Session),
+			_ => None,
+		}
+	}
+}
+
+/// The control field of a PAM entry
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum PamControl {
+	Required,
+	Requisite,
+	Sufficient,
+	Optional,
+	Include,
+	Substack,
+	/// The `[value=action ...]` syntax, as the pairs inside the brackets
+	Complex(Vec<(String, String)>),
+}
+
+impl PamControl {
+	fn parse(s: &str) -> Option<PamControl> {
+		if let Some(inner) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+			return Some(PamControl::Complex(inner.split_whitespace()
+				.map(|pair| match pair.split_once('=') {
+					Some((v, a)) => (v.to_string(), a.to_string()),
+					None => (pair.to_string(), String::new()),
+				})
+				.collect()));
+		}
+
+		match s {
+			"required" => Some(PamControl::Required),
+			"requisite" => Some(PamControl::Requisite),
+			"sufficient" => Some(PamControl::Sufficient),
+			"optional" => Some(PamControl::Optional),
+			"include" => Some(PamControl::Include),
+			"substack" => Some(PamControl::Substack),
+			_ => None,
+		}
+	}
+}
+
+/// A single line of a PAM stack
+#[derive(Clone, Debug)]
+pub struct PamEntry {
+	/// The file the entry was read from, which may be an included file
+	pub file: PathBuf,
+	pub line: usize,
+	pub pam_type: PamType,
+	/// Set if the type was prefixed with `-`, so a missing module is silently skipped
+	pub ignore_missing: bool,
+	pub control: PamControl,
+	pub module: String,
+	pub args: Vec<String>,
+}
+
+impl PamEntry {
+	/// The module name without any directory or `.so`, so `/lib/security/pam_unix.so` becomes `pam_unix`
+	pub fn module_name(&self) -> &str {
+		let base = self.module.rsplit('/').next().unwrap_or(&self.module);
+		base.strip_suffix(".so").unwrap_or(base)
+	}
+
+	/// Get the value of a `key=value` argument, the last one wins if it is given more than once
+	pub fn arg<T: ToString>(&self, key: &T) -> Option<String> {
+		let key = key.to_string();
+		self.args.iter().rev()
+			.filter_map(|a| a.split_once('='))
+			.find(|(k, _)| *k == key)
+			.map(|(_, v)| v.to_string())
+	}
+
+	/// Check if a bare argument, like `nullok` or `use_authtok`, is present
+	pub fn has_flag<T: ToString>(&self, flag: &T) -> bool {
+		let flag = flag.to_string();
+		self.args.contains(&flag)
+	}
+}
+
+/// Split a PAM line into fields, keeping `[...]` groups, which may contain spaces, together
+fn tokenize(line: &str) -> Vec<String> {
+	let mut tokens = Vec::new();
+	let mut cur = String::new();
+	let mut in_brackets = false;
+	let mut chars = line.chars().peekable();
+
+	while let Some(c) = chars.next() {
+		match c {
+			'\\' if in_brackets && chars.peek() == Some(&']') => {
+				cur.push(']');
+				chars.next();
+			},
+			'[' if !in_brackets => {
+				in_brackets = true;
+				cur.push(c);
+			},
+			']' if in_brackets => {
+				in_brackets = false;
+				cur.push(c);
+			},
+			c if c.is_whitespace() && !in_brackets => {
+				if !cur.is_empty() {
+					tokens.push(std::mem::take(&mut cur));
+				}
+			},
+			c => cur.push(c),
+		}
+	}
+
+	if !cur.is_empty() {
+		tokens.push(cur);
+	}
+
+	tokens
+}
+
+/// A PAM service stack, such as /etc/pam.d/common-password, with every include expanded in place
+#[derive(Clone, Default)]
+pub struct PamStack {
+	pub service: String,
+	pub entries: Vec<PamEntry>,
+}
+
+impl PamStack {
+	/// Load the stack for `service` from /etc/pam.d
+	pub fn load<T: ToString>(service: &T) -> Result<PamStack, i32> {
+		PamStack::load_in(&"/", service)
+	}
+
+	/// Load the stack for `service` from /etc/pam.d under the root directory `root`
+	///
+	/// Lines that can't be parsed are skipped, as PAM does.
+	pub fn load_in<A: ToString, B: ToString>(root: &A, service: &B) -> Result<PamStack, i32> {
+		Ok(PamStack {
+			service: service.to_string(),
+			entries: load_service(&root.to_string(), &service.to_string(), None, 0)?,
+		})
+	}
+
+	/// Get the entries of one type, in order
+	pub fn entries_of(&self, pam_type: PamType) -> impl Iterator<Item = &PamEntry> {
+		self.entries.iter().filter(move |e| e.pam_type == pam_type)
+	}
+
+	/// Find the first entry of `pam_type` using the module named `module`, like `pam_unix`
+	pub fn find_module<T: ToString>(&self, pam_type: PamType, module: &T) -> Option<&PamEntry> {
+		let module = module.to_string();
+		self.entries_of(pam_type).find(|e| e.module_name() == module)
+	}
+
+	/// Check if the module named `module` appears anywhere in the stack
+	pub fn has_module<T: ToString>(&self, module: &T) -> bool {
+		let module = module.to_string();
+		self.entries.iter().any(|e| e.module_name() == module)
+	}
+}
+
+/// Read the entries of a service file under `root`, expanding includes, keeping only `only` if given
+fn load_service(root: &String, service: &str, only: Option<PamType>, depth: usize) -> Result<Vec<PamEntry>, i32> {
+	if depth > MAX_INCLUDE_DEPTH {
+		return Err(libc::ELOOP);
+	}
+
+	let path = if service.starts_with('/') {
+		rooted_path(root, service)
+	} else {
+		rooted_path(root, "/etc/pam.d").join(service)
+	};
+	let contents = std::fs::read_to_string(&path).map_err(io_errno)?;
+	let mut entries = Vec::new();
+	let mut logical = String::new();
+	let mut start = 0;
+
+	for (idx, raw) in contents.lines().enumerate() {
+		if logical.is_empty() {
+			start = idx + 1;
+		}
+
+		if let Some(stripped) = raw.strip_suffix('\\') {
+			logical.push_str(stripped);
+			logical.push(' ');
+			continue;
+		}
+		logical.push_str(raw);
+
+		let line = std::mem::take(&mut logical);
+		let line = line.split('#').next().unwrap_or("").trim();
+		if line.is_empty() {
+			continue;
+		}
+
+		let tokens = tokenize(line);
+
+		if tokens[0] == "@include" {
+			if let Some(file) = tokens.get(1) {
+				entries.extend(load_service(root, file, only, depth + 1)?);
+			}
+			continue;
+		}
+
+		// libpam logs and skips lines it can't make sense of, so one bad line doesn't lose the rest of the stack
+		if tokens.len() < 3 {
+			continue;
+		}
+
+		let (ignore_missing, type_str) = match tokens[0].strip_prefix('-') {
+			Some(t) => (true, t),
+			None => (false, tokens[0].as_str()),
+		};
+		let (pam_type, control) = match (PamType::parse(type_str), PamControl::parse(&tokens[1])) {
+			(Some(t), Some(c)) => (t, c),
+			_ => continue,
+		};
+
+		if only.is_some_and(|t| t != pam_type) {
+			continue;
+		}
+
+		if control == PamControl::Include || control == PamControl::Substack {
+			entries.extend(load_service(root, &tokens[2], Some(pam_type), depth + 1)?);
+			continue;
+		}
+
+		entries.push(PamEntry {
+			file: path.clone(),
+			line: start,
+			pam_type,
+			ignore_missing,
+			control,
+			module: tokens[2].clone(),
+			args: tokens[3..].to_vec(),
+		});
+	}
+
+	Ok(entries)
+}
+
+/// A `key = value` configuration file, like /etc/security/pwquality.conf or faillock.conf
+///
+/// Bare keys, like `enforce_for_root`, are stored with a value of [`None`].
+#[derive(Clone, Default)]
+pub struct PamConf {
+	pub values: HashMap<String, Option<String>>,
+}
+
+impl PamConf {
+	/// Parse the contents of a configuration file
+	pub fn parse<T: ToString>(text: &T) -> PamConf {
+		let mut conf = PamConf::default();
+		conf.merge(&text.to_string());
+		conf
+	}
+
+	/// Parse the configuration file at `path`
+	pub fn parse_file<T: ToString>(path: &T) -> Result<PamConf, i32> {
+		Ok(PamConf::parse(&std::fs::read_to_string(path.to_string()).map_err(io_errno)?))
+	}
+
+	/// Load a configuration file from under `root`
+	///
+	/// A missing file is treated as empty, since every setting then has its built in default.
+	fn load_in<T: ToString>(root: &T, path: &str) -> PamConf {
+		let mut conf = PamConf::default();
+		if let Ok(text) = std::fs::read_to_string(rooted_path(root, path)) {
+			conf.merge(&text);
+		}
+		conf
+	}
+
+	/// Load a configuration file from under `root`, then the `*.conf` files in the matching `.d` directory
+	///
+	/// This is how libpwquality reads its configuration: the drop-ins are merged in alphabetical order,
+	/// each overriding the main file and the drop-ins before it. Hidden files and anything but regular files are skipped.
+	fn load_with_dropins_in<T: ToString>(root: &T, path: &str) -> PamConf {
+		let mut conf = PamConf::load_in(root, path);
+
+		let dropin_dir = rooted_path(root, &format!("{}.d", path));
+		if let Ok(rd) = std::fs::read_dir(dropin_dir) {
+			let mut files: Vec<PathBuf> = rd.filter_map(|e| e.ok()).map(|e| e.path())
+				.filter(|p| p.is_file() && p.extension().is_some_and(|e| e == "conf"))
+				.filter(|p| !p.file_name().is_some_and(|n| n.to_string_lossy().starts_with('.')))
+				.collect();
+			files.sort();
+
+			for file in files {
+				if let Ok(text) = std::fs::read_to_string(file) {
+					conf.merge(&text);
+				}
+			}
+		}
+
+		conf
+	}
+
+	fn merge(&mut self, text: &str) {
+		for line in text.lines() {
+			let line = line.split('#').next().unwrap_or("").trim();
+			if line.is_empty() {
+				continue;
+			}
+
+			match line.split_once('=') {
+				Some((k, v)) => self.values.insert(k.trim().to_string(), Some(v.trim().to_string())),
+				None => self.values.insert(line.to_string(), None),
+			};
+		}
+	}
+
+	/// Get the value of `key`, if it was set with a value
+	pub fn get<T: ToString>(&self, key: &T) -> Option<String> {
+		self.values.get(&key.to_string()).cloned().flatten()
+	}
+
+	/// Check if `key` is present, with or without a value
+	pub fn is_set<T: ToString>(&self, key: &T) -> bool {
+		self.values.contains_key(&key.to_string())
+	}
+}
+
+/// The password policy as set by the Debian style common-password and common-auth stacks, pwquality.conf and faillock.conf
+#[derive(Clone, Default)]
+pub struct PamPolicy {
+	pub password: PamStack,
+	pub auth: PamStack,
+	pub pwquality: PamConf,
+	pub faillock: PamConf,
+}
+
+impl PamPolicy {
+	/// Load the password policy from the live system
+	pub fn load() -> Result<PamPolicy, i32> {
+		PamPolicy::load_in(&"/")
+	}
+
+	/// Load the password policy from under the root directory `root`
+	///
+	/// pwquality.conf is merged with /etc/security/pwquality.conf.d/*.conf like libpwquality does,
+	/// while faillock.conf is read alone, as pam_faillock has no drop-in directory.
+	pub fn load_in<T: ToString>(root: &T) -> Result<PamPolicy, i32> {
+		Ok(PamPolicy {
+			password: PamStack::load_in(root, &"common-password")?,
+			auth: PamStack::load_in(root, &"common-auth")?,
+			pwquality: PamConf::load_with_dropins_in(root, "/etc/security/pwquality.conf"),
+			faillock: PamConf::load_in(root, "/etc/security/faillock.conf"),
+		})
+	}
+
+	/// Get a pam_pwquality (or pam_cracklib) option, module arguments first, then pwquality.conf
+	///
+	/// Returns [`None`] if neither module is in the password stack, since the setting then does nothing.
+	pub fn pwquality_option<T: ToString>(&self, key: &T) -> Option<String> {
+		let entry = self.password.find_module(PamType::Password, &"pam_pwquality")
+			.or_else(|| self.password.find_module(PamType::Password, &"pam_cracklib"))?;
+
+		entry.arg(key).or_else(|| {
+			if entry.module_name() == "pam_pwquality" { self.pwquality.get(key) } else { None }
+		})
+	}
+
+	/// Get the effective minimum password length from pam_pwquality or pam_cracklib
+	pub fn pwquality_minlen(&self) -> Option<i64> {
+		self.pwquality_option(&"minlen").and_then(|v| v.parse().ok())
+	}
+
+	/// Get how many old passwords are remembered, from pam_unix or pam_pwhistory
+	pub fn unix_remember(&self) -> Option<i64> {
+		self.password.entries_of(PamType::Password)
+			.filter(|e| e.module_name() == "pam_unix" || e.module_name() == "pam_pwhistory")
+			.find_map(|e| e.arg(&"remember"))
+			.and_then(|v| v.parse().ok())
+	}
+
+	/// Check if any pam_unix entry allows empty passwords with `nullok` or `nullok_secure`
+	pub fn nullok(&self) -> bool {
+		self.password.entries.iter().chain(self.auth.entries.iter())
+			.filter(|e| e.module_name() == "pam_unix")
+			.any(|e| e.has_flag(&"nullok") || e.has_flag(&"nullok_secure"))
+	}
+
+	/// Get a pam_faillock option, module arguments first, then faillock.conf
+	///
+	/// Returns [`None`] if pam_faillock isn't in the auth stack, since the setting then does nothing.
+	pub fn faillock_option<T: ToString>(&self, key: &T) -> Option<String> {
+		let mut entries = self.auth.entries_of(PamType::Auth)
+			.filter(|e| e.module_name() == "pam_faillock")
+			.peekable();
+
+		entries.peek()?;
+		entries.find_map(|e| e.arg(key)).or_else(|| self.faillock.get(key))
+	}
+
+	/// Get how many failed logins lock an account, from pam_faillock or the older pam_tally2
+	pub fn faillock_deny(&self) -> Option<i64> {
+		self.faillock_option(&"deny")
+			.or_else(|| {
+				self.auth.entries_of(PamType::Auth)
+					.filter(|e| e.module_name() == "pam_tally2")
+					.find_map(|e| e.arg(&"deny"))
+			})
+			.and_then(|v| v.parse().ok())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+
+	#[test]
+	fn malformed_lines_are_skipped() {
+		let root = test_root("pam-malformed");
+		write_rooted(&root, "/etc/pam.d/common-auth", "\
+auth [success=1 default=ignore] pam_unix.so nullok
+auth requisite
+bogus required pam_deny.so
+auth required pam_permit.so
+");
+		write_rooted(&root, "/etc/pam.d/login", "\
+@include common-auth
+account required pam_nologin.so
+");
+
+		let stack = PamStack::load_in(&root.display(), &"login").unwrap();
+		let modules: Vec<&str> = stack.entries.iter().map(|e| e.module_name()).collect();
+		assert_eq!(modules, ["pam_unix", "pam_permit", "pam_nologin"]);
+		assert_eq!(stack.entries[1].line, 4);
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn policy_from_debian_stacks() {
+		let root = test_root("pam-policy");
+		write_rooted(&root, "/etc/pam.d/common-password", "\
+# here are the per-package modules (the \"Primary\" block)
+password\trequisite\t\t\tpam_pwquality.so retry=3 minlen=10
+password\t[success=1 default=ignore]\tpam_unix.so obscure use_authtok try_first_pass yescrypt remember=5
+password\trequisite\t\t\tpam_deny.so
+password\trequired\t\t\tpam_permit.so
+");
+		write_rooted(&root, "/etc/pam.d/common-auth", "\
+auth\trequired\t\t\tpam_faillock.so preauth
+auth\t[success=2 default=ignore]\tpam_unix.so nullok
+@include common-faillock
+auth\trequisite\t\t\tpam_deny.so
+");
+		write_rooted(&root, "/etc/pam.d/common-faillock", "\
+auth\t[default=die]\tpam_faillock.so authfail deny=4
+auth\tsufficient\tpam_faillock.so authsucc
+");
+		write_rooted(&root, "/etc/security/pwquality.conf", "minlen = 8\ndcredit = -1\nenforce_for_root\n");
+		write_rooted(&root, "/etc/security/pwquality.conf.d/50-lab.conf", "dcredit = -2 # stricter\nucredit = -1\n");
+		write_rooted(&root, "/etc/security/pwquality.conf.d/10-base.conf", "ucredit = 0\nlcredit = -1\n");
+		write_rooted(&root, "/etc/security/pwquality.conf.d/README", "lcredit = 5\n");
+		write_rooted(&root, "/etc/security/faillock.conf", "deny = 3\nunlock_time = 600\n");
+
+		let policy = PamPolicy::load_in(&root.display()).unwrap();
+
+		let unix = policy.password.find_module(PamType::Password, &"pam_unix").unwrap();
+		assert_eq!(unix.control, PamControl::Complex(vec![
+			("success".to_string(), "1".to_string()),
+			("default".to_string(), "ignore".to_string()),
+		]));
+		assert!(unix.has_flag(&"yescrypt") && !unix.has_flag(&"remember"));
+		assert_eq!(policy.unix_remember(), Some(5));
+
+		// Module arguments win over the config files
+		assert_eq!(policy.pwquality_minlen(), Some(10));
+		assert_eq!(policy.pwquality_option(&"dcredit").as_deref(), Some("-2"));
+		assert_eq!(policy.pwquality_option(&"ucredit").as_deref(), Some("-1"));
+		assert_eq!(policy.pwquality_option(&"lcredit").as_deref(), Some("-1"));
+		assert!(policy.pwquality.is_set(&"enforce_for_root"));
+
+		// The included stack lands in place, and its deny= beats faillock.conf
+		let modules: Vec<&str> = policy.auth.entries.iter().map(|e| e.module_name()).collect();
+		assert_eq!(modules, ["pam_faillock", "pam_unix", "pam_faillock", "pam_faillock", "pam_deny"]);
+		assert!(policy.auth.entries[2].file.ends_with("etc/pam.d/common-faillock"));
+		assert_eq!(policy.faillock_deny(), Some(4));
+		assert_eq!(policy.faillock_option(&"unlock_time").as_deref(), Some("600"));
+		assert!(policy.nullok());
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn policy_defaults_and_tally2() {
+		let root = test_root("pam-tally2");
+		write_rooted(&root, "/etc/pam.d/common-password", "\
+password required pam_pwhistory.so remember=24
+password required pam_unix.so sha512
+");
+		write_rooted(&root, "/etc/pam.d/common-auth", "\
+auth required pam_tally2.so onerr=fail deny=5
+auth required pam_unix.so
+");
+		write_rooted(&root, "/etc/security/faillock.conf", "deny = 3\n");
+
+		let policy = PamPolicy::load_in(&root.display()).unwrap();
+		assert_eq!(policy.unix_remember(), Some(24));
+		assert!(!policy.nullok());
+		// faillock.conf does nothing without pam_faillock in the stack
+		assert_eq!(policy.faillock_deny(), Some(5));
+		assert_eq!(policy.pwquality_minlen(), None);
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+}
@@ -0,0 +1,293 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+//! # Processes
+//!
+//! Reads running processes from /proc, for finding backdoors, keyloggers and miners left running on an image.
+//! Everything has an `_in` variant reading `<root>/proc`, so a fake /proc tree works as well as the real one.
+
+use std::{
+	path::{Path, PathBuf},
+	string::String,
+	vec::Vec,
+};
+
+use libc::{gid_t, uid_t};
+
+use super::{io_errno, rooted_path, list_users_in};
+
+/// What the kernel appends to the target of /proc/<pid>/exe when the binary was deleted after starting
+const DELETED_SUFFIX: &str = " (deleted)";
+
+/// A running process
+#[derive(Clone, Debug)]
+pub struct ProcessInfo {
+	pub pid: u32,
+	pub ppid: u32,
+	/// The kernel's name for the process, which is the binary name cut to 15 characters unless the process changed it
+	pub name: String,
+	/// The state letter, like `R` for running or `Z` for zombie
+	pub state: char,
+	/// The arguments, starting with the program, empty for kernel threads and zombies
+	pub cmdline: Vec<String>,
+	/// The real UID
+	pub uid: uid_t,
+	pub euid: uid_t,
+	pub gid: gid_t,
+	/// The binary the process runs, [`None`] if it can't be read, as for kernel threads or without permission
+	pub exe: Option<PathBuf>,
+	/// Set if the binary was deleted after the process started, a common way to hide malware
+	pub exe_deleted: bool,
+}
+
+impl ProcessInfo {
+	/// Parse the contents of /proc/<pid>/stat, /proc/<pid>/status and /proc/<pid>/cmdline, and the target of /proc/<pid>/exe
+	///
+	/// Returns `Err(-1)` if the stat or status files are malformed.
+	pub fn parse(stat: &str, status: &str, cmdline: &[u8], exe: Option<PathBuf>) -> Result<ProcessInfo, i32> {
+		// The name is in parentheses and can hold spaces and parentheses itself, so split around the last `)`
+		let open = stat.find('(').ok_or(-1)?;
+		let close = stat.rfind(')').ok_or(-1)?;
+		if close < open {
+			return Err(-1);
+		}
+
+		let pid = stat[..open].trim().parse().map_err(|_| -1)?;
+		let name = stat[open + 1..close].to_string();
+		let fields: Vec<&str> = stat[close + 1..].split_whitespace().collect();
+		let state = fields.first().and_then(|s| s.chars().next()).ok_or(-1)?;
+		let ppid = fields.get(1).ok_or(-1)?.parse().map_err(|_| -1)?;
+
+		let ids = |key: &str| -> Result<Vec<u32>, i32> {
+			let line = status.lines().find_map(|l| l.strip_prefix(key)).ok_or(-1)?;
+			line.split_whitespace().map(|v| v.parse().map_err(|_| -1)).collect()
+		};
+		let uids = ids("Uid:")?;
+		let gids = ids("Gid:")?;
+
+		let cmdline = cmdline.split(|b| *b == 0)
+			.map(|a| String::from_utf8_lossy(a).to_string())
+			.collect::<Vec<_>>();
+		// The arguments end with a NUL, which leaves an empty string at the end
+		let cmdline = match cmdline.split_last() {
+			Some((last, rest)) if last.is_empty() => rest.to_vec(),
+			_ => cmdline,
+		};
+
+		let (exe, exe_deleted) = match exe {
+			Some(path) => match path.to_string_lossy().strip_suffix(DELETED_SUFFIX) {
+				Some(p) => (Some(PathBuf::from(p)), true),
+				None => (Some(path), false),
+			},
+			None => (None, false),
+		};
+
+		Ok(ProcessInfo {
+			pid,
+			ppid,
+			name,
+			state,
+			cmdline,
+			uid: *uids.first().ok_or(-1)?,
+			euid: *uids.get(1).ok_or(-1)?,
+			gid: *gids.first().ok_or(-1)?,
+			exe,
+			exe_deleted,
+		})
+	}
+
+	/// Check if the process is the program `name`
+	///
+	/// This compares against the process name, the binary's file name and the program in the command line,
+	/// since the process name is cut short and anything but the binary can be changed by the process.
+	pub fn matches<T: ToString>(&self, name: &T) -> bool {
+		let name = name.to_string();
+		let file_name = |p: &Path| p.file_name().is_some_and(|f| f.to_string_lossy() == name);
+
+		self.name == name
+			|| self.exe.as_ref().is_some_and(|e| file_name(e))
+			|| self.cmdline.first().is_some_and(|a| file_name(Path::new(a)))
+	}
+
+	/// Check if the process is a kernel thread, which has no binary or command line
+	pub fn is_kernel_thread(&self) -> bool {
+		self.cmdline.is_empty() && self.exe.is_none() && self.state != 'Z'
+	}
+}
+
+/// Read the process with the ID `pid`
+pub fn read_process(pid: u32) -> Result<ProcessInfo, i32> {
+	read_process_in(&"/", pid)
+}
+
+/// Read the process with the ID `pid` from /proc under the root directory `root`
+///
+/// Returns `Err(ENOENT)` if there's no such process.
+pub fn read_process_in<T: ToString>(root: &T, pid: u32) -> Result<ProcessInfo, i32> {
+	let dir = rooted_path(root, &format!("/proc/{}", pid));
+
+	let stat = std::fs::read_to_string(dir.join("stat")).map_err(io_errno)?;
+	let status = std::fs::read_to_string(dir.join("status")).map_err(io_errno)?;
+	let cmdline = std::fs::read(dir.join("cmdline")).unwrap_or_default();
+	let exe = std::fs::read_link(dir.join("exe")).ok();
+
+	ProcessInfo::parse(&stat, &status, &cmdline, exe)
+}
+
+/// List every running process
+pub fn list_processes() -> Result<Vec<ProcessInfo>, i32> {
+	list_processes_in(&"/")
+}
+
+/// List every process in /proc under the root directory `root`, in PID order
+///
+/// Processes that exit while being read are skipped.
+pub fn list_processes_in<T: ToString>(root: &T) -> Result<Vec<ProcessInfo>, i32> {
+	let dir = std::fs::read_dir(rooted_path(root, "/proc")).map_err(io_errno)?;
+	let mut pids: Vec<u32> = dir.filter_map(|e| e.ok())
+		.filter_map(|e| e.file_name().to_string_lossy().parse().ok())
+		.collect();
+	pids.sort();
+
+	Ok(pids.into_iter().filter_map(|pid| read_process_in(root, pid).ok()).collect())
+}
+
+/// List the running processes of the program `name`, as matched by [`ProcessInfo::matches`]
+pub fn find_processes<T: ToString>(name: &T) -> Result<Vec<ProcessInfo>, i32> {
+	find_processes_in(&"/", name)
+}
+
+/// List the processes of the program `name` in /proc under the root directory `root`
+pub fn find_processes_in<A: ToString, B: ToString>(root: &A, name: &B) -> Result<Vec<ProcessInfo>, i32> {
+	Ok(list_processes_in(root)?.into_iter().filter(|p| p.matches(name)).collect())
+}
+
+/// Check if the program `name` is running
+pub fn process_running<T: ToString>(name: &T) -> Result<bool, i32> {
+	process_running_in(&"/", name)
+}
+
+/// Check if the program `name` is running, reading /proc under the root directory `root`
+pub fn process_running_in<A: ToString, B: ToString>(root: &A, name: &B) -> Result<bool, i32> {
+	Ok(!find_processes_in(root, name)?.is_empty())
+}
+
+/// List the processes running as the user `name`
+pub fn processes_of_user<T: ToString>(name: &T) -> Result<Vec<ProcessInfo>, i32> {
+	processes_of_user_in(&"/", name)
+}
+
+/// List the processes running as the user `name` under the root directory `root`, by real or effective UID
+///
+/// The user is looked up in the passwd file under `root`. Returns `Err(ENOENT)` if there's no such user.
+pub fn processes_of_user_in<A: ToString, B: ToString>(root: &A, name: &B) -> Result<Vec<ProcessInfo>, i32> {
+	let name = name.to_string();
+	let uid = list_users_in(root)?.into_iter()
+		.find(|u| u.username == name)
+		.ok_or(libc::ENOENT)?
+		.uid;
+
+	Ok(list_processes_in(root)?.into_iter().filter(|p| p.uid == uid || p.euid == uid).collect())
+}
+
+/// List the processes whose binary was deleted after they started
+pub fn processes_with_deleted_exe() -> Result<Vec<ProcessInfo>, i32> {
+	processes_with_deleted_exe_in(&"/")
+}
+
+/// List the processes in /proc under the root directory `root` whose binary was deleted after they started
+pub fn processes_with_deleted_exe_in<T: ToString>(root: &T) -> Result<Vec<ProcessInfo>, i32> {
+	Ok(list_processes_in(root)?.into_iter().filter(|p| p.exe_deleted).collect())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+
+	/// Write /proc/<pid> with the given name, owner and arguments, and `exe` as the target of the exe link
+	fn add_process(root: &Path, pid: u32, name: &str, uid: uid_t, cmdline: &[&str], exe: Option<&str>) {
+		write_rooted(root, &format!("/proc/{}/stat", pid), format!("{} ({}) S 1 {} {} 0 -1 4194560 0 0 0 0 0 0 0 0 20 0 1 0\n", pid, name, pid, pid));
+		write_rooted(root, &format!("/proc/{}/status", pid), format!("Name:\t{}\nState:\tS (sleeping)\nUid:\t{}\t{}\t{}\t{}\nGid:\t{}\t{}\t{}\t{}\n", name, uid, uid, uid, uid, uid, uid, uid, uid));
+		write_rooted(root, &format!("/proc/{}/cmdline", pid), cmdline.iter().map(|a| format!("{}\0", a)).collect::<String>());
+		if let Some(exe) = exe {
+			std::os::unix::fs::symlink(exe, root.join(format!("proc/{}/exe", pid))).unwrap();
+		}
+	}
+
+	fn fixture(name: &str) -> PathBuf {
+		let root = test_root(&format!("process-{}", name));
+
+		write_rooted(&root, "/etc/passwd", "root:x:0:0:root:/root:/bin/bash\nalice:x:1000:1000::/home/alice:/bin/bash\nbob:x:1001:1001::/home/bob:/bin/bash\n");
+		add_process(&root, 1, "systemd", 0, &["/sbin/init", "splash"], Some("/usr/lib/systemd/systemd"));
+		add_process(&root, 2, "kthreadd", 0, &[], None);
+		add_process(&root, 812, "sshd", 0, &["sshd: /usr/sbin/sshd -D [listener]"], Some("/usr/sbin/sshd"));
+		add_process(&root, 1500, "Web (x) S 9", 1000, &["/usr/lib/firefox/firefox", "-contentproc"], Some("/usr/lib/firefox/firefox"));
+		add_process(&root, 1666, "kworker", 1000, &["./kworker"], Some("/tmp/.x/kworker (deleted)"));
+		// Not a process directory
+		write_rooted(&root, "/proc/net/tcp", "");
+
+		root
+	}
+
+	#[test]
+	fn parse_processes() {
+		let root = fixture("parse");
+		let root_s = root.display();
+
+		let pids: Vec<u32> = list_processes_in(&root_s).unwrap().iter().map(|p| p.pid).collect();
+		assert_eq!(pids, [1, 2, 812, 1500, 1666]);
+
+		let content = read_process_in(&root_s, 1500).unwrap();
+		assert_eq!(content.name, "Web (x) S 9");
+		assert_eq!(content.state, 'S');
+		assert_eq!(content.ppid, 1);
+		assert_eq!(content.cmdline, ["/usr/lib/firefox/firefox", "-contentproc"]);
+		assert_eq!((content.uid, content.euid, content.gid), (1000, 1000, 1000));
+		assert!(content.matches(&"firefox"));
+		assert!(!content.is_kernel_thread());
+
+		let kthreadd = read_process_in(&root_s, 2).unwrap();
+		assert!(kthreadd.is_kernel_thread());
+		assert!(kthreadd.cmdline.is_empty());
+
+		assert_eq!(read_process_in(&root_s, 3).map(|p| p.pid), Err(libc::ENOENT));
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn deleted_binaries() {
+		let root = fixture("deleted");
+		let root_s = root.display();
+
+		let deleted = processes_with_deleted_exe_in(&root_s).unwrap();
+		assert_eq!(deleted.len(), 1);
+		assert_eq!(deleted[0].pid, 1666);
+		assert_eq!(deleted[0].exe.as_deref(), Some(Path::new("/tmp/.x/kworker")));
+		// The binary's name still matches even with the suffix removed
+		assert!(deleted[0].matches(&"kworker"));
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn running_and_by_user() {
+		let root = fixture("running");
+		let root_s = root.display();
+
+		assert_eq!(process_running_in(&root_s, &"sshd"), Ok(true));
+		assert_eq!(process_running_in(&root_s, &"firefox"), Ok(true));
+		assert_eq!(process_running_in(&root_s, &"nc"), Ok(false));
+
+		let alice: Vec<u32> = processes_of_user_in(&root_s, &"alice").unwrap().iter().map(|p| p.pid).collect();
+		assert_eq!(alice, [1500, 1666]);
+		assert!(processes_of_user_in(&root_s, &"bob").unwrap().is_empty());
+		assert_eq!(processes_of_user_in(&root_s, &"carol").map(|p| p.len()), Err(libc::ENOENT));
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+}
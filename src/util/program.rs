@@ -11,6 +11,11 @@ use std::{
 };
 use crate::engine::{AppData, InstallMethod};
 
+use super::DebVersion;
+
+#[cfg(target_os = "linux")]
+use super::{detect_package_backend, flatpak_app, manual_installs, snap_package};
+
 /// A bool but with three options, [`TripleBool::Known`], [`TripleBool::Unknown`]
 #[derive(Copy, Clone)]
 pub enum TripleBool {
@@ -26,37 +31,23 @@ pub fn is_package_installed<T: ToString>(name: &T) -> bool {
 	#[cfg(target_os = "linux")]
  	{
 		let pkg_name = name.to_string();
-		let dpkg_cmd = Command::new("dpkg").args(&["-l"]).stderr(Stdio::null()).stdout(Stdio::piped())
-			.output().expect("O no estamos en una distribución basada en Debian o algo está realmente mal.");
-
-		if String::from_utf8_lossy(&dpkg_cmd.stdout).contains(pkg_name.as_str()) {
-			return true;
-		}
 
-		match Command::new("flatpak").args(&["list"]).stderr(Stdio::null()).stdout(Stdio::piped()).output().ok() {
-			Some(output) => {
-				if String::from_utf8_lossy(&output.stdout).contains(pkg_name.as_str()) {
-					return true;
-				}
-			},
-			None => (),
+		if let Some(backend) = detect_package_backend() {
+			if backend.is_installed(&pkg_name).unwrap_or(false) {
+				return true;
+			}
 		}
 
-		match Command::new("snap").args(&["list"]).stderr(Stdio::null()).stdout(Stdio::piped()).output().ok() {
-			Some(output) => {
-				if String::from_utf8_lossy(&output.stdout).contains(pkg_name.as_str()) {
-					return true;
-				}
-			},
-			None => (),
+		if flatpak_app(&pkg_name).is_ok_and(|f| f.is_some()) {
+			return true;
 		}
 
-		false
+		snap_package(&pkg_name).is_ok_and(|s| s.is_some())
 	}
 	#[cfg(target_os = "windows")]
 	{
 		let cmd = Command::new("reg")
-        .args(&["query", "HKEY_LOCAL_MACHINE\\SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\App Paths", "-s"])
+        .args(["query", "HKEY_LOCAL_MACHINE\\SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\App Paths", "-s"])
 		.stderr(Stdio::null()).stdout(Stdio::piped()).output();
 		match cmd.ok() {
 			Some(o) => {
@@ -75,12 +66,16 @@ pub fn is_package_installed<T: ToString>(name: &T) -> bool {
 impl AppData {
 	/// Create a new app data
 	pub fn new<T: ToString>(name: &T, install_method: InstallMethod) -> Self {
-		Self { name: name.to_string(), install_method: install_method }
+		Self { name: name.to_string(), install_method }
 	}
 
 	/// Checks if a package is installed
 	/// 
 	/// For WinGet, APT ([`InstallMethod::Default`] on Linux, aka [`InstallMethod::PackageManager`]), [`InstallMethod::Flatpak`], and [`InstallMethod::Snap`] packages, it uses `self.name` to query said package managers. \
+	/// Snaps must match by exact name, and flatpaks by exact application ID or name. \
+	/// On Linux, packages are looked up by exact name in the database of the [`PackageBackend`] picked by [`detect_package_backend`], so this works on dpkg, pacman and RPM based distributions. \
+	/// If no backend fits the system, this returns [`TripleBool::Unknown`]. \
+	/// On Linux, [`InstallMethod::ManualInstall`] looks in the places [`manual_installs`] does. \
 	/// For [`InstallMethod::PackageManager`]/[`InstallMethod::Default`] on Windows, this just calls [`is_package_installed`]. \
 	/// For anything else, it default returns [`TripleBool::Unknown`]
 	pub fn is_installed(&self) -> TripleBool {
@@ -89,11 +84,10 @@ impl AppData {
 			InstallMethod::Default | InstallMethod::PackageManager => {
 				#[cfg(target_os = "linux")]
 				{
-					let dpkg_cmd = Command::new("dpkg").args(&["-l"])
-					    .stderr(Stdio::null()).stdout(Stdio::piped())
-			            .output().expect("O no estamos en una distribución basada en Debian o algo está muy mal.");
-
-					TripleBool::Known(String::from_utf8_lossy(&dpkg_cmd.stdout).contains(&self.name))
+					match detect_package_backend().map(|b| b.is_installed(&self.name)) {
+						Some(Ok(installed)) => TripleBool::Known(installed),
+						_ => TripleBool::Unknown,
+					}
 				}
 				#[cfg(target_os = "windows")]
 				{
@@ -102,34 +96,76 @@ impl AppData {
 			},
 			#[cfg(target_os = "linux")]
 			InstallMethod::Flatpak => {
-				match Command::new("flatpak").args(&["list"]).stderr(Stdio::null()).stdout(Stdio::piped()).output().ok() {
-					Some(output) => {
-						TripleBool::Known(String::from_utf8_lossy(&output.stdout).contains(&self.name))
-					},
-					None => TripleBool::Unknown,
+				match flatpak_app(&self.name) {
+					Ok(app) => TripleBool::Known(app.is_some()),
+					Err(_) => TripleBool::Unknown,
 				}
 			},
 			#[cfg(target_os = "linux")]
 			InstallMethod::Snap => {
-				match Command::new("snap").args(&["list"]).stdout(Stdio::piped()).output().ok() {
-					Some(output) => {
-						TripleBool::Known(String::from_utf8_lossy(&output.stdout).contains(&self.name))
-					},
-					None => TripleBool::Unknown,
+				match snap_package(&self.name) {
+					Ok(snap) => TripleBool::Known(snap.is_some()),
+					Err(_) => TripleBool::Unknown,
 				}
 			},
 			#[cfg(target_os = "windows")]
 			InstallMethod::WinGet => {
-				match Command::new("winget").args(&["list", "--name"]).stderr(Stdio::null()).stdout(Stdio::piped()).output().ok() {
+				match Command::new("winget").args(["list", "--name"]).stderr(Stdio::null()).stdout(Stdio::piped()).output().ok() {
 					Some(output) => {
 						TripleBool::Known(String::from_utf8_lossy(&output.stdout).contains(&self.name))
 					},
 					None => TripleBool::Unknown,
 				}
 			}
+			#[cfg(target_os = "linux")]
+			InstallMethod::ManualInstall => TripleBool::Known(!manual_installs(&self.name).is_empty()),
+			#[cfg(not(target_os = "linux"))]
 			_ => TripleBool::Unknown,
 		}
 	}
+
+	/// Get the installed version of a package
+	///
+	/// This works for system packages ([`InstallMethod::Default`]/[`InstallMethod::PackageManager`] on Linux), [`InstallMethod::Snap`] and [`InstallMethod::Flatpak`] packages. \
+	/// Pacman and RPM versions are compared with the Debian rules too, which agree with theirs for ordinary versions. \
+	/// Returns [`None`] if the package isn't installed, or the version can't be found.
+	pub fn installed_version(&self) -> Option<DebVersion> {
+		match self.install_method {
+			#[cfg(target_os = "linux")]
+			InstallMethod::Default | InstallMethod::PackageManager => {
+				detect_package_backend()?.package(&self.name).ok().flatten()
+					.and_then(|p| DebVersion::parse(&p.version).ok())
+			},
+			#[cfg(target_os = "linux")]
+			InstallMethod::Snap => {
+				snap_package(&self.name).ok().flatten()
+					.and_then(|s| DebVersion::parse(&s.version).ok())
+			},
+			#[cfg(target_os = "linux")]
+			InstallMethod::Flatpak => {
+				flatpak_app(&self.name).ok().flatten()
+					.and_then(|f| DebVersion::parse(&f.version).ok())
+			},
+			_ => None,
+		}
+	}
+
+	/// Check if the installed version of a package is at least `version`
+	///
+	/// Returns [`TripleBool::Known`]`(false)` if the package isn't installed, and [`TripleBool::Unknown`] if that can't be told,
+	/// the installed version can't be found, or `version` doesn't parse.
+	pub fn is_at_least<T: ToString>(&self, version: &T) -> TripleBool {
+		let wanted = match DebVersion::parse(version) {
+			Ok(v) => v,
+			Err(_) => return TripleBool::Unknown,
+		};
+
+		match (self.is_installed(), self.installed_version()) {
+			(TripleBool::Known(false), _) => TripleBool::Known(false),
+			(_, Some(installed)) => TripleBool::Known(installed >= wanted),
+			(_, None) => TripleBool::Unknown,
+		}
+	}
 }
 
 // TODO: Mucho más
\ No newline at end of file
@@ -0,0 +1,452 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+use std::{
+	collections::{HashMap, HashSet},
+	string::String,
+	vec::Vec,
+};
+
+use crate::engine::{AppData, InstallMethod};
+use super::{
+	detect_package_backend, detect_package_backend_in, list_flatpaks, list_flatpaks_in, list_snaps, list_snaps_in,
+	manual_installs, manual_installs_in, FlatpakRef, PackageBackend, SnapPackage,
+};
+
+/// What kind of prohibited software a [`ProhibitedSoftware`] entry is
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum SoftwareCategory {
+	PasswordCracker,
+	NetworkScanner,
+	/// Exploitation, sniffing and other general hacking tools
+	HackingTool,
+	Keylogger,
+	Game,
+	PeerToPeer,
+}
+
+impl SoftwareCategory {
+	/// A short description, like `password cracker`
+	pub fn description(&self) -> &'static str {
+		match self {
+			SoftwareCategory::PasswordCracker => "password cracker",
+			SoftwareCategory::NetworkScanner => "network scanner",
+			SoftwareCategory::HackingTool => "hacking tool",
+			SoftwareCategory::Keylogger => "keylogger",
+			SoftwareCategory::Game => "game",
+			SoftwareCategory::PeerToPeer => "P2P client",
+		}
+	}
+}
+
+/// One piece of prohibited software, with every name it can be installed under
+#[derive(Clone, Debug)]
+pub struct ProhibitedSoftware {
+	/// The display name, like `John the Ripper`, which is unique in a [`ProhibitedCatalog`]
+	pub name: String,
+	pub category: SoftwareCategory,
+	/// Package names, keyed by [`PackageBackend::name`](super::PackageBackend::name)
+	pub packages: HashMap<String, Vec<String>>,
+	pub snaps: Vec<String>,
+	/// Flatpak application IDs
+	pub flatpaks: Vec<String>,
+	/// Binary names to look for as manual installs
+	pub binaries: Vec<String>,
+}
+
+impl ProhibitedSoftware {
+	/// Create an entry with no names to look for
+	pub fn new<T: ToString>(name: &T, category: SoftwareCategory) -> Self {
+		Self {
+			name: name.to_string(),
+			category,
+			packages: HashMap::new(),
+			snaps: Vec::new(),
+			flatpaks: Vec::new(),
+			binaries: Vec::new(),
+		}
+	}
+
+	/// Add a package name for the backend named `backend`, like `dpkg`, `pacman` or `rpm`
+	pub fn add_package<A: ToString, B: ToString>(&mut self, backend: &A, name: &B) {
+		self.packages.entry(backend.to_string()).or_default().push(name.to_string());
+	}
+
+	pub fn add_snap<T: ToString>(&mut self, name: &T) {
+		self.snaps.push(name.to_string());
+	}
+
+	pub fn add_flatpak<T: ToString>(&mut self, id: &T) {
+		self.flatpaks.push(id.to_string());
+	}
+
+	pub fn add_binary<T: ToString>(&mut self, name: &T) {
+		self.binaries.push(name.to_string());
+	}
+
+	/// Every way to look for this software on a system using the package backend named `backend`
+	///
+	/// Packages for other backends are left out, and binaries are looked for as [`InstallMethod::ManualInstall`].
+	pub fn app_data(&self, backend: Option<&str>) -> Vec<AppData> {
+		let packages = backend.and_then(|b| self.packages.get(b)).into_iter().flatten()
+			.map(|p| AppData::new(p, InstallMethod::PackageManager));
+		let snaps = self.snaps.iter().map(|s| AppData::new(s, InstallMethod::Snap));
+		let flatpaks = self.flatpaks.iter().map(|f| AppData::new(f, InstallMethod::Flatpak));
+		let binaries = self.binaries.iter().map(|b| AppData::new(b, InstallMethod::ManualInstall));
+
+		packages.chain(snaps).chain(flatpaks).chain(binaries).collect()
+	}
+}
+
+/// Prohibited software found by [`ProhibitedCatalog::scan`]
+#[derive(Clone, Debug)]
+pub struct ProhibitedFinding {
+	pub software: ProhibitedSoftware,
+	/// The names it was found installed under
+	pub installed: Vec<AppData>,
+}
+
+impl ProhibitedFinding {
+	/// A short explanation, suitable for a score report entry
+	pub fn reason(&self) -> String {
+		format!("Prohibited {} {} is installed", self.software.category.description(), self.software.name)
+	}
+}
+
+/// A built in catalog entry, kept as plain slices so the table stays readable
+struct Builtin {
+	name: &'static str,
+	category: SoftwareCategory,
+	dpkg: &'static [&'static str],
+	pacman: &'static [&'static str],
+	rpm: &'static [&'static str],
+	snaps: &'static [&'static str],
+	flatpaks: &'static [&'static str],
+	binaries: &'static [&'static str],
+}
+
+const BUILTIN: &[Builtin] = &[
+	// Password crackers
+	Builtin { name: "John the Ripper", category: SoftwareCategory::PasswordCracker,
+		dpkg: &["john"], pacman: &["john"], rpm: &["john"], snaps: &["john-the-ripper"], flatpaks: &[], binaries: &["john"] },
+	Builtin { name: "Hashcat", category: SoftwareCategory::PasswordCracker,
+		dpkg: &["hashcat"], pacman: &["hashcat"], rpm: &["hashcat"], snaps: &[], flatpaks: &[], binaries: &["hashcat"] },
+	Builtin { name: "Hydra", category: SoftwareCategory::PasswordCracker,
+		dpkg: &["hydra", "hydra-gtk"], pacman: &["hydra"], rpm: &["hydra"], snaps: &[], flatpaks: &[], binaries: &["hydra", "xhydra"] },
+	Builtin { name: "Medusa", category: SoftwareCategory::PasswordCracker,
+		dpkg: &["medusa"], pacman: &["medusa"], rpm: &["medusa"], snaps: &[], flatpaks: &[], binaries: &["medusa"] },
+	Builtin { name: "Ncrack", category: SoftwareCategory::PasswordCracker,
+		dpkg: &["ncrack"], pacman: &["ncrack"], rpm: &["ncrack"], snaps: &[], flatpaks: &[], binaries: &["ncrack"] },
+	Builtin { name: "Ophcrack", category: SoftwareCategory::PasswordCracker,
+		dpkg: &["ophcrack", "ophcrack-cli"], pacman: &["ophcrack"], rpm: &["ophcrack"], snaps: &[], flatpaks: &[], binaries: &["ophcrack"] },
+	Builtin { name: "Aircrack-ng", category: SoftwareCategory::PasswordCracker,
+		dpkg: &["aircrack-ng"], pacman: &["aircrack-ng"], rpm: &["aircrack-ng"], snaps: &[], flatpaks: &[], binaries: &["aircrack-ng"] },
+	Builtin { name: "fcrackzip", category: SoftwareCategory::PasswordCracker,
+		dpkg: &["fcrackzip"], pacman: &["fcrackzip"], rpm: &["fcrackzip"], snaps: &[], flatpaks: &[], binaries: &["fcrackzip"] },
+	Builtin { name: "RainbowCrack", category: SoftwareCategory::PasswordCracker,
+		dpkg: &["rainbowcrack"], pacman: &[], rpm: &[], snaps: &[], flatpaks: &[], binaries: &["rcrack", "rtgen"] },
+	Builtin { name: "Crunch", category: SoftwareCategory::PasswordCracker,
+		dpkg: &["crunch"], pacman: &["crunch"], rpm: &["crunch"], snaps: &[], flatpaks: &[], binaries: &["crunch"] },
+
+	// Network scanners
+	Builtin { name: "Nmap", category: SoftwareCategory::NetworkScanner,
+		dpkg: &["nmap", "zenmap"], pacman: &["nmap"], rpm: &["nmap", "nmap-frontend"], snaps: &["nmap"], flatpaks: &[], binaries: &["nmap", "zenmap"] },
+	Builtin { name: "Masscan", category: SoftwareCategory::NetworkScanner,
+		dpkg: &["masscan"], pacman: &["masscan"], rpm: &["masscan"], snaps: &[], flatpaks: &[], binaries: &["masscan"] },
+	Builtin { name: "Zmap", category: SoftwareCategory::NetworkScanner,
+		dpkg: &["zmap"], pacman: &["zmap"], rpm: &["zmap"], snaps: &[], flatpaks: &[], binaries: &["zmap"] },
+	Builtin { name: "Nikto", category: SoftwareCategory::NetworkScanner,
+		dpkg: &["nikto"], pacman: &["nikto"], rpm: &["nikto"], snaps: &[], flatpaks: &[], binaries: &["nikto"] },
+	Builtin { name: "Wireshark", category: SoftwareCategory::NetworkScanner,
+		dpkg: &["wireshark", "wireshark-qt", "wireshark-gtk", "tshark"], pacman: &["wireshark-qt", "wireshark-cli"],
+		rpm: &["wireshark", "wireshark-cli"], snaps: &["wireshark"], flatpaks: &["org.wireshark.Wireshark"], binaries: &["wireshark", "tshark"] },
+	Builtin { name: "Kismet", category: SoftwareCategory::NetworkScanner,
+		dpkg: &["kismet"], pacman: &["kismet"], rpm: &["kismet"], snaps: &[], flatpaks: &[], binaries: &["kismet"] },
+
+	// Hacking tools
+	Builtin { name: "Metasploit", category: SoftwareCategory::HackingTool,
+		dpkg: &["metasploit-framework"], pacman: &["metasploit"], rpm: &["metasploit-framework"], snaps: &[], flatpaks: &[], binaries: &["msfconsole"] },
+	Builtin { name: "sqlmap", category: SoftwareCategory::HackingTool,
+		dpkg: &["sqlmap"], pacman: &["sqlmap"], rpm: &["sqlmap"], snaps: &[], flatpaks: &[], binaries: &["sqlmap"] },
+	Builtin { name: "Ettercap", category: SoftwareCategory::HackingTool,
+		dpkg: &["ettercap-graphical", "ettercap-text-only", "ettercap-common"], pacman: &["ettercap"], rpm: &["ettercap"],
+		snaps: &[], flatpaks: &[], binaries: &["ettercap"] },
+	Builtin { name: "dsniff", category: SoftwareCategory::HackingTool,
+		dpkg: &["dsniff"], pacman: &["dsniff"], rpm: &["dsniff"], snaps: &[], flatpaks: &[], binaries: &["dsniff", "arpspoof"] },
+	Builtin { name: "Netcat (traditional)", category: SoftwareCategory::HackingTool,
+		dpkg: &["netcat-traditional"], pacman: &["gnu-netcat"], rpm: &[], snaps: &[], flatpaks: &[], binaries: &["nc.traditional"] },
+
+	// Keyloggers
+	Builtin { name: "logkeys", category: SoftwareCategory::Keylogger,
+		dpkg: &["logkeys"], pacman: &["logkeys"], rpm: &[], snaps: &[], flatpaks: &[], binaries: &["logkeys"] },
+	Builtin { name: "uberkey", category: SoftwareCategory::Keylogger,
+		dpkg: &["uberkey"], pacman: &[], rpm: &[], snaps: &[], flatpaks: &[], binaries: &["uberkey"] },
+	Builtin { name: "lkl", category: SoftwareCategory::Keylogger,
+		dpkg: &["lkl"], pacman: &[], rpm: &[], snaps: &[], flatpaks: &[], binaries: &["lkl"] },
+	Builtin { name: "PyKeylogger", category: SoftwareCategory::Keylogger,
+		dpkg: &["pykeylogger"], pacman: &[], rpm: &[], snaps: &[], flatpaks: &[], binaries: &["pykeylogger"] },
+
+	// Games
+	Builtin { name: "AisleRiot Solitaire", category: SoftwareCategory::Game,
+		dpkg: &["aisleriot"], pacman: &["aisleriot"], rpm: &["aisleriot"], snaps: &[], flatpaks: &["org.gnome.Aisleriot"], binaries: &["sol"] },
+	Builtin { name: "GNOME Mines", category: SoftwareCategory::Game,
+		dpkg: &["gnome-mines"], pacman: &["gnome-mines"], rpm: &["gnome-mines"], snaps: &[], flatpaks: &["org.gnome.Mines"], binaries: &["gnome-mines"] },
+	Builtin { name: "GNOME Mahjongg", category: SoftwareCategory::Game,
+		dpkg: &["gnome-mahjongg"], pacman: &["gnome-mahjongg"], rpm: &["gnome-mahjongg"], snaps: &[], flatpaks: &["org.gnome.Mahjongg"], binaries: &["gnome-mahjongg"] },
+	Builtin { name: "GNOME Sudoku", category: SoftwareCategory::Game,
+		dpkg: &["gnome-sudoku"], pacman: &["gnome-sudoku"], rpm: &["gnome-sudoku"], snaps: &[], flatpaks: &["org.gnome.Sudoku"], binaries: &["gnome-sudoku"] },
+	Builtin { name: "Steam", category: SoftwareCategory::Game,
+		dpkg: &["steam", "steam-installer", "steam-launcher"], pacman: &["steam"], rpm: &["steam"], snaps: &["steam"],
+		flatpaks: &["com.valvesoftware.Steam"], binaries: &["steam"] },
+	Builtin { name: "Minecraft", category: SoftwareCategory::Game,
+		dpkg: &["minecraft-launcher"], pacman: &[], rpm: &[], snaps: &["mc-installer"], flatpaks: &["com.mojang.Minecraft"], binaries: &["minecraft-launcher"] },
+	Builtin { name: "Minetest", category: SoftwareCategory::Game,
+		dpkg: &["minetest"], pacman: &["minetest"], rpm: &["minetest"], snaps: &["minetest"], flatpaks: &["net.minetest.Minetest"], binaries: &["minetest"] },
+	Builtin { name: "SuperTux", category: SoftwareCategory::Game,
+		dpkg: &["supertux"], pacman: &["supertux"], rpm: &["supertux"], snaps: &[], flatpaks: &["org.supertuxproject.SuperTux"], binaries: &["supertux2"] },
+	Builtin { name: "SuperTuxKart", category: SoftwareCategory::Game,
+		dpkg: &["supertuxkart"], pacman: &["supertuxkart"], rpm: &["supertuxkart"], snaps: &["supertuxkart"],
+		flatpaks: &["net.supertuxkart.SuperTuxKart"], binaries: &["supertuxkart"] },
+	Builtin { name: "Battle for Wesnoth", category: SoftwareCategory::Game,
+		dpkg: &["wesnoth"], pacman: &["wesnoth"], rpm: &["wesnoth"], snaps: &[], flatpaks: &["org.wesnoth.Wesnoth"], binaries: &["wesnoth"] },
+	Builtin { name: "0 A.D.", category: SoftwareCategory::Game,
+		dpkg: &["0ad"], pacman: &["0ad"], rpm: &["0ad"], snaps: &["0ad"], flatpaks: &["com.play0ad.zeroad"], binaries: &["0ad"] },
+	Builtin { name: "Freeciv", category: SoftwareCategory::Game,
+		dpkg: &["freeciv", "freeciv-client-gtk3", "freeciv-client-qt", "freeciv-server"], pacman: &["freeciv"], rpm: &["freeciv"],
+		snaps: &[], flatpaks: &["org.freeciv.gtk3"], binaries: &["freeciv-gtk3", "freeciv-server"] },
+	Builtin { name: "NetHack", category: SoftwareCategory::Game,
+		dpkg: &["nethack-console", "nethack-x11"], pacman: &["nethack"], rpm: &["nethack"], snaps: &[], flatpaks: &[], binaries: &["nethack"] },
+	Builtin { name: "Frozen Bubble", category: SoftwareCategory::Game,
+		dpkg: &["frozen-bubble"], pacman: &["frozen-bubble"], rpm: &["frozen-bubble"], snaps: &[], flatpaks: &[], binaries: &["frozen-bubble"] },
+
+	// P2P clients
+	Builtin { name: "Transmission", category: SoftwareCategory::PeerToPeer,
+		dpkg: &["transmission", "transmission-gtk", "transmission-qt", "transmission-cli", "transmission-daemon"],
+		pacman: &["transmission-gtk", "transmission-qt", "transmission-cli"],
+		rpm: &["transmission", "transmission-gtk", "transmission-qt", "transmission-cli", "transmission-daemon"],
+		snaps: &[], flatpaks: &["com.transmissionbt.Transmission"],
+		binaries: &["transmission-gtk", "transmission-qt", "transmission-cli", "transmission-daemon"] },
+	Builtin { name: "qBittorrent", category: SoftwareCategory::PeerToPeer,
+		dpkg: &["qbittorrent", "qbittorrent-nox"], pacman: &["qbittorrent", "qbittorrent-nox"], rpm: &["qbittorrent", "qbittorrent-nox"],
+		snaps: &[], flatpaks: &["org.qbittorrent.qBittorrent"], binaries: &["qbittorrent", "qbittorrent-nox"] },
+	Builtin { name: "Deluge", category: SoftwareCategory::PeerToPeer,
+		dpkg: &["deluge", "deluge-gtk", "deluged"], pacman: &["deluge", "deluge-gtk"], rpm: &["deluge", "deluge-gtk", "deluge-daemon"],
+		snaps: &[], flatpaks: &["org.deluge_torrent.deluge"], binaries: &["deluge", "deluge-gtk", "deluged"] },
+	Builtin { name: "KTorrent", category: SoftwareCategory::PeerToPeer,
+		dpkg: &["ktorrent"], pacman: &["ktorrent"], rpm: &["ktorrent"], snaps: &[], flatpaks: &["org.kde.ktorrent"], binaries: &["ktorrent"] },
+	Builtin { name: "rTorrent", category: SoftwareCategory::PeerToPeer,
+		dpkg: &["rtorrent"], pacman: &["rtorrent"], rpm: &["rtorrent"], snaps: &[], flatpaks: &[], binaries: &["rtorrent"] },
+	Builtin { name: "aMule", category: SoftwareCategory::PeerToPeer,
+		dpkg: &["amule", "amule-daemon"], pacman: &["amule"], rpm: &["amule"], snaps: &[], flatpaks: &["org.amule.aMule"], binaries: &["amule", "amuled"] },
+	Builtin { name: "Nicotine+", category: SoftwareCategory::PeerToPeer,
+		dpkg: &["nicotine"], pacman: &["nicotine+"], rpm: &["nicotine+"], snaps: &[], flatpaks: &["org.nicotine_plus.Nicotine"], binaries: &["nicotine"] },
+	Builtin { name: "FrostWire", category: SoftwareCategory::PeerToPeer,
+		dpkg: &["frostwire"], pacman: &[], rpm: &[], snaps: &[], flatpaks: &[], binaries: &["frostwire"] },
+];
+
+/// A list of prohibited software to scan a system for
+///
+/// [`ProhibitedCatalog::builtin`] covers the password crackers, network scanners, hacking tools, keyloggers, games and P2P clients
+/// that usually show up on practice images. Some of those, like Transmission and AisleRiot, ship with desktop installs,
+/// so remove any entry the README allows with [`ProhibitedCatalog::remove`].
+#[derive(Clone, Debug, Default)]
+pub struct ProhibitedCatalog {
+	pub entries: Vec<ProhibitedSoftware>,
+}
+
+impl ProhibitedCatalog {
+	/// Create an empty catalog
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Create a catalog with every built in entry
+	pub fn builtin() -> Self {
+		let entries = BUILTIN.iter()
+			.map(|b| {
+				let mut entry = ProhibitedSoftware::new(&b.name, b.category);
+				for (backend, names) in [("dpkg", b.dpkg), ("pacman", b.pacman), ("rpm", b.rpm)] {
+					names.iter().for_each(|n| entry.add_package(&backend, n));
+				}
+				b.snaps.iter().for_each(|s| entry.add_snap(s));
+				b.flatpaks.iter().for_each(|f| entry.add_flatpak(f));
+				b.binaries.iter().for_each(|n| entry.add_binary(n));
+				entry
+			})
+			.collect();
+
+		Self { entries }
+	}
+
+	/// Add an entry, replacing any entry with the same name
+	pub fn add(&mut self, entry: ProhibitedSoftware) {
+		match self.entries.iter_mut().find(|e| e.name == entry.name) {
+			Some(existing) => *existing = entry,
+			None => self.entries.push(entry),
+		}
+	}
+
+	/// Remove the entry named `name`, returning it if it was there
+	pub fn remove<T: ToString>(&mut self, name: &T) -> Option<ProhibitedSoftware> {
+		let name = name.to_string();
+		let index = self.entries.iter().position(|e| e.name == name)?;
+		Some(self.entries.remove(index))
+	}
+
+	/// Remove every entry in `category`
+	pub fn remove_category(&mut self, category: SoftwareCategory) {
+		self.entries.retain(|e| e.category != category);
+	}
+
+	/// Get the entry named `name`
+	pub fn get<T: ToString>(&self, name: &T) -> Option<&ProhibitedSoftware> {
+		let name = name.to_string();
+		self.entries.iter().find(|e| e.name == name)
+	}
+
+	/// Scan the system for every entry in the catalog
+	///
+	/// The installed packages of the backend picked by [`detect_package_backend`], `snap list` and `flatpak list` are each
+	/// read once, then every name is checked against them. Binaries are looked for with [`manual_installs`].
+	/// A source that can't be read counts as having nothing installed.
+	pub fn scan(&self) -> Vec<ProhibitedFinding> {
+		let backend = detect_package_backend();
+		let installed = InstalledSoftware::load(backend.as_deref(), list_snaps().ok(), list_flatpaks().ok());
+
+		self.scan_with(&installed, |name| !manual_installs(&name).is_empty())
+	}
+
+	/// Scan the system under the root directory `root` for every entry in the catalog, like [`ProhibitedCatalog::scan`]
+	///
+	/// Snaps and flatpaks are read from snapd's state and the flatpak installations on disk, and binaries are looked for with [`manual_installs_in`].
+	pub fn scan_in<T: ToString>(&self, root: &T) -> Vec<ProhibitedFinding> {
+		let backend = detect_package_backend_in(root);
+		let installed = InstalledSoftware::load(backend.as_deref(), list_snaps_in(root).ok(), list_flatpaks_in(root).ok());
+
+		self.scan_with(&installed, |name| !manual_installs_in(root, &name).is_empty())
+	}
+
+	fn scan_with<F: Fn(&str) -> bool>(&self, installed: &InstalledSoftware, manual: F) -> Vec<ProhibitedFinding> {
+		self.entries.iter()
+			.filter_map(|entry| {
+				let installed: Vec<AppData> = entry.app_data(installed.backend).into_iter()
+					.filter(|a| match a.install_method {
+						InstallMethod::ManualInstall => manual(&a.name),
+						method => installed.contains(method, &a.name),
+					})
+					.collect();
+
+				if installed.is_empty() {
+					None
+				} else {
+					Some(ProhibitedFinding { software: entry.clone(), installed })
+				}
+			})
+			.collect()
+	}
+}
+
+/// The packages, snaps and flatpaks installed on a system, read once for a whole scan
+struct InstalledSoftware {
+	backend: Option<&'static str>,
+	packages: HashSet<String>,
+	snaps: HashSet<String>,
+	flatpaks: Vec<FlatpakRef>,
+}
+
+impl InstalledSoftware {
+	fn load(backend: Option<&dyn PackageBackend>, snaps: Option<Vec<SnapPackage>>, flatpaks: Option<Vec<FlatpakRef>>) -> Self {
+		Self {
+			backend: backend.map(|b| b.name()),
+			packages: backend.and_then(|b| b.list_installed().ok()).into_iter().flatten().map(|p| p.name).collect(),
+			snaps: snaps.into_iter().flatten().map(|s| s.name).collect(),
+			flatpaks: flatpaks.into_iter().flatten().filter(|f| !f.runtime).collect(),
+		}
+	}
+
+	fn contains(&self, method: InstallMethod, name: &str) -> bool {
+		match method {
+			InstallMethod::Default | InstallMethod::PackageManager => self.packages.contains(name),
+			InstallMethod::Snap => self.snaps.contains(name),
+			InstallMethod::Flatpak => self.flatpaks.iter().any(|f| f.matches(&name)),
+			_ => false,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+
+	#[test]
+	fn scan_fixture_root() {
+		let root = test_root("prohibited-scan");
+		write_rooted(&root, "/etc/os-release", "ID=ubuntu\nID_LIKE=debian\n");
+		write_rooted(&root, "/etc/passwd", "root:x:0:0:root:/root:/bin/bash\nperry:x:1000:1000::/home/perry:/bin/bash\n");
+		write_rooted(&root, "/var/lib/dpkg/status", "\
+Package: john
+Status: install ok installed
+Architecture: amd64
+Version: 1.9.0-2
+
+Package: hydra
+Status: deinstall ok config-files
+Architecture: amd64
+Version: 9.5-1
+
+Package: transmission-gtk
+Status: install ok installed
+Architecture: amd64
+Version: 4.0.5-1build5
+");
+		write_rooted(&root, "/var/lib/dpkg/info/john.list", "/usr/sbin/john\n");
+		write_rooted(&root, "/usr/sbin/john", "");
+		write_rooted(&root, "/usr/local/bin/hashcat", "");
+		write_rooted(&root, "/var/lib/snapd/state.json", r#"{"data": {"snaps": {"wireshark": {"type": "app", "active": true, "current": "17"}}}}"#);
+		std::fs::create_dir_all(root.join("var/lib/flatpak/app/org.gnome.Mines/x86_64/stable/active")).unwrap();
+		std::fs::create_dir_all(root.join("var/lib/flatpak/runtime/org.gnome.Sudoku/x86_64/stable/active")).unwrap();
+
+		let mut catalog = ProhibitedCatalog::builtin();
+		assert!(catalog.remove(&"Transmission").is_some());
+		let mut arch_only = ProhibitedSoftware::new(&"Arch Tool", SoftwareCategory::HackingTool);
+		arch_only.add_package(&"pacman", &"john");
+		catalog.add(arch_only);
+
+		let findings = catalog.scan_in(&root.display());
+		let found: Vec<(&str, Vec<(String, InstallMethod)>)> = findings.iter()
+			.map(|f| (f.software.name.as_str(), f.installed.iter().map(|a| (a.name.clone(), a.install_method)).collect()))
+			.collect();
+		assert_eq!(found, [
+			("John the Ripper", vec![("john".to_string(), InstallMethod::PackageManager)]),
+			("Hashcat", vec![("hashcat".to_string(), InstallMethod::ManualInstall)]),
+			("Wireshark", vec![("wireshark".to_string(), InstallMethod::Snap)]),
+			("GNOME Mines", vec![("org.gnome.Mines".to_string(), InstallMethod::Flatpak)]),
+		]);
+		assert_eq!(findings[0].reason(), "Prohibited password cracker John the Ripper is installed");
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn catalog_edits() {
+		let mut catalog = ProhibitedCatalog::builtin();
+		let count = catalog.entries.len();
+
+		let nmap = catalog.get(&"Nmap").unwrap();
+		let names: Vec<String> = nmap.app_data(Some("rpm")).into_iter().map(|a| a.name).collect();
+		assert_eq!(names, ["nmap", "nmap-frontend", "nmap", "nmap", "zenmap"]);
+		assert_eq!(nmap.app_data(None).len(), 3);
+
+		catalog.add(ProhibitedSoftware::new(&"Nmap", SoftwareCategory::NetworkScanner));
+		assert_eq!(catalog.entries.len(), count);
+		assert!(catalog.get(&"Nmap").unwrap().binaries.is_empty());
+
+		catalog.remove_category(SoftwareCategory::Game);
+		assert!(catalog.get(&"Steam").is_none());
+		assert!(catalog.entries.iter().all(|e| e.category != SoftwareCategory::Game));
+	}
+}
@@ -0,0 +1,367 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+use std::{
+	collections::BTreeSet,
+	string::String,
+	vec::Vec,
+};
+
+use super::{io_errno, user_exists, user_is_admin};
+
+#[cfg(target_os = "linux")]
+use super::{list_groups_in, list_human_users_in, list_root_users_in, user_exists_in, user_is_admin_in};
+
+/// Groups that grant administrator privileges on common Linux distributions
+#[cfg(target_os = "linux")]
+const ADMIN_GROUPS: [&str; 3] = ["sudo", "admin", "wheel"];
+
+/// One way the system disagrees with a [`Roster`]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum RosterIssue {
+	/// A user exists on the system but isn't on the roster
+	UnauthorizedUser(String),
+	/// A user on the roster doesn't exist on the system
+	MissingUser(String),
+	/// A user has administrator privileges but isn't an authorized administrator
+	UnauthorizedAdmin(String),
+	/// An authorized standard user has administrator privileges
+	AdminToDemote(String),
+}
+
+impl RosterIssue {
+	/// The name of the user the issue is about
+	pub fn name(&self) -> &str {
+		match self {
+			RosterIssue::UnauthorizedUser(n)
+			| RosterIssue::MissingUser(n)
+			| RosterIssue::UnauthorizedAdmin(n)
+			| RosterIssue::AdminToDemote(n) => n.as_str(),
+		}
+	}
+
+	/// A short explanation, suitable for a score report entry
+	pub fn reason(&self) -> String {
+		match self {
+			RosterIssue::UnauthorizedUser(n) => format!("Unauthorized user {} exists", n),
+			RosterIssue::MissingUser(n) => format!("Authorized user {} is missing", n),
+			RosterIssue::UnauthorizedAdmin(n) => format!("Unauthorized user {} is an administrator", n),
+			RosterIssue::AdminToDemote(n) => format!("User {} should not be an administrator", n),
+		}
+	}
+}
+
+/// The list of authorized users and administrators for an image
+///
+/// This is the same list every CyberPatriot README ships with.
+/// It can be built in code with [`Roster::add_user`] and [`Roster::add_admin`], or parsed from a file in the README's format:
+///
+/// ```text
+/// Authorized Administrators:
+/// perry (you)
+///     password: securepassword
+/// carl
+///
+/// Authorized Users:
+/// candace
+/// ```
+///
+/// Only the first word of each line under a heading is read, and `password:` lines, blank lines and `#` comments are skipped.
+#[derive(Clone, Default)]
+pub struct Roster {
+	pub users: BTreeSet<String>,
+	pub admins: BTreeSet<String>,
+}
+
+impl Roster {
+	/// Create an empty roster
+	pub fn new() -> Roster {
+		Roster::default()
+	}
+
+	/// Add an authorized standard user
+	pub fn add_user<T: ToString>(&mut self, name: &T) {
+		self.users.insert(name.to_string());
+	}
+
+	/// Add an authorized administrator
+	pub fn add_admin<T: ToString>(&mut self, name: &T) {
+		self.admins.insert(name.to_string());
+	}
+
+	/// Parse a roster from a string in the README format
+	///
+	/// Returns `Err(-1)` if a name appears before any heading.
+	pub fn parse<T: ToString>(text: &T) -> Result<Roster, i32> {
+		let text = text.to_string();
+		let mut roster = Roster::new();
+		let mut admins = None;
+
+		for line in text.lines() {
+			let line = line.trim();
+			let lower = line.to_lowercase();
+
+			if line.is_empty() || line.starts_with('#') || lower.starts_with("password:") {
+				continue;
+			}
+
+			if lower.ends_with(':') {
+				if lower.contains("admin") {
+					admins = Some(true);
+				} else if lower.contains("user") {
+					admins = Some(false);
+				}
+				continue;
+			}
+
+			let name = line.split_whitespace().next().unwrap_or(line);
+
+			match admins {
+				Some(true) => roster.add_admin(&name),
+				Some(false) => roster.add_user(&name),
+				None => return Err(-1),
+			}
+		}
+
+		Ok(roster)
+	}
+
+	/// Parse a roster from the file at `path`
+	pub fn from_file<T: ToString>(path: &T) -> Result<Roster, i32> {
+		Roster::parse(&std::fs::read_to_string(path.to_string()).map_err(io_errno)?)
+	}
+
+	/// Check if `name` is on the roster as either a user or an administrator
+	pub fn is_authorized<T: ToString>(&self, name: &T) -> bool {
+		let name = name.to_string();
+		self.users.contains(&name) || self.admins.contains(&name)
+	}
+
+	/// Check if `name` is on the roster as an administrator
+	pub fn is_authorized_admin<T: ToString>(&self, name: &T) -> bool {
+		self.admins.contains(&name.to_string())
+	}
+
+	/// List the human users on the system that aren't on the roster
+	#[cfg(target_os = "linux")]
+	pub fn unauthorized_users(&self) -> Result<Vec<String>, i32> {
+		self.unauthorized_users_in(&"/")
+	}
+
+	/// List the human users in /etc/passwd under the root directory `root` that aren't on the roster
+	#[cfg(target_os = "linux")]
+	pub fn unauthorized_users_in<T: ToString>(&self, root: &T) -> Result<Vec<String>, i32> {
+		Ok(list_human_users_in(root)?.into_iter()
+			.map(|u| u.username)
+			.filter(|n| !self.is_authorized(n))
+			.collect())
+	}
+
+	/// List the users on the roster that don't exist on the system
+	pub fn missing_users(&self) -> Result<Vec<String>, i32> {
+		self.find_missing_users(|n| user_exists(&n))
+	}
+
+	/// List the users on the roster that aren't in /etc/passwd under the root directory `root`
+	#[cfg(target_os = "linux")]
+	pub fn missing_users_in<T: ToString>(&self, root: &T) -> Result<Vec<String>, i32> {
+		self.find_missing_users(|n| user_exists_in(root, &n))
+	}
+
+	fn find_missing_users<E>(&self, exists: E) -> Result<Vec<String>, i32>
+	where
+		E: Fn(&str) -> Result<bool, i32>,
+	{
+		let mut missing = Vec::new();
+
+		for name in self.users.iter().chain(self.admins.iter()) {
+			if !exists(name)? {
+				missing.push(name.clone());
+			}
+		}
+
+		Ok(missing)
+	}
+
+	/// List the users with administrator privileges who aren't authorized administrators
+	///
+	/// Candidates are the human users, every UID 0 account, and members of the sudo, admin and wheel groups.
+	/// Each is then checked with [`user_is_admin`]. Root itself is never reported, and neither are authorized
+	/// standard users, who [`Roster::admins_to_demote`] covers instead. Group members with no account are skipped.
+	#[cfg(target_os = "linux")]
+	pub fn unauthorized_admins(&self) -> Result<Vec<String>, i32> {
+		self.find_unauthorized_admins(&"/", |n| user_exists(&n), |n| user_is_admin(&n))
+	}
+
+	/// List the users with administrator privileges who aren't authorized administrators under the root directory `root`
+	///
+	/// This works like [`Roster::unauthorized_admins`], but reads passwd, group and sudoers under `root`.
+	#[cfg(target_os = "linux")]
+	pub fn unauthorized_admins_in<T: ToString>(&self, root: &T) -> Result<Vec<String>, i32> {
+		self.find_unauthorized_admins(root, |n| user_exists_in(root, &n), |n| user_is_admin_in(root, &n))
+	}
+
+	#[cfg(target_os = "linux")]
+	fn find_unauthorized_admins<T, E, A>(&self, root: &T, exists: E, is_admin: A) -> Result<Vec<String>, i32>
+	where
+		T: ToString,
+		E: Fn(&str) -> Result<bool, i32>,
+		A: Fn(&str) -> Result<bool, i32>,
+	{
+		let mut candidates: BTreeSet<String> = BTreeSet::new();
+
+		candidates.extend(list_human_users_in(root)?.into_iter().map(|u| u.username));
+		candidates.extend(list_root_users_in(root)?.into_iter().map(|u| u.username));
+		for group in list_groups_in(root)? {
+			if ADMIN_GROUPS.contains(&group.groupname.as_str()) {
+				candidates.extend(group.list);
+			}
+		}
+
+		let mut unauthorized = Vec::new();
+		for name in candidates {
+			if name == "root" || self.is_authorized(&name) {
+				continue;
+			}
+
+			if exists(&name)? && is_admin(&name)? {
+				unauthorized.push(name);
+			}
+		}
+
+		Ok(unauthorized)
+	}
+
+	/// List the authorized standard users who have administrator privileges and should be demoted
+	pub fn admins_to_demote(&self) -> Result<Vec<String>, i32> {
+		self.find_admins_to_demote(|n| user_exists(&n), |n| user_is_admin(&n))
+	}
+
+	/// List the authorized standard users who have administrator privileges under the root directory `root`
+	#[cfg(target_os = "linux")]
+	pub fn admins_to_demote_in<T: ToString>(&self, root: &T) -> Result<Vec<String>, i32> {
+		self.find_admins_to_demote(|n| user_exists_in(root, &n), |n| user_is_admin_in(root, &n))
+	}
+
+	fn find_admins_to_demote<E, A>(&self, exists: E, is_admin: A) -> Result<Vec<String>, i32>
+	where
+		E: Fn(&str) -> Result<bool, i32>,
+		A: Fn(&str) -> Result<bool, i32>,
+	{
+		let mut demote = Vec::new();
+
+		for name in self.users.iter().filter(|n| !self.admins.contains(*n)) {
+			if exists(name)? && is_admin(name)? {
+				demote.push(name.clone());
+			}
+		}
+
+		Ok(demote)
+	}
+
+	/// Collect every way the system disagrees with the roster
+	pub fn issues(&self) -> Result<Vec<RosterIssue>, i32> {
+		let mut issues = Vec::new();
+
+		#[cfg(target_os = "linux")]
+		{
+			issues.extend(self.unauthorized_users()?.into_iter().map(RosterIssue::UnauthorizedUser));
+		}
+		issues.extend(self.missing_users()?.into_iter().map(RosterIssue::MissingUser));
+		#[cfg(target_os = "linux")]
+		{
+			issues.extend(self.unauthorized_admins()?.into_iter().map(RosterIssue::UnauthorizedAdmin));
+		}
+		issues.extend(self.admins_to_demote()?.into_iter().map(RosterIssue::AdminToDemote));
+
+		Ok(issues)
+	}
+
+	/// Collect every way the files under the root directory `root` disagree with the roster
+	#[cfg(target_os = "linux")]
+	pub fn issues_in<T: ToString>(&self, root: &T) -> Result<Vec<RosterIssue>, i32> {
+		let mut issues = Vec::new();
+
+		issues.extend(self.unauthorized_users_in(root)?.into_iter().map(RosterIssue::UnauthorizedUser));
+		issues.extend(self.missing_users_in(root)?.into_iter().map(RosterIssue::MissingUser));
+		issues.extend(self.unauthorized_admins_in(root)?.into_iter().map(RosterIssue::UnauthorizedAdmin));
+		issues.extend(self.admins_to_demote_in(root)?.into_iter().map(RosterIssue::AdminToDemote));
+
+		Ok(issues)
+	}
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+
+	const README: &str = "\
+Authorized Administrators:
+perry (you)
+	password: securepassword
+carl
+	password: CarlIsCool123
+
+Authorized Users:
+candace
+isabella
+ferb
+";
+
+	#[test]
+	fn parse_readme() {
+		let roster = Roster::parse(&README).unwrap();
+
+		assert_eq!(roster.admins.iter().collect::<Vec<_>>(), ["carl", "perry"]);
+		assert_eq!(roster.users.iter().collect::<Vec<_>>(), ["candace", "ferb", "isabella"]);
+		assert!(roster.is_authorized_admin(&"perry"));
+		assert!(roster.is_authorized(&"candace") && !roster.is_authorized_admin(&"candace"));
+
+		assert!(Roster::parse(&"perry\nAuthorized Users:\ncandace\n").is_err());
+	}
+
+	#[test]
+	fn compare_against_system() {
+		let root = test_root("roster-system");
+		write_rooted(&root, "/etc/passwd", "\
+root:x:0:0:root:/root:/bin/bash
+toor:x:0:0::/root:/bin/bash
+daemon:x:1:1:daemon:/usr/sbin:/usr/sbin/nologin
+perry:x:1000:1000::/home/perry:/bin/bash
+carl:x:1001:1001::/home/carl:/bin/bash
+candace:x:1002:1002::/home/candace:/bin/bash
+isabella:x:1003:1003::/home/isabella:/bin/bash
+doof:x:1004:1004::/home/doof:/bin/bash
+nobody:x:65534:65534:nobody:/nonexistent:/usr/sbin/nologin
+");
+		write_rooted(&root, "/etc/group", "\
+root:x:0:
+sudo:x:27:perry,isabella,doof,ghost
+perry:x:1000:
+carl:x:1001:
+");
+		write_rooted(&root, "/etc/sudoers", "%sudo ALL=(ALL:ALL) ALL\ncarl ALL=(ALL) ALL\n");
+		let root_s = root.display();
+		let roster = Roster::parse(&README).unwrap();
+
+		assert_eq!(roster.unauthorized_users_in(&root_s).unwrap(), ["doof"]);
+		assert_eq!(roster.missing_users_in(&root_s).unwrap(), ["ferb"]);
+		// isabella is an authorized user, so only shows up as one to demote
+		assert_eq!(roster.unauthorized_admins_in(&root_s).unwrap(), ["doof", "toor"]);
+		assert_eq!(roster.admins_to_demote_in(&root_s).unwrap(), ["isabella"]);
+
+		assert_eq!(roster.issues_in(&root_s).unwrap(), [
+			RosterIssue::UnauthorizedUser("doof".to_string()),
+			RosterIssue::MissingUser("ferb".to_string()),
+			RosterIssue::UnauthorizedAdmin("doof".to_string()),
+			RosterIssue::UnauthorizedAdmin("toor".to_string()),
+			RosterIssue::AdminToDemote("isabella".to_string()),
+		]);
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+}
@@ -0,0 +1,568 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+//! # systemd units
+//!
+//! Works out whether units are enabled or masked by reading unit files and the symlinks `systemctl enable` leaves behind,
+//! so it works against a fixture root or an image whose systemd isn't running.
+//! Whether a unit is running can only come from systemd itself, so [`unit_status`] asks `systemctl show`.
+
+use std::{
+	collections::BTreeSet,
+	path::{Path, PathBuf},
+	process::{Command, Stdio},
+	string::String,
+	vec::Vec,
+};
+
+use super::{io_errno, rooted_path};
+
+/// Directories unit files are loaded from, highest priority first
+const UNIT_DIRS: [&str; 5] = [
+	"/etc/systemd/system",
+	"/run/systemd/system",
+	"/usr/local/lib/systemd/system",
+	"/usr/lib/systemd/system",
+	"/lib/systemd/system",
+];
+
+/// Directories `systemctl enable` and `systemctl mask` write to, with whether they only last until reboot
+const CONFIG_DIRS: [(&str, bool); 2] = [("/etc/systemd/system", false), ("/run/systemd/system", true)];
+
+/// Suffixes of the dependency directories enabling a unit creates symlinks in
+const DEPENDENCY_SUFFIXES: [&str; 3] = [".wants", ".requires", ".upholds"];
+
+/// Unit types, for telling `ssh` apart from `ssh.socket`
+const UNIT_TYPES: [&str; 11] = ["service", "socket", "target", "timer", "mount", "automount", "path", "slice", "scope", "swap", "device"];
+
+/// Longest chain of symlinks followed before giving up, in case of loops
+const MAX_LINK_DEPTH: usize = 16;
+
+/// Whether a unit starts at boot, as `systemctl is-enabled` reports it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UnitFileState {
+	/// Symlinked into a dependency directory or aliased in /etc/systemd/system
+	Enabled,
+	/// Enabled only in /run/systemd/system, so until the next reboot
+	EnabledRuntime,
+	/// Has an `[Install]` section but isn't enabled
+	Disabled,
+	/// Has no `[Install]` section, so it only starts when something else pulls it in
+	Static,
+	/// Its `[Install]` section only has `Also=`, so enabling it enables other units instead
+	Indirect,
+	/// Symlinked to /dev/null, or empty, so it can't be started at all
+	Masked,
+}
+
+impl UnitFileState {
+	/// Check if the unit starts at boot
+	pub fn is_enabled(&self) -> bool {
+		matches!(self, UnitFileState::Enabled | UnitFileState::EnabledRuntime)
+	}
+}
+
+/// A parsed unit file
+///
+/// Drop-ins under `<unit>.d` aren't merged, which matches how `systemctl enable` reads `[Install]` sections.
+#[derive(Clone, Debug, Default)]
+pub struct UnitFile {
+	/// Every `(section, key, value)` assignment in file order, with continuation lines joined
+	pub entries: Vec<(String, String, String)>,
+}
+
+impl UnitFile {
+	/// Parse the contents of a unit file
+	pub fn parse<T: ToString>(text: &T) -> UnitFile {
+		let mut entries = Vec::new();
+		let mut section = String::new();
+		let mut pending = String::new();
+
+		for line in text.to_string().lines() {
+			let line = line.trim();
+			if pending.is_empty() && (line.is_empty() || line.starts_with('#') || line.starts_with(';')) {
+				continue;
+			}
+
+			if let Some(rest) = line.strip_suffix('\\') {
+				pending.push_str(rest.trim_end());
+				pending.push(' ');
+				continue;
+			}
+			pending.push_str(line);
+			let full = std::mem::take(&mut pending);
+
+			if let Some(name) = full.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+				section = name.to_string();
+			} else if let Some((key, value)) = full.split_once('=') {
+				entries.push((section.clone(), key.trim().to_string(), value.trim().to_string()));
+			}
+		}
+
+		UnitFile { entries }
+	}
+
+	/// Read and parse the unit file at `path`
+	pub fn parse_file<T: ToString>(path: &T) -> Result<UnitFile, i32> {
+		Ok(UnitFile::parse(&std::fs::read_to_string(path.to_string()).map_err(io_errno)?))
+	}
+
+	/// Get the last value assigned to `key` in `section`
+	pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+		self.entries.iter().rev()
+			.find(|(s, k, _)| s == section && k == key)
+			.map(|(_, _, v)| v.as_str())
+	}
+
+	/// Get every whitespace separated value assigned to a list setting like `WantedBy=`, where an empty assignment clears the list
+	pub fn get_list(&self, section: &str, key: &str) -> Vec<String> {
+		let mut values = Vec::new();
+		for (_, _, value) in self.entries.iter().filter(|(s, k, _)| s == section && k == key) {
+			if value.is_empty() {
+				values.clear();
+			}
+			values.extend(value.split_whitespace().map(|v| v.to_string()));
+		}
+		values
+	}
+}
+
+/// What a unit name resolves to on disk
+#[derive(Clone, Debug)]
+pub struct UnitInfo {
+	/// The unit's real name, after following aliases
+	pub name: String,
+	/// The name that was asked for, which differs from `name` if it's an alias
+	pub requested: String,
+	/// Where the unit file is, as a path on the system rather than under any root, or /dev/null if masked
+	pub path: PathBuf,
+	pub state: UnitFileState,
+	/// The parsed unit file, empty if masked
+	pub file: UnitFile,
+}
+
+impl UnitInfo {
+	/// Check if the name asked for is an alias of another unit
+	pub fn is_alias(&self) -> bool {
+		self.name != self.requested
+	}
+}
+
+/// Add `.service` to a unit name without a type, the way systemctl does
+pub fn normalize_unit_name<T: ToString>(name: &T) -> String {
+	let name = name.to_string();
+	match name.rsplit_once('.') {
+		Some((_, suffix)) if UNIT_TYPES.contains(&suffix) => name,
+		_ => format!("{}.service", name),
+	}
+}
+
+/// Get the template a unit instance comes from, like `getty@.service` for `getty@tty1.service`
+fn template_name(name: &str) -> Option<String> {
+	let (prefix, rest) = name.split_once('@')?;
+	let (instance, suffix) = rest.rsplit_once('.')?;
+	if instance.is_empty() {
+		None
+	} else {
+		Some(format!("{}@.{}", prefix, suffix))
+	}
+}
+
+fn file_name(path: &Path) -> String {
+	path.file_name().unwrap_or_default().to_string_lossy().to_string()
+}
+
+/// Read where the symlink at the system path `path` points, as a system path, or [`None`] if it isn't a symlink
+fn link_target(root: &str, path: &Path) -> Option<PathBuf> {
+	let target = std::fs::read_link(rooted_path(&root, &path.display().to_string())).ok()?;
+	if target.is_absolute() {
+		Some(target)
+	} else {
+		Some(path.parent().unwrap_or(Path::new("/")).join(target))
+	}
+}
+
+/// Find the highest priority unit file named `name`, as a system path
+fn find_unit_file(root: &str, name: &str) -> Option<PathBuf> {
+	UNIT_DIRS.iter()
+		.map(|d| Path::new(d).join(name))
+		.find(|p| std::fs::symlink_metadata(rooted_path(&root, &p.display().to_string())).is_ok())
+}
+
+/// Check if any of `names` is symlinked into a dependency directory or as an alias under the config directory `dir`
+fn is_linked(root: &str, dir: &str, unit: &str, names: &[String]) -> bool {
+	let matches = |path: &Path| {
+		names.contains(&file_name(path)) || link_target(root, path).is_some_and(|t| file_name(&t) == unit)
+	};
+
+	for entry in std::fs::read_dir(rooted_path(&root, dir)).into_iter().flatten().filter_map(|e| e.ok()) {
+		let entry_name = entry.file_name().to_string_lossy().to_string();
+		let system_path = Path::new(dir).join(&entry_name);
+
+		if DEPENDENCY_SUFFIXES.iter().any(|s| entry_name.ends_with(s)) {
+			let links = std::fs::read_dir(entry.path()).into_iter().flatten().filter_map(|e| e.ok());
+			if links.map(|l| system_path.join(l.file_name())).any(|p| matches(&p)) {
+				return true;
+			}
+		} else if entry_name != unit && names.contains(&entry_name) && link_target(root, &system_path).is_some_and(|t| file_name(&t) == unit) {
+			return true;
+		}
+	}
+
+	false
+}
+
+/// Look up the unit named `name`
+pub fn unit_info<T: ToString>(name: &T) -> Result<UnitInfo, i32> {
+	unit_info_in(&"/", name)
+}
+
+/// Look up the unit named `name` under the root directory `root`
+///
+/// A name without a type, like `ssh`, is taken to be a service. Aliases are followed to the real unit,
+/// and instances like `getty@tty1.service` fall back to their template's file.
+/// Returns `Err(ENOENT)` if there's no such unit.
+pub fn unit_info_in<A: ToString, B: ToString>(root: &A, name: &B) -> Result<UnitInfo, i32> {
+	let root = root.to_string();
+	let requested = normalize_unit_name(name);
+
+	let mut path = find_unit_file(&root, &requested)
+		.or_else(|| template_name(&requested).and_then(|t| find_unit_file(&root, &t)))
+		.ok_or(libc::ENOENT)?;
+
+	let mut unit = requested.clone();
+	for _ in 0..MAX_LINK_DEPTH {
+		let target = match link_target(&root, &path) {
+			Some(t) => t,
+			None => break,
+		};
+
+		if target == Path::new("/dev/null") {
+			return Ok(UnitInfo { name: unit, requested, path: target, state: UnitFileState::Masked, file: UnitFile::default() });
+		}
+
+		// A link to a file with another name makes this an alias, unless it's an instance pointing at its template
+		let target_name = file_name(&target);
+		if target_name != unit && Some(target_name.clone()) != template_name(&unit) {
+			unit = target_name;
+		}
+		path = target;
+	}
+
+	let file_path = rooted_path(&root, &path.display().to_string());
+	let contents = std::fs::read_to_string(&file_path).map_err(io_errno)?;
+	if contents.trim().is_empty() {
+		return Ok(UnitInfo { name: unit, requested, path, state: UnitFileState::Masked, file: UnitFile::default() });
+	}
+
+	let file = UnitFile::parse(&contents);
+	let mut names = file.get_list("Install", "Alias");
+	names.push(unit.clone());
+	names.push(requested.clone());
+
+	let state = if let Some((_, runtime)) = CONFIG_DIRS.iter().find(|(dir, _)| is_linked(&root, dir, &unit, &names)) {
+		if *runtime { UnitFileState::EnabledRuntime } else { UnitFileState::Enabled }
+	} else if ["WantedBy", "RequiredBy", "UpheldBy", "Alias"].iter().any(|k| !file.get_list("Install", k).is_empty()) {
+		UnitFileState::Disabled
+	} else if !file.get_list("Install", "Also").is_empty() {
+		UnitFileState::Indirect
+	} else {
+		UnitFileState::Static
+	};
+
+	Ok(UnitInfo { name: unit, requested, path, state, file })
+}
+
+/// Get whether the unit named `name` starts at boot
+pub fn unit_file_state<T: ToString>(name: &T) -> Result<UnitFileState, i32> {
+	unit_file_state_in(&"/", name)
+}
+
+/// Get whether the unit named `name` starts at boot under the root directory `root`
+pub fn unit_file_state_in<A: ToString, B: ToString>(root: &A, name: &B) -> Result<UnitFileState, i32> {
+	Ok(unit_info_in(root, name)?.state)
+}
+
+/// Check if the unit named `name` is enabled, counting a missing unit as not enabled
+pub fn service_enabled<T: ToString>(name: &T) -> bool {
+	service_enabled_in(&"/", name)
+}
+
+/// Check if the unit named `name` is enabled under the root directory `root`, counting a missing unit as not enabled
+pub fn service_enabled_in<A: ToString, B: ToString>(root: &A, name: &B) -> bool {
+	unit_file_state_in(root, name).is_ok_and(|s| s.is_enabled())
+}
+
+/// Check if the unit named `name` is masked
+pub fn service_masked<T: ToString>(name: &T) -> bool {
+	service_masked_in(&"/", name)
+}
+
+/// Check if the unit named `name` is masked under the root directory `root`
+pub fn service_masked_in<A: ToString, B: ToString>(root: &A, name: &B) -> bool {
+	unit_file_state_in(root, name).is_ok_and(|s| s == UnitFileState::Masked)
+}
+
+/// List the units symlinked into a dependency directory, which is what enabling most units does
+pub fn enabled_units() -> Result<Vec<String>, i32> {
+	enabled_units_in(&"/")
+}
+
+/// List the units symlinked into a dependency directory under the root directory `root`, by their real names
+///
+/// Masked units are left out, since they can't start even when something wants them.
+pub fn enabled_units_in<T: ToString>(root: &T) -> Result<Vec<String>, i32> {
+	let root = root.to_string();
+	let mut linked = BTreeSet::new();
+
+	for (dir, _) in CONFIG_DIRS {
+		for entry in std::fs::read_dir(rooted_path(&root, dir)).into_iter().flatten().filter_map(|e| e.ok()) {
+			let entry_name = entry.file_name().to_string_lossy().to_string();
+			if !DEPENDENCY_SUFFIXES.iter().any(|s| entry_name.ends_with(s)) {
+				continue;
+			}
+
+			for link in std::fs::read_dir(entry.path()).into_iter().flatten().filter_map(|e| e.ok()) {
+				linked.insert(link.file_name().to_string_lossy().to_string());
+			}
+		}
+	}
+
+	let mut units = BTreeSet::new();
+	for name in linked {
+		match unit_info_in(&root, &name) {
+			Ok(info) if info.state != UnitFileState::Masked => { units.insert(info.name); },
+			Ok(_) => (),
+			Err(e) if e == libc::ENOENT => (),
+			Err(e) => return Err(e),
+		}
+	}
+
+	Ok(units.into_iter().collect())
+}
+
+/// Whether a unit is running, as systemd reports it
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ActiveState {
+	Active,
+	Reloading,
+	Inactive,
+	Failed,
+	Activating,
+	Deactivating,
+	/// Anything newer systemd versions add, like `maintenance` or `refreshing`
+	Other(String),
+}
+
+impl ActiveState {
+	fn parse(s: &str) -> ActiveState {
+		match s {
+			"active" => ActiveState::Active,
+			"reloading" => ActiveState::Reloading,
+			"inactive" => ActiveState::Inactive,
+			"failed" => ActiveState::Failed,
+			"activating" => ActiveState::Activating,
+			"deactivating" => ActiveState::Deactivating,
+			other => ActiveState::Other(other.to_string()),
+		}
+	}
+
+	/// Check if the unit is running, counting reloads
+	pub fn is_active(&self) -> bool {
+		matches!(self, ActiveState::Active | ActiveState::Reloading)
+	}
+}
+
+/// A unit's runtime state, from `systemctl show`
+#[derive(Clone, Debug)]
+pub struct UnitStatus {
+	pub name: String,
+	/// Like `loaded`, `not-found` or `masked`
+	pub load_state: String,
+	pub active_state: ActiveState,
+	/// The type specific state, like `running` or `exited` for services
+	pub sub_state: String,
+	/// The state `systemctl is-enabled` would print, like `enabled` or `masked`
+	pub unit_file_state: String,
+	/// The main process of a running service
+	pub main_pid: Option<u32>,
+}
+
+impl UnitStatus {
+	/// Parse the `Key=value` output of `systemctl show`
+	///
+	/// Returns `Err(-1)` if there's no `ActiveState`.
+	pub fn parse_show<T: ToString>(output: &T) -> Result<UnitStatus, i32> {
+		let output = output.to_string();
+		let get = |key: &str| output.lines()
+			.find_map(|l| l.strip_prefix(key).and_then(|l| l.strip_prefix('=')))
+			.unwrap_or("")
+			.to_string();
+
+		let active = get("ActiveState");
+		if active.is_empty() {
+			return Err(-1);
+		}
+
+		Ok(UnitStatus {
+			name: get("Id"),
+			load_state: get("LoadState"),
+			active_state: ActiveState::parse(&active),
+			sub_state: get("SubState"),
+			unit_file_state: get("UnitFileState"),
+			main_pid: get("MainPID").parse().ok().filter(|p| *p != 0),
+		})
+	}
+}
+
+/// Ask systemd for the runtime state of the unit named `name` with `systemctl show`
+///
+/// Returns `Err(-1)` if systemctl fails, which is the case when systemd isn't running, like in most containers.
+/// A unit that doesn't exist isn't an error, it has a `load_state` of `not-found`.
+pub fn unit_status<T: ToString>(name: &T) -> Result<UnitStatus, i32> {
+	let output = Command::new("systemctl")
+		.args(["show", "--property=Id,LoadState,ActiveState,SubState,UnitFileState,MainPID", &normalize_unit_name(name)])
+		.stderr(Stdio::null()).stdout(Stdio::piped())
+		.output().map_err(io_errno)?;
+
+	if !output.status.success() {
+		return Err(-1);
+	}
+
+	UnitStatus::parse_show(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Check if the unit named `name` is running, according to systemd
+pub fn service_active<T: ToString>(name: &T) -> Result<bool, i32> {
+	Ok(unit_status(name)?.active_state.is_active())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+	use std::os::unix::fs::symlink;
+
+	const LIB: &str = "/usr/lib/systemd/system";
+
+	fn link(root: &Path, path: &str, target: &str) {
+		let path = rooted_path(&root.display(), path);
+		std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+		symlink(target, path).unwrap();
+	}
+
+	fn fixture(name: &str) -> PathBuf {
+		let root = test_root(&format!("service-{}", name));
+
+		write_rooted(&root, &format!("{}/ssh.service", LIB), "\
+[Unit]
+Description=OpenBSD Secure Shell server
+
+[Service]
+ExecStart=/usr/sbin/sshd -D
+
+[Install]
+WantedBy=multi-user.target
+Alias=sshd.service
+");
+		link(&root, "/etc/systemd/system/multi-user.target.wants/ssh.service", &format!("{}/ssh.service", LIB));
+		link(&root, "/etc/systemd/system/sshd.service", &format!("{}/ssh.service", LIB));
+
+		write_rooted(&root, &format!("{}/cups.service", LIB), "[Service]\nExecStart=/usr/sbin/cupsd -l\n\n[Install]\nWantedBy=multi-user.target\n");
+
+		write_rooted(&root, &format!("{}/telnet.service", LIB), "[Service]\nExecStart=/usr/sbin/in.telnetd\n\n[Install]\nWantedBy=multi-user.target\n");
+		link(&root, "/etc/systemd/system/telnet.service", "/dev/null");
+		link(&root, "/etc/systemd/system/multi-user.target.wants/telnet.service", &format!("{}/telnet.service", LIB));
+
+		write_rooted(&root, &format!("{}/vsftpd.service", LIB), "[Service]\nExecStart=/usr/sbin/vsftpd\n\n[Install]\nWantedBy=multi-user.target\n");
+		write_rooted(&root, "/etc/systemd/system/vsftpd.service", "");
+
+		write_rooted(&root, &format!("{}/apache2.service", LIB), "[Service]\nExecStart=/usr/sbin/apachectl start\n\n[Install]\nWantedBy=multi-user.target\n");
+		link(&root, "/run/systemd/system/multi-user.target.wants/apache2.service", &format!("{}/apache2.service", LIB));
+
+		write_rooted(&root, &format!("{}/systemd-journald.service", LIB), "[Service]\nExecStart=/usr/lib/systemd/systemd-journald\n");
+		write_rooted(&root, &format!("{}/rsync.socket", LIB), "[Socket]\nListenStream=873\n\n[Install]\nAlso=rsync.service\n");
+
+		write_rooted(&root, &format!("{}/getty@.service", LIB), "[Service]\nExecStart=-/sbin/agetty %I\n\n[Install]\nWantedBy=getty.target\n");
+		link(&root, "/etc/systemd/system/getty.target.wants/getty@tty1.service", &format!("{}/getty@.service", LIB));
+
+		root
+	}
+
+	#[test]
+	fn unit_file_states() {
+		let root = fixture("states");
+		let root_s = root.display();
+		let state = |name: &str| unit_file_state_in(&root_s, &name);
+
+		assert_eq!(state("ssh"), Ok(UnitFileState::Enabled));
+		assert_eq!(state("cups.service"), Ok(UnitFileState::Disabled));
+		assert_eq!(state("telnet"), Ok(UnitFileState::Masked));
+		assert_eq!(state("vsftpd"), Ok(UnitFileState::Masked));
+		assert_eq!(state("apache2"), Ok(UnitFileState::EnabledRuntime));
+		assert_eq!(state("systemd-journald"), Ok(UnitFileState::Static));
+		assert_eq!(state("rsync.socket"), Ok(UnitFileState::Indirect));
+		assert_eq!(state("nginx"), Err(libc::ENOENT));
+
+		assert!(service_enabled_in(&root_s, &"apache2"));
+		assert!(!service_enabled_in(&root_s, &"telnet"));
+		assert!(service_masked_in(&root_s, &"vsftpd"));
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn aliases_and_instances() {
+		let root = fixture("aliases");
+		let root_s = root.display();
+
+		let sshd = unit_info_in(&root_s, &"sshd").unwrap();
+		assert!(sshd.is_alias());
+		assert_eq!(sshd.name, "ssh.service");
+		assert_eq!(sshd.requested, "sshd.service");
+		assert_eq!(sshd.path, Path::new(LIB).join("ssh.service"));
+		assert_eq!(sshd.state, UnitFileState::Enabled);
+		assert_eq!(sshd.file.get("Service", "ExecStart"), Some("/usr/sbin/sshd -D"));
+
+		let masked = unit_info_in(&root_s, &"telnet").unwrap();
+		assert_eq!(masked.path, Path::new("/dev/null"));
+
+		// Instances have no file of their own, so they use the template's
+		let tty1 = unit_info_in(&root_s, &"getty@tty1.service").unwrap();
+		assert!(!tty1.is_alias());
+		assert_eq!(tty1.path, Path::new(LIB).join("getty@.service"));
+		assert_eq!(tty1.state, UnitFileState::Enabled);
+		assert_eq!(unit_file_state_in(&root_s, &"getty@tty2.service"), Ok(UnitFileState::Disabled));
+
+		assert_eq!(enabled_units_in(&root_s).unwrap(), ["apache2.service", "getty@tty1.service", "ssh.service"]);
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn parse_systemctl_show() {
+		let status = UnitStatus::parse_show(&"\
+Id=ssh.service
+LoadState=loaded
+ActiveState=active
+SubState=running
+UnitFileState=enabled
+MainPID=812
+").unwrap();
+		assert_eq!(status.name, "ssh.service");
+		assert_eq!(status.load_state, "loaded");
+		assert!(status.active_state.is_active());
+		assert_eq!(status.sub_state, "running");
+		assert_eq!(status.unit_file_state, "enabled");
+		assert_eq!(status.main_pid, Some(812));
+
+		let missing = UnitStatus::parse_show(&"Id=nginx.service\nLoadState=not-found\nActiveState=inactive\nSubState=dead\nUnitFileState=\nMainPID=0\n").unwrap();
+		assert_eq!(missing.active_state, ActiveState::Inactive);
+		assert_eq!(missing.main_pid, None);
+
+		assert_eq!(UnitStatus::parse_show(&"").map(|s| s.name), Err(-1));
+	}
+}
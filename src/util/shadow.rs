@@ -0,0 +1,278 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+use std::{
+	string::String,
+	vec::Vec,
+};
+
+use super::{io_errno, rooted_path};
+
+/// The algorithm used to hash a password in /etc/shadow, identified by its `$id$` prefix
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum HashAlgorithm {
+	/// Traditional DES crypt, 13 characters with no prefix
+	Des,
+	/// MD5 crypt, `$1$`
+	Md5,
+	/// bcrypt, `$2a$`, `$2b$` or `$2y$`
+	Bcrypt,
+	/// SHA-256 crypt, `$5$`
+	Sha256,
+	/// SHA-512 crypt, `$6$`
+	Sha512,
+	/// scrypt, `$7$`
+	Scrypt,
+	/// yescrypt, `$y$`
+	Yescrypt,
+	/// GOST yescrypt, `$gy$`
+	GostYescrypt,
+	/// Any other prefix, stored as is
+	Unknown(String),
+}
+
+impl HashAlgorithm {
+	/// Identify the algorithm of a crypt style hash, ignoring any leading `!` lock markers
+	///
+	/// Returns [`None`] for fields that don't hold a hash at all, like an empty field, `*` or `!`.
+	pub fn from_hash<T: ToString>(hash: &T) -> Option<HashAlgorithm> {
+		let hash = hash.to_string();
+		let hash = hash.trim_start_matches('!');
+
+		if hash.is_empty() || hash.starts_with('*') {
+			return None;
+		}
+
+		if !hash.starts_with('$') {
+			return if hash.len() == 13 { Some(HashAlgorithm::Des) } else { Some(HashAlgorithm::Unknown(String::new())) };
+		}
+
+		let id = hash[1..].split('$').next().unwrap_or("");
+		Some(match id {
+			"1" => HashAlgorithm::Md5,
+			"2a" | "2b" | "2y" => HashAlgorithm::Bcrypt,
+			"5" => HashAlgorithm::Sha256,
+			"6" => HashAlgorithm::Sha512,
+			"7" => HashAlgorithm::Scrypt,
+			"y" => HashAlgorithm::Yescrypt,
+			"gy" => HashAlgorithm::GostYescrypt,
+			other => HashAlgorithm::Unknown(other.to_string()),
+		})
+	}
+
+	/// Check if the algorithm is considered weak, meaning DES, MD5 or something unrecognized
+	pub fn is_weak(&self) -> bool {
+		matches!(self, HashAlgorithm::Des | HashAlgorithm::Md5 | HashAlgorithm::Unknown(_))
+	}
+}
+
+/// An entry to the /etc/shadow file.
+///
+/// Day counts are days since the epoch or plain numbers of days, exactly as stored.
+/// Empty fields are [`None`].
+#[derive(Clone)]
+pub struct ShadowEntry {
+	pub username: String,
+	pub password: String,
+	pub last_change: Option<i64>,
+	pub min_days: Option<i64>,
+	pub max_days: Option<i64>,
+	pub warn_days: Option<i64>,
+	pub inactive_days: Option<i64>,
+	pub expire_date: Option<i64>,
+}
+
+fn parse_days(field: &str) -> Result<Option<i64>, i32> {
+	if field.is_empty() {
+		Ok(None)
+	} else {
+		field.parse::<i64>().map(Some).map_err(|_| -1)
+	}
+}
+
+impl ShadowEntry {
+	/// Parse a shadow entry from a string
+	///
+	/// Returns `Err(-1)` if the line doesn't have nine fields or a numeric field isn't a number.
+	pub fn parse_entry<T: ToString>(entry: &T) -> Result<ShadowEntry, i32> {
+		let entry_str = entry.to_string();
+		let tokenized_entry: Vec<_> = entry_str.split(':').collect();
+
+		if tokenized_entry.len() != 9 {
+			return Err(-1);
+		}
+
+		Ok(ShadowEntry {
+			username: tokenized_entry[0].to_string(),
+			password: tokenized_entry[1].to_string(),
+			last_change: parse_days(tokenized_entry[2])?,
+			min_days: parse_days(tokenized_entry[3])?,
+			max_days: parse_days(tokenized_entry[4])?,
+			warn_days: parse_days(tokenized_entry[5])?,
+			inactive_days: parse_days(tokenized_entry[6])?,
+			expire_date: parse_days(tokenized_entry[7])?,
+		})
+	}
+
+	/// Parse every entry in a shadow formatted file
+	///
+	/// Blank lines and lines starting with `#` are skipped, any other malformed line is an error.
+	pub fn parse_file<T: ToString>(path: &T) -> Result<Vec<ShadowEntry>, i32> {
+		let contents = std::fs::read_to_string(path.to_string()).map_err(io_errno)?;
+
+		contents.lines()
+			.filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+			.map(|l| ShadowEntry::parse_entry(&l))
+			.collect()
+	}
+
+	/// Get the shadow entry for the user named `name` from /etc/shadow
+	pub fn get_entry<T: ToString>(name: &T) -> Result<ShadowEntry, i32> {
+		ShadowEntry::get_entry_in(&"/", name)
+	}
+
+	/// Get the shadow entry for the user named `name` from /etc/shadow under the root directory `root`
+	///
+	/// Returns `ENOENT` if there is no entry for the user.
+	pub fn get_entry_in<A: ToString, B: ToString>(root: &A, name: &B) -> Result<ShadowEntry, i32> {
+		let name = name.to_string();
+
+		list_shadow_in(root)?.into_iter()
+			.find(|e| e.username == name)
+			.ok_or(libc::ENOENT)
+	}
+
+	/// Check if the account has no password at all, allowing login without one
+	pub fn password_is_empty(&self) -> bool {
+		self.password.is_empty()
+	}
+
+	/// Check if the password is locked, as done by `passwd -l` or `usermod -L`
+	pub fn account_locked(&self) -> bool {
+		self.password.starts_with('!')
+	}
+
+	/// Check if the account has expired, given today's date in days since the epoch
+	pub fn account_expired(&self, today: i64) -> bool {
+		matches!(self.expire_date, Some(d) if d <= today)
+	}
+
+	/// Get the algorithm the password was hashed with, if there is a hash
+	pub fn hash_algorithm(&self) -> Option<HashAlgorithm> {
+		HashAlgorithm::from_hash(&self.password)
+	}
+
+	/// Get the maximum number of days a password may be used before it must be changed
+	pub fn password_max_days(&self) -> Option<i64> {
+		self.max_days
+	}
+
+	/// Get the minimum number of days between password changes
+	pub fn password_min_days(&self) -> Option<i64> {
+		self.min_days
+	}
+
+	/// Get the number of days of warning given before a password expires
+	pub fn password_warn_days(&self) -> Option<i64> {
+		self.warn_days
+	}
+}
+
+/// List every entry in /etc/shadow
+pub fn list_shadow() -> Result<Vec<ShadowEntry>, i32> {
+	list_shadow_in(&"/")
+}
+
+/// List every entry in /etc/shadow under the root directory `root`
+pub fn list_shadow_in<T: ToString>(root: &T) -> Result<Vec<ShadowEntry>, i32> {
+	ShadowEntry::parse_file(&rooted_path(root, "/etc/shadow").display())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+
+	#[test]
+	fn shadow_entries() {
+		let root = test_root("shadow-entries");
+		write_rooted(&root, "/etc/shadow", "\
+root:$y$j9T$F5Jx5fExrKuPp53xLKQ..1$X3DX6M94c7o.9agCG9G317fhZg9SqC.5i5rd.RhAtQ7:19500:0:99999:7:::
+daemon:*:19500:0:99999:7:::
+alice:!$6$saltsalt$hash:19600:1:90:14:30:20000:
+bob::19000::::::
+carol:!:19500:0:99999:7:::
+");
+		let entries = list_shadow_in(&root.display()).unwrap();
+		assert_eq!(entries.len(), 5);
+
+		let root_entry = &entries[0];
+		assert_eq!(root_entry.hash_algorithm(), Some(HashAlgorithm::Yescrypt));
+		assert!(!root_entry.account_locked() && !root_entry.password_is_empty());
+		assert_eq!(root_entry.password_max_days(), Some(99999));
+		assert_eq!(root_entry.inactive_days, None);
+		assert_eq!(root_entry.expire_date, None);
+
+		// `*` means no password can match, without being a `passwd -l` lock
+		assert_eq!(entries[1].hash_algorithm(), None);
+		assert!(!entries[1].account_locked());
+
+		let alice = &entries[2];
+		assert!(alice.account_locked());
+		assert_eq!(alice.hash_algorithm(), Some(HashAlgorithm::Sha512));
+		assert_eq!((alice.password_min_days(), alice.password_max_days(), alice.password_warn_days()), (Some(1), Some(90), Some(14)));
+		assert_eq!(alice.inactive_days, Some(30));
+		assert!(alice.account_expired(20000) && !alice.account_expired(19999));
+
+		let bob = &entries[3];
+		assert!(bob.password_is_empty());
+		assert_eq!(bob.hash_algorithm(), None);
+		assert_eq!((bob.last_change, bob.min_days, bob.max_days, bob.warn_days), (Some(19000), None, None, None));
+		assert!(!bob.account_expired(i64::MAX));
+
+		assert!(entries[4].account_locked());
+		assert_eq!(entries[4].hash_algorithm(), None);
+
+		assert_eq!(ShadowEntry::get_entry_in(&root.display(), &"bob").map(|e| e.username), Ok("bob".to_string()));
+		assert_eq!(ShadowEntry::get_entry_in(&root.display(), &"eve").map(|e| e.username), Err(libc::ENOENT));
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn malformed_entries() {
+		assert!(ShadowEntry::parse_entry(&"alice:x:1:2").is_err());
+		assert!(ShadowEntry::parse_entry(&"alice:x:soon:0:99999:7:::").is_err());
+	}
+
+	#[test]
+	fn hash_algorithms() {
+		let cases = [
+			("$1$saltsalt$abcdefghijklmnopqrstuv", Some(HashAlgorithm::Md5)),
+			("$5$rounds=5000$salt$hash", Some(HashAlgorithm::Sha256)),
+			("$6$salt$hash", Some(HashAlgorithm::Sha512)),
+			("$y$j9T$salt$hash", Some(HashAlgorithm::Yescrypt)),
+			("$gy$j9T$salt$hash", Some(HashAlgorithm::GostYescrypt)),
+			("$2b$12$R9h/cIPz0gi.URNNX3kh2OPST9/PgBkqquzi.Ss7KIUgO2t0jWMUW", Some(HashAlgorithm::Bcrypt)),
+			("$2y$10$abc", Some(HashAlgorithm::Bcrypt)),
+			("$7$CU..../....salt$hash", Some(HashAlgorithm::Scrypt)),
+			("abJnggxhB/yWI", Some(HashAlgorithm::Des)),
+			("$md5$salt$hash", Some(HashAlgorithm::Unknown("md5".to_string()))),
+			("!!$6$salt$hash", Some(HashAlgorithm::Sha512)),
+			("", None),
+			("*", None),
+			("!", None),
+			("!*", None),
+		];
+
+		for (hash, expected) in cases {
+			assert_eq!(HashAlgorithm::from_hash(&hash), expected, "{}", hash);
+		}
+
+		assert!(HashAlgorithm::Md5.is_weak() && HashAlgorithm::Des.is_weak());
+		assert!(!HashAlgorithm::Yescrypt.is_weak() && !HashAlgorithm::Sha512.is_weak());
+	}
+}
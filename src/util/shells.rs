@@ -0,0 +1,181 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+use std::{
+	string::String,
+	vec::Vec,
+};
+
+use super::{io_errno, rooted_path, list_users_in, LoginDefs, PasswdEntry};
+
+/// Shells that exist only to refuse a login
+const NOLOGIN_SHELLS: [&str; 2] = ["nologin", "false"];
+
+/// One problem with the login shell of an account
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ShellIssue {
+	/// A system account has a shell that allows interactive logins
+	SystemAccountShell { name: String, shell: String },
+	/// A human account has a shell that isn't listed in /etc/shells
+	InvalidShell { name: String, shell: String },
+}
+
+impl ShellIssue {
+	/// The name of the user the issue is about
+	pub fn name(&self) -> &str {
+		match self {
+			ShellIssue::SystemAccountShell { name, .. } | ShellIssue::InvalidShell { name, .. } => name.as_str(),
+		}
+	}
+
+	/// A short explanation, suitable for a score report entry
+	pub fn reason(&self) -> String {
+		match self {
+			ShellIssue::SystemAccountShell { name, shell } => format!("System account {} has login shell {}", name, shell),
+			ShellIssue::InvalidShell { name, shell } => format!("User {} has invalid shell {}", name, shell),
+		}
+	}
+}
+
+/// List the valid login shells in /etc/shells
+pub fn list_shells() -> Result<Vec<String>, i32> {
+	list_shells_in(&"/")
+}
+
+/// List the valid login shells in /etc/shells under the root directory `root`
+pub fn list_shells_in<T: ToString>(root: &T) -> Result<Vec<String>, i32> {
+	let contents = std::fs::read_to_string(rooted_path(root, "/etc/shells")).map_err(io_errno)?;
+
+	Ok(contents.lines()
+		.map(|l| l.trim())
+		.filter(|l| !l.is_empty() && !l.starts_with('#'))
+		.map(|l| l.to_string())
+		.collect())
+}
+
+/// Check if `shell` is one that refuses logins, like /usr/sbin/nologin or /bin/false
+pub fn shell_is_nologin<T: ToString>(shell: &T) -> bool {
+	let shell = shell.to_string();
+	let base = shell.rsplit('/').next().unwrap_or(&shell);
+	NOLOGIN_SHELLS.contains(&base)
+}
+
+/// Check if `shell` allows an interactive login, given the valid shells from /etc/shells
+///
+/// An empty shell field means /bin/sh, so it counts as interactive.
+pub fn shell_is_interactive<T: ToString>(shell: &T, valid_shells: &[String]) -> bool {
+	let shell = shell.to_string();
+	if shell.is_empty() {
+		return true;
+	}
+
+	!shell_is_nologin(&shell) && valid_shells.contains(&shell)
+}
+
+/// List the system accounts, other than root, that have an interactive login shell
+pub fn system_users_with_shells() -> Result<Vec<PasswdEntry>, i32> {
+	system_users_with_shells_in(&"/")
+}
+
+/// List the system accounts with an interactive login shell under the root directory `root`
+pub fn system_users_with_shells_in<T: ToString>(root: &T) -> Result<Vec<PasswdEntry>, i32> {
+	let defs = LoginDefs::load_in(root).unwrap_or_default();
+	let shells = list_shells_in(root)?;
+
+	Ok(list_users_in(root)?.into_iter()
+		.filter(|u| u.username != "root" && !defs.is_human_uid(u.uid))
+		.filter(|u| shell_is_interactive(&u.shell, &shells))
+		.collect())
+}
+
+/// List the human accounts whose shell is neither in /etc/shells nor a nologin shell
+pub fn human_users_with_invalid_shells() -> Result<Vec<PasswdEntry>, i32> {
+	human_users_with_invalid_shells_in(&"/")
+}
+
+/// List the human accounts with an invalid shell under the root directory `root`
+pub fn human_users_with_invalid_shells_in<T: ToString>(root: &T) -> Result<Vec<PasswdEntry>, i32> {
+	let defs = LoginDefs::load_in(root).unwrap_or_default();
+	let shells = list_shells_in(root)?;
+
+	Ok(list_users_in(root)?.into_iter()
+		.filter(|u| defs.is_human_uid(u.uid))
+		.filter(|u| !u.shell.is_empty() && !shell_is_nologin(&u.shell) && !shells.contains(&u.shell))
+		.collect())
+}
+
+/// Collect every login shell problem on the system
+pub fn audit_shells() -> Result<Vec<ShellIssue>, i32> {
+	audit_shells_in(&"/")
+}
+
+/// Collect every login shell problem under the root directory `root`
+pub fn audit_shells_in<T: ToString>(root: &T) -> Result<Vec<ShellIssue>, i32> {
+	let mut issues: Vec<ShellIssue> = system_users_with_shells_in(root)?.into_iter()
+		.map(|u| ShellIssue::SystemAccountShell { name: u.username, shell: u.shell })
+		.collect();
+
+	issues.extend(human_users_with_invalid_shells_in(root)?.into_iter()
+		.map(|u| ShellIssue::InvalidShell { name: u.username, shell: u.shell }));
+
+	Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+
+	#[test]
+	fn classify_shells() {
+		let valid = vec!["/bin/sh".to_string(), "/bin/bash".to_string(), "/usr/bin/zsh".to_string()];
+
+		assert!(shell_is_nologin(&"/usr/sbin/nologin") && shell_is_nologin(&"/sbin/nologin") && shell_is_nologin(&"/bin/false"));
+		assert!(!shell_is_nologin(&"/bin/bash") && !shell_is_nologin(&""));
+		assert!(shell_is_interactive(&"/bin/bash", &valid));
+		assert!(shell_is_interactive(&"", &valid));
+		assert!(!shell_is_interactive(&"/usr/sbin/nologin", &valid));
+		assert!(!shell_is_interactive(&"/bin/fish", &valid));
+	}
+
+	#[test]
+	fn audit_fixture_shells() {
+		let root = test_root("shells-audit");
+		write_rooted(&root, "/etc/shells", "\
+# /etc/shells: valid login shells
+/bin/sh
+/bin/bash
+
+  /usr/bin/zsh
+");
+		write_rooted(&root, "/etc/passwd", "\
+root:x:0:0:root:/root:/bin/bash
+daemon:x:1:1:daemon:/usr/sbin:/usr/sbin/nologin
+sync:x:4:65534:sync:/bin:/bin/sync
+games:x:5:60:games:/usr/games:/bin/bash
+www-data:x:33:33:www-data:/var/www:
+alice:x:1000:1000::/home/alice:/bin/bash
+bob:x:1001:1001::/home/bob:/home/bob/evil-shell
+carol:x:1002:1002::/home/carol:/bin/false
+dave:x:1003:1003::/home/dave:
+nobody:x:65534:65534:nobody:/nonexistent:/usr/sbin/nologin
+");
+
+		assert_eq!(list_shells_in(&root.display()).unwrap(), ["/bin/sh", "/bin/bash", "/usr/bin/zsh"]);
+		assert_eq!(audit_shells_in(&root.display()).unwrap(), [
+			ShellIssue::SystemAccountShell { name: "games".to_string(), shell: "/bin/bash".to_string() },
+			ShellIssue::SystemAccountShell { name: "www-data".to_string(), shell: String::new() },
+			ShellIssue::InvalidShell { name: "bob".to_string(), shell: "/home/bob/evil-shell".to_string() },
+		]);
+
+		// A login.defs with a higher UID_MIN turns alice into a system account
+		write_rooted(&root, "/etc/login.defs", "UID_MIN 1001\n");
+		let names: Vec<String> = system_users_with_shells_in(&root.display()).unwrap().into_iter().map(|u| u.username).collect();
+		assert_eq!(names, ["games", "www-data", "alice"]);
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+}
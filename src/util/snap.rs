@@ -0,0 +1,218 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+use std::{
+	process::{Command, Stdio},
+	string::String,
+	vec::Vec,
+};
+
+use serde_json::Value;
+
+use super::{io_errno, rooted_path};
+
+/// An installed snap
+#[derive(Clone, Debug, Default)]
+pub struct SnapPackage {
+	pub name: String,
+	/// Empty if it couldn't be found
+	pub version: String,
+	pub revision: String,
+	/// The channel being tracked, like `latest/stable`, or empty for locally installed snaps
+	pub channel: String,
+	/// Empty if it couldn't be found, which is always the case when read from snapd's state
+	pub publisher: String,
+	/// The notes column of `snap list`, like `classic` or `disabled`, empty if there are none
+	pub notes: Vec<String>,
+}
+
+impl SnapPackage {
+	/// Parse the output of `snap list`
+	///
+	/// The verified publisher marks, `✓` or `**`, are stripped from the publisher.
+	pub fn parse_list<T: ToString>(output: &T) -> Vec<SnapPackage> {
+		output.to_string().lines()
+			.skip_while(|l| !l.starts_with("Name"))
+			.skip(1)
+			.filter_map(|l| {
+				let cols: Vec<&str> = l.split_whitespace().collect();
+				if cols.len() < 5 {
+					return None;
+				}
+
+				let dash_empty = |s: &str| if s == "-" { String::new() } else { s.to_string() };
+				Some(SnapPackage {
+					name: cols[0].to_string(),
+					version: cols[1].to_string(),
+					revision: cols[2].to_string(),
+					channel: dash_empty(cols[3]),
+					publisher: cols[4].trim_end_matches(['✓', '*']).to_string(),
+					notes: cols.get(5)
+						.filter(|n| **n != "-")
+						.map(|n| n.split(',').map(|s| s.to_string()).collect())
+						.unwrap_or_default(),
+				})
+			})
+			.collect()
+	}
+
+	/// Parse snapd's state file, listing the active snaps from its `data.snaps` map
+	///
+	/// The state doesn't hold versions, so they are read from each snap's meta/snap.yaml under /snap in `root`, if mounted.
+	/// Returns `Err(-1)` if the state isn't valid JSON.
+	pub fn parse_state<A: ToString, B: ToString>(root: &A, state: &B) -> Result<Vec<SnapPackage>, i32> {
+		let json: Value = serde_json::from_str(&state.to_string()).map_err(|_| -1)?;
+		let snaps = match json.pointer("/data/snaps").and_then(|s| s.as_object()) {
+			Some(s) => s,
+			None => return Ok(Vec::new()),
+		};
+
+		let mut packages = Vec::new();
+		for (name, snap) in snaps {
+			// snapd leaves `active` out when it's false, as for disabled snaps
+			if snap.get("active").and_then(|a| a.as_bool()) != Some(true) {
+				continue;
+			}
+
+			let as_string = |v: Option<&Value>| match v {
+				Some(Value::String(s)) => s.clone(),
+				Some(Value::Number(n)) => n.to_string(),
+				_ => String::new(),
+			};
+
+			let revision = as_string(snap.get("current"));
+			let channel = as_string(snap.get("channel"));
+
+			// The confinement flags are embedded in the snap's own object rather than nested
+			let notes = ["classic", "devmode"].iter()
+				.filter(|f| snap.get(**f).and_then(|c| c.as_bool()) == Some(true))
+				.map(|f| f.to_string())
+				.collect();
+
+			let yaml = rooted_path(root, &format!("/snap/{}/{}/meta/snap.yaml", name, revision));
+			let version = std::fs::read_to_string(yaml).ok()
+				.and_then(|y| y.lines().find_map(|l| l.strip_prefix("version:").map(|v| v.trim().trim_matches(['\'', '"']).to_string())))
+				.unwrap_or_default();
+
+			packages.push(SnapPackage { name: name.clone(), version, revision, channel, publisher: String::new(), notes });
+		}
+
+		packages.sort_by(|a, b| a.name.cmp(&b.name));
+		Ok(packages)
+	}
+}
+
+/// List the installed snaps with `snap list`, falling back to snapd's state file if the command fails
+pub fn list_snaps() -> Result<Vec<SnapPackage>, i32> {
+	match Command::new("snap").args(["list"]).stderr(Stdio::null()).stdout(Stdio::piped()).output() {
+		Ok(output) if output.status.success() => Ok(SnapPackage::parse_list(&String::from_utf8_lossy(&output.stdout))),
+		_ => list_snaps_in(&"/"),
+	}
+}
+
+/// List the installed snaps from /var/lib/snapd/state.json under the root directory `root`, without snapd running
+pub fn list_snaps_in<T: ToString>(root: &T) -> Result<Vec<SnapPackage>, i32> {
+	let state = std::fs::read_to_string(rooted_path(root, "/var/lib/snapd/state.json")).map_err(io_errno)?;
+	SnapPackage::parse_state(root, &state)
+}
+
+/// Get the installed snap named exactly `name`
+pub fn snap_package<T: ToString>(name: &T) -> Result<Option<SnapPackage>, i32> {
+	let name = name.to_string();
+	Ok(list_snaps()?.into_iter().find(|s| s.name == name))
+}
+
+/// Get the installed snap named exactly `name` under the root directory `root`
+pub fn snap_package_in<A: ToString, B: ToString>(root: &A, name: &B) -> Result<Option<SnapPackage>, i32> {
+	let name = name.to_string();
+	Ok(list_snaps_in(root)?.into_iter().find(|s| s.name == name))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+
+	#[test]
+	fn snaps_from_state() {
+		let root = test_root("snap-state");
+		write_rooted(&root, "/var/lib/snapd/state.json", r#"{
+	"data": {
+		"api-download-tokens-secret": "mV8zl3sL5oVhTm0d9sAsvyx6cPZUl8BdbWb3kAeYJmM=",
+		"last-refresh": "2024-10-01T09:12:44.116403538Z",
+		"seeded": true,
+		"snaps": {
+			"core22": {
+				"type": "base",
+				"sequence": [{"name": "core22", "snap-id": "amcUKQILKXHHTlmSa7NMdnXSx02dNeeT", "revision": "1621"}],
+				"active": true,
+				"current": "1621",
+				"channel": "latest/stable"
+			},
+			"firefox": {
+				"type": "app",
+				"sequence": [
+					{"name": "firefox", "snap-id": "3wdHCAVyZEmYsCMFDE9qt92UV8rC8Wdk", "revision": "4793"},
+					{"name": "firefox", "snap-id": "3wdHCAVyZEmYsCMFDE9qt92UV8rC8Wdk", "revision": "4848"}
+				],
+				"active": true,
+				"current": "4848",
+				"channel": "latest/stable",
+				"last-refresh-time": "2024-09-30T18:02:11.511402119Z"
+			},
+			"code": {
+				"type": "app",
+				"sequence": [{"name": "code", "snap-id": "Ht0aUXmjA8QKpPL7EE5qQp5XdsyUfmjK", "revision": "159"}],
+				"active": true,
+				"current": "159",
+				"channel": "latest/stable",
+				"classic": true
+			},
+			"hacktool": {
+				"type": "app",
+				"sequence": [{"name": "hacktool", "snap-id": "", "revision": "x1"}],
+				"active": true,
+				"current": "x1",
+				"devmode": true
+			},
+			"hello": {
+				"type": "app",
+				"sequence": [{"name": "hello", "snap-id": "buPKUD3TKqCOgLEjjHx5kSiCpIs5cMuQ", "revision": "42"}],
+				"current": "42",
+				"channel": "latest/stable"
+			}
+		}
+	},
+	"changes": {},
+	"tasks": {},
+	"last-change-id": 12,
+	"last-task-id": 87,
+	"last-lane-id": 4,
+	"last-notice-id": 0
+}"#);
+		write_rooted(&root, "/snap/firefox/4848/meta/snap.yaml", "name: firefox\nversion: '131.0-1'\nsummary: Mozilla Firefox\n");
+
+		let snaps = list_snaps_in(&root.display()).unwrap();
+		let names: Vec<&str> = snaps.iter().map(|s| s.name.as_str()).collect();
+		// hello has no `active`, so it's disabled
+		assert_eq!(names, ["code", "core22", "firefox", "hacktool"]);
+
+		assert_eq!(snaps[0].notes, ["classic"]);
+		assert_eq!(snaps[2].version, "131.0-1");
+		assert_eq!(snaps[2].revision, "4848");
+		assert_eq!(snaps[2].channel, "latest/stable");
+		assert!(snaps[2].notes.is_empty());
+		assert_eq!(snaps[3].notes, ["devmode"]);
+		assert_eq!(snaps[3].channel, "");
+		assert_eq!(snap_package_in(&root.display(), &"hello").unwrap().map(|s| s.name), None);
+
+		// A state from before seeding has no snaps at all
+		assert!(SnapPackage::parse_state(&root.display(), &r#"{"data": {"seeded": false}, "changes": {}}"#).unwrap().is_empty());
+		assert_eq!(SnapPackage::parse_state(&root.display(), &"{").err(), Some(-1));
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+}
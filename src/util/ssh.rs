@@ -0,0 +1,382 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+use std::{
+	path::{Path, PathBuf},
+	string::String,
+	vec::Vec,
+};
+
+use base64::{Engine, engine::general_purpose::{STANDARD, STANDARD_NO_PAD}};
+use libc::uid_t;
+use sha2::{Digest, Sha256};
+
+use super::{io_errno, rooted_path, get_file_mode, get_file_owner_uid, list_users_in, LoginDefs, PasswdEntry};
+
+/// Files under ~/.ssh that sshd reads keys from by default
+const AUTHORIZED_KEYS_FILES: [&str; 2] = ["authorized_keys", "authorized_keys2"];
+
+/// Key type prefixes that start the key part of an authorized_keys line
+const KEY_TYPE_PREFIXES: [&str; 4] = ["ssh-", "ecdsa-sha2-", "sk-ssh-", "sk-ecdsa-"];
+
+/// Options on an authorized_keys line, as names and optional values
+pub type KeyOptions = Vec<(String, Option<String>)>;
+
+/// A single key from an authorized_keys file
+#[derive(Clone, Debug)]
+pub struct AuthorizedKey {
+	pub file: PathBuf,
+	pub line: usize,
+	/// Options in front of the key, like `command="..."` or `no-pty`, with quotes removed from values
+	pub options: KeyOptions,
+	pub key_type: String,
+	/// The base64 encoded key
+	pub key: String,
+	pub comment: String,
+}
+
+fn is_key_type(s: &str) -> bool {
+	KEY_TYPE_PREFIXES.iter().any(|p| s.starts_with(p))
+}
+
+/// Split the options field of an authorized_keys line, which may contain quoted commas and spaces
+///
+/// Returns the options and the rest of the line.
+fn split_options(line: &str) -> Result<(KeyOptions, &str), i32> {
+	let mut options = Vec::new();
+	let mut name = String::new();
+	let mut value: Option<String> = None;
+	let mut in_quotes = false;
+	let mut chars = line.char_indices();
+
+	while let Some((idx, c)) = chars.next() {
+		match c {
+			'\\' if in_quotes => {
+				if let Some((_, n)) = chars.next() {
+					if n != '"' {
+						value.get_or_insert_with(String::new).push('\\');
+					}
+					value.get_or_insert_with(String::new).push(n);
+				}
+			},
+			'"' => in_quotes = !in_quotes,
+			'=' if !in_quotes && value.is_none() => value = Some(String::new()),
+			',' if !in_quotes => {
+				options.push((std::mem::take(&mut name), value.take()));
+			},
+			c if c.is_whitespace() && !in_quotes => {
+				options.push((name, value));
+				return Ok((options, line[idx..].trim_start()));
+			},
+			c => match value.as_mut() {
+				Some(v) => v.push(c),
+				None => name.push(c),
+			},
+		}
+	}
+
+	Err(-1)
+}
+
+impl AuthorizedKey {
+	/// Parse a key from a line of an authorized_keys file
+	///
+	/// Returns `Err(-1)` if the line doesn't have a key type and key.
+	pub fn parse_entry<T: ToString>(entry: &T) -> Result<AuthorizedKey, i32> {
+		let entry = entry.to_string();
+		let entry = entry.trim();
+
+		let (options, rest) = match entry.split_whitespace().next() {
+			Some(first) if is_key_type(first) => (Vec::new(), entry),
+			Some(_) => split_options(entry)?,
+			None => return Err(-1),
+		};
+
+		let mut tokens = rest.splitn(3, char::is_whitespace);
+		let key_type = tokens.next().filter(|t| is_key_type(t)).ok_or(-1)?;
+		let key = tokens.next().filter(|t| !t.is_empty()).ok_or(-1)?;
+
+		Ok(AuthorizedKey {
+			file: PathBuf::new(),
+			line: 0,
+			options,
+			key_type: key_type.to_string(),
+			key: key.to_string(),
+			comment: tokens.next().unwrap_or("").trim().to_string(),
+		})
+	}
+
+	/// Parse every key in an authorized_keys file
+	///
+	/// Blank lines and `#` comments are skipped, and like sshd, so are lines that aren't valid keys.
+	pub fn parse_file<T: ToString>(path: &T) -> Result<Vec<AuthorizedKey>, i32> {
+		let path = PathBuf::from(path.to_string());
+		let contents = std::fs::read_to_string(&path).map_err(io_errno)?;
+
+		Ok(contents.lines().enumerate()
+			.filter(|(_, l)| !l.trim().is_empty() && !l.trim_start().starts_with('#'))
+			.filter_map(|(i, l)| AuthorizedKey::parse_entry(&l).ok().map(|mut k| {
+				k.file = path.clone();
+				k.line = i + 1;
+				k
+			}))
+			.collect())
+	}
+
+	/// Get the value of an option, like `from` or `command`
+	pub fn option<T: ToString>(&self, name: &T) -> Option<String> {
+		let name = name.to_string();
+		self.options.iter()
+			.find(|(n, _)| n.eq_ignore_ascii_case(&name))
+			.and_then(|(_, v)| v.clone())
+	}
+
+	/// Check if a flag option, like `no-pty` or `restrict`, is present
+	pub fn has_option<T: ToString>(&self, name: &T) -> bool {
+		let name = name.to_string();
+		self.options.iter().any(|(n, _)| n.eq_ignore_ascii_case(&name))
+	}
+
+	/// Get the command forced by the `command=` option
+	pub fn forced_command(&self) -> Option<String> {
+		self.option(&"command")
+	}
+
+	/// Get the host patterns the key is restricted to by the `from=` option
+	pub fn from_hosts(&self) -> Option<String> {
+		self.option(&"from")
+	}
+
+	/// Get the SHA256 fingerprint of the key, in the same `SHA256:...` form `ssh-keygen -l` prints
+	///
+	/// Returns `Err(-1)` if the key isn't valid base64.
+	pub fn fingerprint(&self) -> Result<String, i32> {
+		let blob = STANDARD.decode(&self.key).map_err(|_| -1)?;
+		Ok(format!("SHA256:{}", STANDARD_NO_PAD.encode(Sha256::digest(blob))))
+	}
+
+	/// Check if the key is in `allowed`, which may hold fingerprints or base64 keys
+	pub fn is_allowed(&self, allowed: &[String]) -> bool {
+		let fingerprint = self.fingerprint().ok();
+		allowed.iter().any(|a| *a == self.key || Some(a) == fingerprint.as_ref())
+	}
+}
+
+/// One problem with a user's SSH keys or the permissions protecting them
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SshIssue {
+	/// A key that isn't on the allow-list
+	UnexpectedKey { name: String, fingerprint: String, comment: String },
+	/// The home directory, ~/.ssh or a keys file can be written by other users
+	UnsafeMode { name: String, path: PathBuf, mode: u32 },
+	/// The home directory, ~/.ssh or a keys file is owned by someone other than the user or root
+	UnsafeOwner { name: String, path: PathBuf, uid: uid_t },
+}
+
+impl SshIssue {
+	/// The name of the user the issue is about
+	pub fn name(&self) -> &str {
+		match self {
+			SshIssue::UnexpectedKey { name, .. }
+			| SshIssue::UnsafeMode { name, .. }
+			| SshIssue::UnsafeOwner { name, .. } => name.as_str(),
+		}
+	}
+
+	/// A short explanation, suitable for a score report entry
+	pub fn reason(&self) -> String {
+		match self {
+			SshIssue::UnexpectedKey { name, fingerprint, comment } => format!("User {} has unexpected SSH key {} {}", name, fingerprint, comment).trim_end().to_string(),
+			SshIssue::UnsafeMode { name, path, mode } => format!("{} of user {} has unsafe mode {:o}", path.display(), name, mode),
+			SshIssue::UnsafeOwner { name, path, uid } => format!("{} of user {} is owned by UID {}", path.display(), name, uid),
+		}
+	}
+}
+
+/// List the keys in a user's ~/.ssh/authorized_keys and authorized_keys2
+pub fn authorized_keys(user: &PasswdEntry) -> Result<Vec<AuthorizedKey>, i32> {
+	authorized_keys_in(&"/", user)
+}
+
+/// List the keys in a user's authorized keys files under the root directory `root`
+///
+/// Missing files are skipped, so a user without any gets an empty list.
+pub fn authorized_keys_in<T: ToString>(root: &T, user: &PasswdEntry) -> Result<Vec<AuthorizedKey>, i32> {
+	let ssh_dir = rooted_path(root, &user.home_dir).join(".ssh");
+	let mut keys = Vec::new();
+
+	for file in AUTHORIZED_KEYS_FILES {
+		match AuthorizedKey::parse_file(&ssh_dir.join(file).display()) {
+			Ok(k) => keys.extend(k),
+			Err(libc::ENOENT) | Err(libc::ENOTDIR) => (),
+			Err(e) => return Err(e),
+		}
+	}
+
+	Ok(keys)
+}
+
+/// Check one path for modes and owners sshd's StrictModes would refuse
+fn check_path(user: &PasswdEntry, path: &Path, issues: &mut Vec<SshIssue>) -> Result<(), i32> {
+	let display = path.display();
+
+	let mode = get_file_mode(&display)?;
+	if mode & 0o022 != 0 {
+		issues.push(SshIssue::UnsafeMode { name: user.username.clone(), path: path.to_path_buf(), mode });
+	}
+
+	let uid = get_file_owner_uid(&display)?;
+	if uid != user.uid && uid != 0 {
+		issues.push(SshIssue::UnsafeOwner { name: user.username.clone(), path: path.to_path_buf(), uid });
+	}
+
+	Ok(())
+}
+
+/// Find unsafe modes and owners on a user's home directory, ~/.ssh and authorized keys files
+pub fn ssh_permission_issues(user: &PasswdEntry) -> Result<Vec<SshIssue>, i32> {
+	ssh_permission_issues_in(&"/", user)
+}
+
+/// Find unsafe modes and owners for a user under the root directory `root`
+///
+/// Anything missing is skipped, and anything group or world writable is reported.
+pub fn ssh_permission_issues_in<T: ToString>(root: &T, user: &PasswdEntry) -> Result<Vec<SshIssue>, i32> {
+	let home = rooted_path(root, &user.home_dir);
+	let ssh_dir = home.join(".ssh");
+	let mut issues = Vec::new();
+
+	let mut paths = vec![home, ssh_dir.clone()];
+	paths.extend(AUTHORIZED_KEYS_FILES.iter().map(|f| ssh_dir.join(f)));
+
+	for path in paths.iter().filter(|p| p.exists()) {
+		check_path(user, path, &mut issues)?;
+	}
+
+	Ok(issues)
+}
+
+/// Collect every SSH key and permission problem on the system
+///
+/// `allowed` holds the fingerprints or base64 keys that are expected to be present.
+pub fn audit_ssh(allowed: &[String]) -> Result<Vec<SshIssue>, i32> {
+	audit_ssh_in(&"/", allowed)
+}
+
+/// Collect every SSH key and permission problem under the root directory `root`
+///
+/// Every user's keys are checked. Permissions are checked for human users and anyone with a ~/.ssh directory.
+pub fn audit_ssh_in<T: ToString>(root: &T, allowed: &[String]) -> Result<Vec<SshIssue>, i32> {
+	let defs = LoginDefs::load_in(root).unwrap_or_default();
+	let mut issues = Vec::new();
+
+	for user in list_users_in(root)? {
+		for key in authorized_keys_in(root, &user)? {
+			if !key.is_allowed(allowed) {
+				issues.push(SshIssue::UnexpectedKey {
+					name: user.username.clone(),
+					fingerprint: key.fingerprint().unwrap_or(key.key),
+					comment: key.comment,
+				});
+			}
+		}
+
+		let has_ssh_dir = rooted_path(root, &user.home_dir).join(".ssh").is_dir();
+		if defs.is_human_uid(user.uid) || has_ssh_dir {
+			issues.extend(ssh_permission_issues_in(root, &user)?);
+		}
+	}
+
+	Ok(issues)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+	use std::os::unix::fs::PermissionsExt;
+
+	const PERRY_KEY: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIOrCTQZIWThkrFwIfEBWreVmQbQ46CqaiuUgbJ2w3y+q perry@agency";
+	/// From `ssh-keygen -lf` on the key above
+	const PERRY_FINGERPRINT: &str = "SHA256:BeVPfkffiBJeZj1Fn+FP874MzuVnbzVrlsj7ZT6FRqw";
+	const DOOF_KEY: &str = "ecdsa-sha2-nistp256 AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBKMkR+7fXo+mFJuvjTQwP+4sTdjmMr44TSEXJy2eXhd7uz17k/LCPSsBcahuf9H1CGY6rRWGHt7PjXQSCcmBOiI= doof@evil";
+	const DOOF_FINGERPRINT: &str = "SHA256:sltuJGDuGwIfc0fFxsA0X87FPskyJmwNDONQi3dU6jI";
+
+	fn set_mode(path: &Path, mode: u32) {
+		std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap();
+	}
+
+	#[test]
+	fn options_and_keys() {
+		let (options, rest) = split_options(r#"command="echo \"hi, there\" \d",no-pty,from="10.0.0.0/8,!10.0.0.1" ssh-ed25519 AAAA c"#).unwrap();
+		assert_eq!(options, vec![
+			("command".to_string(), Some(r#"echo "hi, there" \d"#.to_string())),
+			("no-pty".to_string(), None),
+			("from".to_string(), Some("10.0.0.0/8,!10.0.0.1".to_string())),
+		]);
+		assert_eq!(rest, "ssh-ed25519 AAAA c");
+		assert_eq!(split_options(r#"command="never closed ssh-ed25519 AAAA"#), Err(-1));
+
+		let key = AuthorizedKey::parse_entry(&format!("restrict,command=\"/usr/bin/backup --daily\" {}", PERRY_KEY)).unwrap();
+		assert!(key.has_option(&"RESTRICT"));
+		assert_eq!(key.forced_command().as_deref(), Some("/usr/bin/backup --daily"));
+		assert_eq!(key.from_hosts(), None);
+		assert_eq!((key.key_type.as_str(), key.comment.as_str()), ("ssh-ed25519", "perry@agency"));
+		assert_eq!(key.fingerprint().as_deref(), Ok(PERRY_FINGERPRINT));
+
+		let key = AuthorizedKey::parse_entry(&DOOF_KEY).unwrap();
+		assert!(key.options.is_empty());
+		assert_eq!(key.fingerprint().as_deref(), Ok(DOOF_FINGERPRINT));
+		assert!(key.is_allowed(&[DOOF_FINGERPRINT.to_string()]));
+		assert!(!key.is_allowed(&[PERRY_FINGERPRINT.to_string()]));
+
+		assert!(AuthorizedKey::parse_entry(&"no-pty").is_err());
+		assert!(AuthorizedKey::parse_entry(&"ssh-rsa").is_err());
+		assert_eq!(AuthorizedKey::parse_entry(&"ssh-rsa !!!").unwrap().fingerprint(), Err(-1));
+	}
+
+	#[test]
+	fn audit_fixture_keys_and_modes() {
+		let root = test_root("ssh-audit");
+		write_rooted(&root, "/etc/passwd", "\
+root:x:0:0:root:/root:/bin/bash
+perry:x:1000:1000::/home/perry:/bin/bash
+doof:x:1001:1001::/home/doof:/bin/bash
+");
+		write_rooted(&root, "/home/perry/.ssh/authorized_keys", format!("# work laptop\n\n{}\nnot a key\n{}\n", PERRY_KEY, DOOF_KEY));
+		write_rooted(&root, "/home/doof/.ssh/authorized_keys2", format!("from=\"*.evil.com\" {}\n", DOOF_KEY));
+		std::fs::create_dir_all(root.join("root")).unwrap();
+
+		let perry_ssh = root.join("home/perry/.ssh");
+		set_mode(&root.join("home/perry"), 0o755);
+		set_mode(&perry_ssh, 0o777);
+		set_mode(&perry_ssh.join("authorized_keys"), 0o620);
+		set_mode(&root.join("home/doof"), 0o750);
+		set_mode(&root.join("home/doof/.ssh"), 0o700);
+		set_mode(&root.join("home/doof/.ssh/authorized_keys2"), 0o600);
+		std::os::unix::fs::chown(root.join("home/doof/.ssh/authorized_keys2"), Some(4242), None).unwrap();
+
+		assert_eq!(get_file_mode(&perry_ssh.display()), Ok(0o777));
+		assert_eq!(get_file_mode(&perry_ssh.join("authorized_keys").display()), Ok(0o620));
+		assert_eq!(get_file_mode(&perry_ssh.join("authorized_keys2").display()), Err(libc::ENOENT));
+
+		let users = list_users_in(&root.display()).unwrap();
+		let keys = authorized_keys_in(&root.display(), &users[1]).unwrap();
+		assert_eq!(keys.iter().map(|k| k.line).collect::<Vec<_>>(), [3, 5]);
+		assert!(authorized_keys_in(&root.display(), &users[0]).unwrap().is_empty());
+
+		let issues = audit_ssh_in(&root.display(), &[PERRY_FINGERPRINT.to_string()]).unwrap();
+		assert_eq!(issues, [
+			SshIssue::UnexpectedKey { name: "perry".to_string(), fingerprint: DOOF_FINGERPRINT.to_string(), comment: "doof@evil".to_string() },
+			SshIssue::UnsafeMode { name: "perry".to_string(), path: perry_ssh.clone(), mode: 0o777 },
+			SshIssue::UnsafeMode { name: "perry".to_string(), path: perry_ssh.join("authorized_keys"), mode: 0o620 },
+			SshIssue::UnexpectedKey { name: "doof".to_string(), fingerprint: DOOF_FINGERPRINT.to_string(), comment: "doof@evil".to_string() },
+			SshIssue::UnsafeOwner { name: "doof".to_string(), path: root.join("home/doof/.ssh/authorized_keys2"), uid: 4242 },
+		]);
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+}
@@ -0,0 +1,635 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+	string::String,
+	vec::Vec,
+};
+
+use libc::{gid_t, uid_t};
+
+use super::{io_errno, list_users_in, rooted_path, user_groups, user_groups_in, PasswdEntry};
+
+/// Tags that can prefix a command in a user specification
+const SUDO_TAGS: [&str; 16] = [
+	"NOPASSWD", "PASSWD", "NOEXEC", "EXEC", "SETENV", "NOSETENV", "LOG_INPUT", "NOLOG_INPUT",
+	"LOG_OUTPUT", "NOLOG_OUTPUT", "MAIL", "NOMAIL", "FOLLOW", "NOFOLLOW", "INTERCEPT", "NOINTERCEPT",
+];
+
+/// Deepest chain of includes followed before giving up, in case of loops
+const MAX_INCLUDE_DEPTH: usize = 128;
+
+/// A single command granted by a user specification in sudoers
+///
+/// A line like `alice ALL=(root) NOPASSWD: /bin/ls, /bin/cat` produces one rule per command.
+#[derive(Clone, Debug)]
+pub struct SudoRule {
+	/// The file the rule was read from
+	pub file: PathBuf,
+	/// The line the rule starts on
+	pub line: usize,
+	/// The full text of the user specification
+	pub text: String,
+	pub users: Vec<String>,
+	pub hosts: Vec<String>,
+	/// Users the command may be run as, [`None`] if no runas list was given, meaning root
+	pub runas_users: Option<Vec<String>>,
+	pub runas_groups: Vec<String>,
+	pub tags: Vec<String>,
+	pub command: String,
+}
+
+impl SudoRule {
+	/// Check if the rule lets the command run without a password
+	pub fn is_nopasswd(&self) -> bool {
+		self.tags.iter().any(|t| t == "NOPASSWD")
+	}
+}
+
+/// A `Defaults` line from sudoers
+#[derive(Clone, Debug)]
+pub struct SudoDefaults {
+	pub file: PathBuf,
+	pub line: usize,
+	/// The scope of the line, such as `:alice` or `@host`, empty for plain `Defaults`
+	pub scope: String,
+	pub settings: Vec<String>,
+}
+
+/// A user as sudoers rules see them, resolved once per query
+struct Identity {
+	name: String,
+	uid: uid_t,
+	/// Every group the user is in, as names and GIDs
+	groups: Vec<(String, gid_t)>,
+}
+
+/// The parsed contents of /etc/sudoers and everything it includes
+#[derive(Clone, Default)]
+pub struct Sudoers {
+	pub rules: Vec<SudoRule>,
+	pub defaults: Vec<SudoDefaults>,
+	pub user_aliases: HashMap<String, Vec<String>>,
+	pub runas_aliases: HashMap<String, Vec<String>>,
+	pub host_aliases: HashMap<String, Vec<String>>,
+	pub cmnd_aliases: HashMap<String, Vec<String>>,
+	root: String,
+	hostname: Option<String>,
+}
+
+/// Split on `sep` outside of parentheses, honoring backslash escapes
+fn split_top_level(s: &str, sep: char) -> Vec<String> {
+	let mut parts = Vec::new();
+	let mut cur = String::new();
+	let mut depth = 0;
+	let mut escaped = false;
+
+	for c in s.chars() {
+		if escaped {
+			cur.push(c);
+			escaped = false;
+			continue;
+		}
+
+		match c {
+			'\\' => { cur.push(c); escaped = true; },
+			'(' => { depth += 1; cur.push(c); },
+			')' => { depth -= 1; cur.push(c); },
+			c if c == sep && depth == 0 => parts.push(std::mem::take(&mut cur)),
+			c => cur.push(c),
+		}
+	}
+	parts.push(cur);
+
+	parts
+}
+
+fn split_list(s: &str) -> Vec<String> {
+	split_top_level(s, ',').into_iter()
+		.map(|i| i.trim().to_string())
+		.filter(|i| !i.is_empty())
+		.collect()
+}
+
+/// Strip a trailing comment, leaving `#uid` style references alone
+fn strip_comment(line: &str) -> &str {
+	let bytes = line.as_bytes();
+	for (i, b) in bytes.iter().enumerate() {
+		if *b == b'#' && (i == 0 || bytes[i - 1].is_ascii_whitespace()) && !bytes.get(i + 1).is_some_and(|n| n.is_ascii_digit()) {
+			return &line[..i];
+		}
+	}
+	line
+}
+
+/// Split the `:` separated host and command groups of a user specification, leaving tag colons alone
+fn split_spec_groups(s: &str) -> Vec<String> {
+	let mut parts = Vec::new();
+	let mut cur = String::new();
+	let mut depth = 0;
+
+	for c in s.chars() {
+		match c {
+			'(' => depth += 1,
+			')' => depth -= 1,
+			':' if depth == 0 => {
+				let word: String = cur.chars().rev().take_while(|c| c.is_ascii_uppercase() || *c == '_').collect::<Vec<_>>().into_iter().rev().collect();
+				if !SUDO_TAGS.contains(&word.as_str()) {
+					parts.push(std::mem::take(&mut cur));
+					continue;
+				}
+			},
+			_ => (),
+		}
+		cur.push(c);
+	}
+	parts.push(cur);
+
+	parts
+}
+
+impl Sudoers {
+	/// Load /etc/sudoers and everything it includes
+	pub fn load() -> Result<Sudoers, i32> {
+		Sudoers::load_in(&"/")
+	}
+
+	/// Load /etc/sudoers and everything it includes under the root directory `root`
+	///
+	/// Absolute include paths are resolved under `root` too.
+	/// Host lists are matched against the running kernel's hostname on the live system, and /etc/hostname under any other root.
+	pub fn load_in<T: ToString>(root: &T) -> Result<Sudoers, i32> {
+		let hostname_file = if Path::new(&root.to_string()) == Path::new("/") {
+			PathBuf::from("/proc/sys/kernel/hostname")
+		} else {
+			rooted_path(root, "/etc/hostname")
+		};
+
+		let mut sudoers = Sudoers {
+			root: root.to_string(),
+			hostname: std::fs::read_to_string(hostname_file).ok()
+				.map(|h| h.trim().to_string())
+				.filter(|h| !h.is_empty()),
+			..Default::default()
+		};
+
+		sudoers.load_file(&rooted_path(root, "/etc/sudoers"), 0)?;
+		Ok(sudoers)
+	}
+
+	fn resolve_include(&self, current: &Path, target: &str) -> PathBuf {
+		let target = target.trim().trim_matches('"');
+		if target.starts_with('/') {
+			rooted_path(&self.root, target)
+		} else {
+			current.parent().unwrap_or(Path::new("/")).join(target)
+		}
+	}
+
+	fn load_file(&mut self, path: &Path, depth: usize) -> Result<(), i32> {
+		if depth > MAX_INCLUDE_DEPTH {
+			return Err(libc::ELOOP);
+		}
+
+		let contents = std::fs::read_to_string(path).map_err(io_errno)?;
+		let mut logical = String::new();
+		let mut start = 0;
+
+		for (idx, raw) in contents.lines().enumerate() {
+			if logical.is_empty() {
+				start = idx + 1;
+			}
+
+			if let Some(stripped) = raw.strip_suffix('\\') {
+				logical.push_str(stripped);
+				logical.push(' ');
+				continue;
+			}
+			logical.push_str(raw);
+
+			let line = std::mem::take(&mut logical);
+			self.parse_line(path, start, line.trim(), depth)?;
+		}
+
+		if !logical.is_empty() {
+			self.parse_line(path, start, logical.trim(), depth)?;
+		}
+
+		Ok(())
+	}
+
+	fn parse_line(&mut self, path: &Path, line_no: usize, line: &str, depth: usize) -> Result<(), i32> {
+		for prefix in ["#includedir", "@includedir"] {
+			if let Some(dir) = line.strip_prefix(prefix) {
+				let dir = self.resolve_include(path, dir);
+				let mut entries: Vec<PathBuf> = match std::fs::read_dir(&dir) {
+					Ok(rd) => rd.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+					Err(_) => return Ok(()),
+				};
+				entries.sort();
+
+				for entry in entries {
+					let name = entry.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+					if name.ends_with('~') || name.contains('.') || !entry.is_file() {
+						continue;
+					}
+					self.load_file(&entry, depth + 1)?;
+				}
+				return Ok(());
+			}
+		}
+
+		for prefix in ["#include", "@include"] {
+			if let Some(file) = line.strip_prefix(prefix) {
+				let file = self.resolve_include(path, file);
+				return self.load_file(&file, depth + 1);
+			}
+		}
+
+		let line = strip_comment(line).trim();
+		if line.is_empty() {
+			return Ok(());
+		}
+
+		let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+
+		if let Some(scope) = keyword.strip_prefix("Defaults") {
+			self.defaults.push(SudoDefaults {
+				file: path.to_path_buf(),
+				line: line_no,
+				scope: scope.to_string(),
+				settings: split_list(rest),
+			});
+			return Ok(());
+		}
+
+		let aliases = match keyword {
+			"User_Alias" => Some(&mut self.user_aliases),
+			"Runas_Alias" => Some(&mut self.runas_aliases),
+			"Host_Alias" => Some(&mut self.host_aliases),
+			"Cmnd_Alias" | "Cmd_Alias" => Some(&mut self.cmnd_aliases),
+			_ => None,
+		};
+
+		if let Some(aliases) = aliases {
+			for def in split_top_level(rest, ':') {
+				if let Some((name, members)) = def.split_once('=') {
+					aliases.insert(name.trim().to_string(), split_list(members));
+				}
+			}
+			return Ok(());
+		}
+
+		self.parse_user_spec(path, line_no, line);
+		Ok(())
+	}
+
+	fn parse_user_spec(&mut self, path: &Path, line_no: usize, line: &str) {
+		let (who, rest) = match line.split_once('=') {
+			Some(s) => s,
+			None => return,
+		};
+
+		let who = who.replace(", ", ",").replace(" ,", ",");
+		let mut tokens = who.split_whitespace();
+		let users = split_list(tokens.next().unwrap_or(""));
+		let first_hosts = tokens.collect::<Vec<_>>().join(",");
+
+		let mut groups = split_spec_groups(rest).into_iter();
+		let mut current = (split_list(&first_hosts), groups.next().unwrap_or_default());
+
+		loop {
+			let (hosts, cmnds) = current;
+			let mut runas_users = None;
+			let mut runas_groups = Vec::new();
+			let mut tags: Vec<String> = Vec::new();
+
+			for spec in split_list(&cmnds) {
+				let mut spec = spec.as_str();
+
+				if let Some(inner) = spec.strip_prefix('(') {
+					let (runas, after) = inner.split_once(')').unwrap_or((inner, ""));
+					let (u, g) = runas.split_once(':').unwrap_or((runas, ""));
+					runas_users = Some(split_list(u));
+					runas_groups = split_list(g);
+					spec = after.trim_start();
+				}
+
+				loop {
+					let (word, after) = spec.split_once(char::is_whitespace).unwrap_or((spec, ""));
+					if let Some(tag) = word.strip_suffix(':').filter(|t| SUDO_TAGS.contains(t)) {
+						tags.retain(|t| !is_opposite_tag(t, tag));
+						tags.push(tag.to_string());
+						spec = after.trim_start();
+					} else if let Some((tag, after_tag)) = spec.split_once(':').filter(|(t, _)| SUDO_TAGS.contains(t)) {
+						tags.retain(|t| !is_opposite_tag(t, tag));
+						tags.push(tag.to_string());
+						spec = after_tag.trim_start();
+					} else if word.split_once('=').is_some_and(|(k, _)| !k.is_empty() && k.chars().all(|c| c.is_ascii_uppercase())) {
+						spec = after.trim_start();
+					} else {
+						break;
+					}
+				}
+
+				if spec.is_empty() {
+					continue;
+				}
+
+				self.rules.push(SudoRule {
+					file: path.to_path_buf(),
+					line: line_no,
+					text: line.to_string(),
+					users: users.clone(),
+					hosts: hosts.clone(),
+					runas_users: runas_users.clone(),
+					runas_groups: runas_groups.clone(),
+					tags: tags.clone(),
+					command: spec.to_string(),
+				});
+			}
+
+			match groups.next() {
+				Some(g) => {
+					let (h, c) = g.split_once('=').unwrap_or(("", &g));
+					current = (split_list(h), c.to_string());
+				},
+				None => break,
+			}
+		}
+	}
+
+	/// Evaluate a sudoers list, where later matches override earlier ones and `!` negates
+	fn list_matches<F>(&self, list: &[String], aliases: &HashMap<String, Vec<String>>, matches: &F, depth: usize) -> bool
+	where
+		F: Fn(&str) -> bool,
+	{
+		let mut result = None;
+
+		for item in list {
+			let (negated, item) = match item.strip_prefix('!') {
+				Some(i) => (true, i.trim()),
+				None => (false, item.as_str()),
+			};
+
+			let hit = if item == "ALL" {
+				true
+			} else if let Some(members) = aliases.get(item).filter(|_| depth < MAX_INCLUDE_DEPTH) {
+				self.list_matches(members, aliases, matches, depth + 1)
+			} else {
+				matches(item)
+			};
+
+			if hit {
+				result = Some(!negated);
+			}
+		}
+
+		result == Some(true)
+	}
+
+	/// Resolve the UID and every group of the user named `name`
+	///
+	/// On the live system this goes through NSS like sudo does, so users from LDAP and other directories are found.
+	/// Under any other root, passwd and group are read from there.
+	fn identity_of(&self, name: &str) -> Result<Identity, i32> {
+		let (uid, groups) = if Path::new(&self.root) == Path::new("/") {
+			let entry = match PasswdEntry::get_entry_from_passwd(&name) {
+				Ok(e) => e,
+				Err(0 | libc::ESRCH | libc::EBADF | libc::EPERM) => return Err(libc::ENOENT),
+				Err(e) => return Err(e),
+			};
+			(entry.uid, user_groups(&name)?)
+		} else {
+			let user = list_users_in(&self.root)?.into_iter().find(|u| u.username == name).ok_or(libc::ENOENT)?;
+			(user.uid, user_groups_in(&self.root, &name)?)
+		};
+
+		Ok(Identity {
+			name: name.to_string(),
+			uid,
+			groups: groups.into_iter().map(|g| (g.groupname, g.gid)).collect(),
+		})
+	}
+
+	fn user_matches(&self, rule_users: &[String], id: &Identity) -> bool {
+		self.list_matches(rule_users, &self.user_aliases, &|item: &str| {
+			if let Some(gid) = item.strip_prefix("%#") {
+				id.groups.iter().any(|(_, g)| gid.parse::<gid_t>() == Ok(*g))
+			} else if let Some(group) = item.strip_prefix('%') {
+				id.groups.iter().any(|(g, _)| g == group)
+			} else if let Some(uid) = item.strip_prefix('#') {
+				uid.parse::<uid_t>() == Ok(id.uid)
+			} else {
+				item == id.name
+			}
+		}, 0)
+	}
+
+	/// Hosts match by `ALL`, a Host_Alias, or the hostname either in full or without its domain
+	///
+	/// If the hostname couldn't be read only `ALL` matches.
+	fn host_matches(&self, hosts: &[String]) -> bool {
+		self.list_matches(hosts, &self.host_aliases, &|item: &str| {
+			match &self.hostname {
+				Some(h) => item == h || h.split('.').next() == Some(item),
+				None => false,
+			}
+		}, 0)
+	}
+
+	fn runs_as_root(&self, rule: &SudoRule) -> bool {
+		match &rule.runas_users {
+			None => true,
+			Some(users) => self.list_matches(users, &self.runas_aliases, &|item: &str| item == "root" || item == "#0", 0),
+		}
+	}
+
+	/// Rules for the user on this host that run as root, including ones that take a command away with `!`
+	fn root_specs_of(&self, id: &Identity) -> Vec<&SudoRule> {
+		self.rules.iter()
+			.filter(|r| self.host_matches(&r.hosts) && self.runs_as_root(r))
+			.filter(|r| self.user_matches(&r.users, id))
+			.collect()
+	}
+
+	fn root_rules_of(&self, id: &Identity) -> Vec<&SudoRule> {
+		self.root_specs_of(id).into_iter()
+			.filter(|r| !r.command.starts_with('!'))
+			.collect()
+	}
+
+	/// List the rules that let the user named `name` run a command as root on this host, in file order
+	///
+	/// Returns `Err(ENOENT)` if there's no such user.
+	pub fn root_rules_for<T: ToString>(&self, name: &T) -> Result<Vec<&SudoRule>, i32> {
+		let id = self.identity_of(&name.to_string())?;
+		Ok(self.root_rules_of(&id))
+	}
+
+	/// Check if the user named `name` can run any command as root
+	pub fn can_run_as_root<T: ToString>(&self, name: &T) -> Result<bool, i32> {
+		Ok(!self.root_rules_for(name)?.is_empty())
+	}
+
+	/// Check if the user named `name` can run every command as root, through a rule for `ALL` or an alias containing it
+	///
+	/// A negated command like `!/bin/su` after the grant limits it, so `ALL, !/bin/su` is not every command.
+	/// Negations before the grant are overridden by it, as sudo uses the last match.
+	pub fn can_run_all_as_root<T: ToString>(&self, name: &T) -> Result<bool, i32> {
+		let id = self.identity_of(&name.to_string())?;
+		let mut all = false;
+
+		for rule in self.root_specs_of(&id) {
+			for (negated, cmnd) in self.expand_commands(&rule.command, false, 0) {
+				if negated {
+					all = false;
+				} else if cmnd == "ALL" {
+					all = true;
+				}
+			}
+		}
+
+		Ok(all)
+	}
+
+	/// Expand Cmnd_Aliases in a command, in order, with whether each command ends up negated
+	fn expand_commands(&self, cmnd: &str, negated: bool, depth: usize) -> Vec<(bool, String)> {
+		let (negated, cmnd) = match cmnd.strip_prefix('!') {
+			Some(c) => (!negated, c.trim()),
+			None => (negated, cmnd),
+		};
+
+		match self.cmnd_aliases.get(cmnd).filter(|_| depth < MAX_INCLUDE_DEPTH) {
+			Some(members) => members.iter().flat_map(|m| self.expand_commands(m, negated, depth + 1)).collect(),
+			None => vec![(negated, cmnd.to_string())],
+		}
+	}
+
+	/// Check if the user named `name` can run anything as root without a password
+	///
+	/// This is true for a `NOPASSWD` rule, or if `!authenticate` is set in a `Defaults` line that applies to the user.
+	pub fn nopasswd_granted<T: ToString>(&self, name: &T) -> Result<bool, i32> {
+		let id = self.identity_of(&name.to_string())?;
+		let rules = self.root_rules_of(&id);
+
+		if rules.is_empty() {
+			return Ok(false);
+		}
+
+		for d in self.defaults.iter().filter(|d| d.settings.iter().any(|s| s == "!authenticate")) {
+			if d.scope.is_empty() {
+				return Ok(true);
+			}
+			if let Some(users) = d.scope.strip_prefix(':') {
+				if self.user_matches(&split_list(users), &id) {
+					return Ok(true);
+				}
+			}
+		}
+
+		Ok(rules.iter().any(|r| r.is_nopasswd()))
+	}
+
+	/// Get the rule that grants the user named `name` root, the last one to match like sudo itself uses
+	pub fn granting_rule<T: ToString>(&self, name: &T) -> Result<Option<SudoRule>, i32> {
+		Ok(self.root_rules_for(name)?.last().map(|r| (*r).clone()))
+	}
+}
+
+fn is_opposite_tag(existing: &str, new: &str) -> bool {
+	existing == new
+		|| existing.strip_prefix("NO") == Some(new)
+		|| new.strip_prefix("NO") == Some(existing)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+
+	#[test]
+	fn sudoers_fixture() {
+		let root = test_root("sudoers-fixture");
+		let mut passwd = String::from("root:x:0:0:root:/root:/bin/bash\n");
+		for (i, name) in ["perry", "isabella", "candace", "doof", "linda", "carl", "ferb", "stacy", "baljeet"].iter().enumerate() {
+			passwd.push_str(&format!("{0}:x:{1}:{1}::/home/{0}:/bin/bash\n", name, 1000 + i));
+		}
+		write_rooted(&root, "/etc/passwd", passwd);
+		write_rooted(&root, "/etc/group", "root:x:0:\nsudo:x:27:baljeet\n");
+		write_rooted(&root, "/etc/hostname", "lab1.example.com\n");
+		write_rooted(&root, "/etc/sudoers", "\
+Defaults env_reset
+Defaults:carl !authenticate
+Host_Alias LAB = lab1, lab2
+User_Alias ADMINS = perry, \\
+	isabella
+Cmnd_Alias SHELLS = /bin/sh, /bin/bash
+Cmnd_Alias EVERYTHING = ALL
+
+root ALL=(ALL:ALL) ALL
+%sudo ALL=(ALL:ALL) ALL # the usual group
+ADMINS LAB=(root) ALL
+candace ALL=(ALL) ALL, !/bin/su, !SHELLS
+doof otherhost=(ALL) NOPASSWD: ALL
+#include /etc/sudoers.local
+#includedir /etc/sudoers.d
+");
+		write_rooted(&root, "/etc/sudoers.local", "linda localhost=(ALL) ALL\ncarl ALL=/usr/bin/apt\n");
+		write_rooted(&root, "/etc/sudoers.d/10-ferb", "ferb ALL=(root) NOPASSWD: /usr/bin/systemctl\nferb ALL=(ALL) !/bin/su, EVERYTHING\n");
+		write_rooted(&root, "/etc/sudoers.d/README.txt", "stacy ALL=(ALL) ALL\n");
+		write_rooted(&root, "/etc/sudoers.d/stacy~", "stacy ALL=(ALL) ALL\n");
+
+		let sudoers = Sudoers::load_in(&root.display()).unwrap();
+		assert_eq!(sudoers.defaults.len(), 2);
+		assert_eq!(sudoers.defaults[1].scope, ":carl");
+		assert_eq!(sudoers.user_aliases["ADMINS"], vec!["perry", "isabella"]);
+
+		for (name, root_any, root_all, nopasswd) in [
+			("root", true, true, false),
+			("baljeet", true, true, false),
+			("perry", true, true, false),
+			("isabella", true, true, false),
+			("candace", true, false, false),
+			("doof", false, false, false),
+			("linda", false, false, false),
+			("carl", true, false, true),
+			("ferb", true, true, true),
+			("stacy", false, false, false),
+		] {
+			assert_eq!(sudoers.can_run_as_root(&name), Ok(root_any), "{}", name);
+			assert_eq!(sudoers.can_run_all_as_root(&name), Ok(root_all), "{}", name);
+			assert_eq!(sudoers.nopasswd_granted(&name), Ok(nopasswd), "{}", name);
+		}
+
+		let rule = sudoers.granting_rule(&"ferb").unwrap().unwrap();
+		assert!(rule.file.ends_with("etc/sudoers.d/10-ferb"));
+		assert_eq!((rule.line, rule.command.as_str()), (2, "EVERYTHING"));
+
+		let rule = sudoers.granting_rule(&"isabella").unwrap().unwrap();
+		assert_eq!((rule.line, rule.hosts.clone(), rule.runas_users.clone()), (11, vec!["LAB".to_string()], Some(vec!["root".to_string()])));
+
+		assert_eq!(sudoers.can_run_as_root(&"ghost"), Err(libc::ENOENT));
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn unknown_hostname() {
+		let root = test_root("sudoers-hostname");
+		write_rooted(&root, "/etc/passwd", "alice:x:1000:1000::/home/alice:/bin/bash\nbob:x:1001:1001::/home/bob:/bin/bash\n");
+		write_rooted(&root, "/etc/group", "");
+		write_rooted(&root, "/etc/sudoers", "alice ALL=(ALL) ALL\nbob somehost=(ALL) ALL\n");
+
+		let sudoers = Sudoers::load_in(&root.display()).unwrap();
+		assert_eq!(sudoers.can_run_as_root(&"alice"), Ok(true));
+		assert_eq!(sudoers.can_run_as_root(&"bob"), Ok(false));
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+}
@@ -0,0 +1,305 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+use std::{
+	collections::BTreeMap,
+	path::{Component, Path, PathBuf},
+	string::String,
+	vec::Vec,
+};
+
+use super::{io_errno, rooted_path};
+
+/// Directories of sysctl.d drop-ins, highest priority first, as systemd-sysctl reads them
+const SYSCTL_DIRS: [&str; 5] = ["/etc/sysctl.d", "/run/sysctl.d", "/usr/local/lib/sysctl.d", "/usr/lib/sysctl.d", "/lib/sysctl.d"];
+
+/// Turn a key written with dots or slashes into the dotted form, like `net.ipv4.ip_forward`
+///
+/// A key with slashes uses them as separators, so any dots in it, like in a VLAN interface name, are swapped for slashes.
+fn normalize_key(key: &str) -> String {
+	let key = key.trim().trim_start_matches('-');
+	if key.contains('/') {
+		key.trim_start_matches('/').split('/').map(|p| p.replace('.', "/")).collect::<Vec<_>>().join(".")
+	} else {
+		key.to_string()
+	}
+}
+
+/// Check if the dotted `key` matches `pattern`, where `*` in the pattern matches any one component
+fn key_matches(pattern: &str, key: &str) -> bool {
+	let pattern: Vec<&str> = pattern.split('.').collect();
+	let key: Vec<&str> = key.split('.').collect();
+	pattern.len() == key.len() && pattern.iter().zip(key.iter()).all(|(p, k)| *p == "*" || p == k)
+}
+
+/// Collapse the whitespace in a value, since /proc/sys separates multiple values with tabs
+fn normalize_value(value: &str) -> String {
+	value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Read where the symlink at the system path `path` under `root` points, as a system path with any `..` resolved
+fn link_target<T: ToString>(root: &T, path: &Path) -> Option<PathBuf> {
+	let target = std::fs::read_link(rooted_path(root, &path.display().to_string())).ok()?;
+	let mut resolved = PathBuf::from("/");
+
+	for part in path.parent().unwrap_or(Path::new("/")).join(target).components() {
+		match part {
+			Component::ParentDir => { resolved.pop(); },
+			Component::Normal(p) => resolved.push(p),
+			_ => (),
+		}
+	}
+
+	Some(resolved)
+}
+
+/// One `key = value` assignment from a sysctl configuration file
+#[derive(Clone, Debug)]
+pub struct SysctlSetting {
+	/// The key in dotted form, which may have `*` components
+	pub key: String,
+	pub value: String,
+	/// Where the assignment was made, as a path on the system rather than under any root
+	pub file: PathBuf,
+	/// The line number, starting at 1
+	pub line: usize,
+	/// Set if the key started with `-`, which means errors setting it are ignored
+	pub ignore_errors: bool,
+}
+
+/// The persistent kernel parameters, from /etc/sysctl.conf and the sysctl.d directories
+#[derive(Clone, Debug, Default)]
+pub struct SysctlConfig {
+	/// Every assignment in the order they're applied at boot, so later ones win
+	pub settings: Vec<SysctlSetting>,
+}
+
+impl SysctlConfig {
+	/// Parse the contents of a sysctl configuration file, recording `file` as where each setting came from
+	pub fn parse<A: ToString, B: ToString>(text: &A, file: &B) -> Vec<SysctlSetting> {
+		let file = PathBuf::from(file.to_string());
+
+		text.to_string().lines().enumerate()
+			.filter_map(|(i, l)| {
+				let l = l.trim();
+				if l.is_empty() || l.starts_with('#') || l.starts_with(';') {
+					return None;
+				}
+
+				let (key, value) = l.split_once('=')?;
+				Some(SysctlSetting {
+					key: normalize_key(key),
+					value: normalize_value(value),
+					file: file.clone(),
+					line: i + 1,
+					ignore_errors: key.trim().starts_with('-'),
+				})
+			})
+			.collect()
+	}
+
+	/// List the configuration files in the order they're applied, as paths on the system
+	///
+	/// The sysctl.d files are sorted by name, where a file in a higher priority directory replaces
+	/// any file of the same name in a lower one, and one linked to /dev/null disables it.
+	/// systemd-sysctl only reads /etc/sysctl.conf through a link in sysctl.d, usually 99-sysctl.conf,
+	/// so it only comes last, as `sysctl --system` has it, when no such link exists.
+	pub fn files_in<T: ToString>(root: &T) -> Vec<PathBuf> {
+		let mut files: BTreeMap<String, Option<PathBuf>> = BTreeMap::new();
+
+		for dir in SYSCTL_DIRS {
+			for entry in std::fs::read_dir(rooted_path(root, dir)).into_iter().flatten().filter_map(|e| e.ok()) {
+				let name = entry.file_name().to_string_lossy().to_string();
+				if !name.ends_with(".conf") || files.contains_key(&name) {
+					continue;
+				}
+
+				let masked = std::fs::read_link(entry.path()).is_ok_and(|t| t == Path::new("/dev/null"));
+				files.insert(name.clone(), if masked { None } else { Some(Path::new(dir).join(&name)) });
+			}
+		}
+
+		let mut ordered: Vec<PathBuf> = files.into_values().flatten().collect();
+		let linked = ordered.iter().any(|f| link_target(root, f).is_some_and(|t| t == Path::new("/etc/sysctl.conf")));
+		if !linked && rooted_path(root, "/etc/sysctl.conf").is_file() {
+			ordered.push(PathBuf::from("/etc/sysctl.conf"));
+		}
+		ordered
+	}
+
+	/// Read the persistent kernel parameters
+	pub fn load() -> SysctlConfig {
+		SysctlConfig::load_in(&"/")
+	}
+
+	/// Read the persistent kernel parameters under the root directory `root`
+	///
+	/// Files that can't be read are skipped.
+	pub fn load_in<T: ToString>(root: &T) -> SysctlConfig {
+		let root = root.to_string();
+		let mut settings = Vec::new();
+
+		for file in SysctlConfig::files_in(&root) {
+			// An absolute symlink, like 99-sysctl.conf pointing at /etc/sysctl.conf, has to stay under the root
+			let mut path = rooted_path(&root, &file.display().to_string());
+			if let Ok(target) = std::fs::read_link(&path) {
+				if target.is_absolute() {
+					path = rooted_path(&root, &target.display().to_string());
+				}
+			}
+
+			if let Ok(text) = std::fs::read_to_string(&path) {
+				settings.extend(SysctlConfig::parse(&text, &file.display()));
+			}
+		}
+
+		SysctlConfig { settings }
+	}
+
+	/// Get the assignment that wins for `key`, written with dots or slashes, including ones made through `*` patterns
+	pub fn get<T: ToString>(&self, key: &T) -> Option<&SysctlSetting> {
+		let key = normalize_key(&key.to_string());
+		self.settings.iter().rev().find(|s| key_matches(&s.key, &key))
+	}
+}
+
+/// A kernel parameter's value now and at the next boot
+#[derive(Clone, Debug)]
+pub struct SysctlValue {
+	/// The key in dotted form
+	pub key: String,
+	/// The value in /proc/sys, [`None`] if the parameter doesn't exist on the running kernel
+	pub runtime: Option<String>,
+	/// The value the configuration files set, [`None`] if none of them do
+	pub configured: Option<String>,
+	/// The file that set the configured value
+	pub source: Option<PathBuf>,
+}
+
+impl SysctlValue {
+	/// Check if the running kernel has the value `expected`, ignoring differences in whitespace
+	pub fn runtime_is<T: ToString>(&self, expected: &T) -> bool {
+		self.runtime.as_ref().is_some_and(|v| *v == normalize_value(&expected.to_string()))
+	}
+
+	/// Check if the configuration files set the value `expected`, ignoring differences in whitespace
+	pub fn configured_is<T: ToString>(&self, expected: &T) -> bool {
+		self.configured.as_ref().is_some_and(|v| *v == normalize_value(&expected.to_string()))
+	}
+
+	/// Check if the value is `expected` now and will be after a reboot
+	///
+	/// A parameter no configuration file sets keeps its runtime value only until the kernel's default comes back,
+	/// so this needs both.
+	pub fn is<T: ToString>(&self, expected: &T) -> bool {
+		self.runtime_is(expected) && self.configured_is(expected)
+	}
+}
+
+/// Read the runtime value of the kernel parameter `key`, written with dots or slashes
+pub fn sysctl_runtime<T: ToString>(key: &T) -> Result<String, i32> {
+	sysctl_runtime_in(&"/", key)
+}
+
+/// Read the runtime value of the kernel parameter `key` from /proc/sys under the root directory `root`
+///
+/// Returns `Err(ENOENT)` if there's no such parameter.
+pub fn sysctl_runtime_in<A: ToString, B: ToString>(root: &A, key: &B) -> Result<String, i32> {
+	let path = normalize_key(&key.to_string()).split('.').map(|p| p.replace('/', ".")).collect::<Vec<_>>().join("/");
+	let value = std::fs::read_to_string(rooted_path(root, &format!("/proc/sys/{}", path))).map_err(io_errno)?;
+	Ok(normalize_value(&value))
+}
+
+/// Get the runtime and configured values of the kernel parameter `key`
+pub fn sysctl_value<T: ToString>(key: &T) -> SysctlValue {
+	sysctl_value_in(&"/", key)
+}
+
+/// Get the runtime and configured values of the kernel parameter `key` under the root directory `root`
+pub fn sysctl_value_in<A: ToString, B: ToString>(root: &A, key: &B) -> SysctlValue {
+	let config = SysctlConfig::load_in(root);
+	let setting = config.get(key);
+
+	SysctlValue {
+		key: normalize_key(&key.to_string()),
+		runtime: sysctl_runtime_in(root, key).ok(),
+		configured: setting.map(|s| s.value.clone()),
+		source: setting.map(|s| s.file.clone()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+	use std::os::unix::fs::symlink;
+
+	fn fixture(name: &str, link_sysctl_conf: bool) -> PathBuf {
+		let root = test_root(&format!("sysctl-{}", name));
+
+		write_rooted(&root, "/etc/sysctl.conf", "net.ipv4.ip_forward = 1\nkernel.randomize_va_space=1\n");
+		write_rooted(&root, "/etc/sysctl.d/99-zz-hardening.conf", "# Hardening\nnet.ipv4.ip_forward = 0\n-net.ipv6.conf.all.forwarding=0\n");
+		write_rooted(&root, "/usr/lib/sysctl.d/10-default.conf", "kernel.randomize_va_space = 2\n");
+		write_rooted(&root, "/usr/lib/sysctl.d/20-rp-filter.conf", "net/ipv4/conf/*/rp_filter = 2\n");
+		write_rooted(&root, "/usr/lib/sysctl.d/50-pid-max.conf", "kernel.pid_max = 4194304\n");
+		write_rooted(&root, "/etc/sysctl.d/10-default.conf", "kernel.randomize_va_space = 0\n");
+		symlink("/dev/null", root.join("etc/sysctl.d/50-pid-max.conf")).unwrap();
+		if link_sysctl_conf {
+			symlink("../sysctl.conf", root.join("etc/sysctl.d/99-sysctl.conf")).unwrap();
+		}
+		write_rooted(&root, "/proc/sys/net/ipv4/ip_forward", "0\n");
+
+		root
+	}
+
+	#[test]
+	fn sysctl_conf_through_sysctl_d() {
+		let root = fixture("linked", true);
+		let root_s = root.display();
+
+		let files = SysctlConfig::files_in(&root_s);
+		assert_eq!(files, [
+			PathBuf::from("/etc/sysctl.d/10-default.conf"),
+			PathBuf::from("/usr/lib/sysctl.d/20-rp-filter.conf"),
+			PathBuf::from("/etc/sysctl.d/99-sysctl.conf"),
+			PathBuf::from("/etc/sysctl.d/99-zz-hardening.conf"),
+		]);
+
+		let config = SysctlConfig::load_in(&root_s);
+		let forward = config.get(&"net.ipv4.ip_forward").unwrap();
+		assert_eq!(forward.value, "0");
+		assert_eq!(forward.file, Path::new("/etc/sysctl.d/99-zz-hardening.conf"));
+		assert_eq!(forward.line, 2);
+		// sysctl.conf comes after 10-default.conf through the link
+		assert_eq!(config.get(&"kernel.randomize_va_space").unwrap().value, "1");
+		assert!(config.get(&"kernel.pid_max").is_none());
+		assert!(config.get(&"net.ipv6.conf.all.forwarding").unwrap().ignore_errors);
+
+		let value = sysctl_value_in(&root_s, &"net/ipv4/ip_forward");
+		assert!(value.is(&"0"));
+		assert_eq!(value.source.as_deref(), Some(Path::new("/etc/sysctl.d/99-zz-hardening.conf")));
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn sysctl_conf_last_without_link() {
+		let root = fixture("unlinked", false);
+		let root_s = root.display();
+
+		assert_eq!(SysctlConfig::files_in(&root_s).last(), Some(&PathBuf::from("/etc/sysctl.conf")));
+
+		let config = SysctlConfig::load_in(&root_s);
+		assert_eq!(config.get(&"net.ipv4.ip_forward").unwrap().value, "1");
+		assert_eq!(config.get(&"net.ipv4.conf.eth0.rp_filter").unwrap().value, "2");
+
+		let value = sysctl_value_in(&root_s, &"net.ipv4.ip_forward");
+		assert!(value.runtime_is(&"0") && !value.is(&"0"));
+		assert_eq!(sysctl_runtime_in(&root_s, &"kernel.pid_max"), Err(libc::ENOENT));
+
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+}
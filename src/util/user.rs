@@ -14,13 +14,17 @@ use std::{
 	str::FromStr, 
 	string::String, 
 	vec::Vec,
-	ffi::CString,
+	ffi::{CStr, CString},
+	collections::BTreeMap,
 };
 
-use super::errno;
+use super::{errno, io_errno, rooted_path};
 
 #[cfg(target_os = "linux")]
-use libc::{gid_t, uid_t, sysconf, getpwnam_r, getgrnam_r, getpwuid_r, getgrgid_r, getgrouplist, strlen};
+use super::{LoginDefs, Sudoers};
+
+#[cfg(target_os = "linux")]
+use libc::{gid_t, uid_t, c_char, c_int, sysconf, getpwnam_r, getgrnam_r, getpwuid_r, getgrgid_r, getgrouplist, strlen};
 
 #[cfg(target_os = "windows")]
 use winapi::{
@@ -61,9 +65,57 @@ pub struct PasswdEntry {
     pub shell: String,
 }
 
+/// Copy a C string owned by libc into a [`String`], treating null as empty
+#[cfg(target_os = "linux")]
+unsafe fn copy_c_string(ptr: *const c_char) -> String {
+	if ptr.is_null() {
+		String::new()
+	} else {
+		CStr::from_ptr(ptr).to_string_lossy().into_owned()
+	}
+}
+
+/// Run one of the reentrant `get*_r` lookups, growing the buffer until the entry fits.
+///
+/// Everything `convert` needs is copied out before the buffer is dropped, so nothing returned aliases it.
+/// A lookup that succeeds without finding anything returns `ENOENT`.
+#[cfg(target_os = "linux")]
+unsafe fn reentrant_lookup<R, T, F, C>(size_hint: c_int, mut lookup: F, convert: C) -> Result<T, i32>
+where
+	F: FnMut(*mut R, *mut c_char, usize, *mut *mut R) -> c_int,
+	C: FnOnce(&R) -> T,
+{
+	let mut record = MaybeUninit::<R>::zeroed();
+	let mut record_ptr: *mut R = null_mut();
+	let mut buf: Vec<c_char> = vec![0; match sysconf(size_hint) {
+		n if n > 0 => n as usize,
+		_ => 1024,
+	}];
+	let mut res = lookup(record.as_mut_ptr(), buf.as_mut_ptr(), buf.len(), &mut record_ptr);
+
+	while res == libc::ERANGE {
+		let len = buf.len() * 2;
+		buf.resize(len, 0);
+		res = lookup(record.as_mut_ptr(), buf.as_mut_ptr(), buf.len(), &mut record_ptr);
+	}
+
+	if res != 0 {
+		return Err(res);
+	}
+
+	match record_ptr.as_ref() {
+		Some(r) => Ok(convert(r)),
+		None => Err(libc::ENOENT),
+	}
+}
+
 #[cfg(target_os = "linux")]
 impl PasswdEntry {
 	/// Parse a passwd entry from a string
+	///
+	/// # Panics
+	///
+	/// Panics if the entry is malformed, see [`PasswdEntry::try_parse_entry`] for a version that doesn't.
 	pub fn parse_entry<T: ToString>(entry: &T) -> PasswdEntry {
 		let entry_str = entry.to_string();
 		let tokenized_entry: Vec<_> = entry_str.split(':').collect();
@@ -78,7 +130,55 @@ impl PasswdEntry {
 			shell: tokenized_entry[6].to_string(),
 		}
 	}
-	
+
+	/// Parse a passwd entry from a string
+	///
+	/// Returns `Err(-1)` if the line doesn't have exactly seven fields or the UID or GID isn't a number.
+	pub fn try_parse_entry<T: ToString>(entry: &T) -> Result<PasswdEntry, i32> {
+		let entry_str = entry.to_string();
+		let tokenized_entry: Vec<_> = entry_str.split(':').collect();
+
+		if tokenized_entry.len() != 7 {
+			return Err(-1);
+		}
+
+		Ok(PasswdEntry {
+			username: tokenized_entry[0].to_string(),
+			password_in_shadow: tokenized_entry[1] == "x",
+			uid: tokenized_entry[2].parse::<uid_t>().map_err(|_| -1)?,
+			gid: tokenized_entry[3].parse::<gid_t>().map_err(|_| -1)?,
+			gecos: tokenized_entry[4].to_string(),
+			home_dir: tokenized_entry[5].to_string(),
+			shell: tokenized_entry[6].to_string(),
+		})
+	}
+
+	/// Parse every entry in a passwd formatted file, such as /etc/passwd
+	///
+	/// Blank lines and lines starting with `#` are skipped, any other malformed line is an error.
+	pub fn parse_file<T: ToString>(path: &T) -> Result<Vec<PasswdEntry>, i32> {
+		let contents = std::fs::read_to_string(path.to_string()).map_err(io_errno)?;
+
+		contents.lines()
+			.filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+			.map(|l| PasswdEntry::try_parse_entry(&l))
+			.collect()
+	}
+
+	unsafe fn from_raw(raw: &libc::passwd) -> PasswdEntry {
+		let password = copy_c_string(raw.pw_passwd);
+
+		PasswdEntry {
+			username: copy_c_string(raw.pw_name),
+			password_in_shadow: password == "x",
+			uid: raw.pw_uid,
+			gid: raw.pw_gid,
+			gecos: copy_c_string(raw.pw_gecos),
+			home_dir: copy_c_string(raw.pw_dir),
+			shell: copy_c_string(raw.pw_shell),
+		}
+	}
+
 	/// Get the entry from the password database
 	pub fn get_entry_from_passwd<T: ToString>(name: &T) -> Result<PasswdEntry, i32> {
 		let username = match CString::new(name.to_string()) {
@@ -87,193 +187,223 @@ impl PasswdEntry {
 		};
 
 		unsafe {
-			let mut pass = MaybeUninit::zeroed().assume_init();
-        	let mut pass_ptr = MaybeUninit::zeroed().assume_init();
-        	let mut buf = vec![0i8; sysconf(libc::_SC_GETPW_R_SIZE_MAX) as usize];
-        	let mut res ;
-			let tmp;
-        	res = getpwnam_r(username.as_ptr() as *const i8, &mut pass, buf.as_mut_ptr(), buf.len(), &mut pass_ptr);
-
-        	while res != 0 && errno() == libc::ERANGE {
-            	let mut nb = vec![0i8; sysconf(libc::_SC_GETPW_R_SIZE_MAX) as usize];
-            	buf.append(&mut nb);
-            	res = getpwnam_r(username.as_ptr() as *const i8, &mut pass, buf.as_mut_ptr(), buf.len(), &mut pass_ptr);
-        	}
-
-			if res != 0 {
-				return Err(errno());
-			}
-
-			tmp = pass_ptr.as_mut().unwrap();
-
-			Ok(PasswdEntry {
-				username: String::from_raw_parts(tmp.pw_name as *mut u8, libc::strlen(tmp.pw_name), libc::strlen(tmp.pw_name)),
-				uid: tmp.pw_uid,
-				gid: tmp.pw_gid,
-				password_in_shadow: *tmp.pw_passwd == 'x' as i8,
-				gecos: String::from_raw_parts(tmp.pw_gecos as *mut u8, libc::strlen(tmp.pw_gecos), libc::strlen(tmp.pw_gecos)),
-				home_dir: String::from_raw_parts(tmp.pw_dir as *mut u8, libc::strlen(tmp.pw_dir), libc::strlen(tmp.pw_dir)),
-				shell: String::from_raw_parts(tmp.pw_shell as *mut u8, libc::strlen(tmp.pw_shell), libc::strlen(tmp.pw_shell)),
-			}.clone())
+			reentrant_lookup(
+				libc::_SC_GETPW_R_SIZE_MAX,
+				|pass, buf, len, res| getpwnam_r(username.as_ptr(), pass, buf, len, res),
+				|pass| PasswdEntry::from_raw(pass)
+			)
 		}
 	}
 
 	/// Get the entry from the password database by uid
 	pub fn get_entry_from_passwd_by_uid(uid: uid_t) -> Result<PasswdEntry, i32> {
 		unsafe {
-			let mut pass = MaybeUninit::zeroed().assume_init();
-        	let mut pass_ptr = MaybeUninit::zeroed().assume_init();
-        	let mut buf = vec![0i8; sysconf(libc::_SC_GETPW_R_SIZE_MAX) as usize];
-        	let mut res;
-			let tmp;
-        	res = getpwuid_r(uid, &mut pass, buf.as_mut_ptr(), buf.len(), &mut pass_ptr);
-
-        	while res != 0 && errno() == libc::ERANGE {
-            	let mut nb = vec![0i8; sysconf(libc::_SC_GETPW_R_SIZE_MAX) as usize];
-            	buf.append(&mut nb);
-            	res = getpwuid_r(uid, &mut pass, buf.as_mut_ptr(), buf.len(), &mut pass_ptr);
-        	}
-
-			if res != 0 {
-				return Err(errno());
-			}
-
-			tmp = pass_ptr.as_mut().unwrap();
-
-			Ok(PasswdEntry {
-				username: String::from_raw_parts(tmp.pw_name as *mut u8, libc::strlen(tmp.pw_name), libc::strlen(tmp.pw_name)),
-				uid: tmp.pw_uid,
-				gid: tmp.pw_gid,
-				password_in_shadow: *tmp.pw_passwd == 'x' as i8,
-				gecos: String::from_raw_parts(tmp.pw_gecos as *mut u8, libc::strlen(tmp.pw_gecos), libc::strlen(tmp.pw_gecos)),
-				home_dir: String::from_raw_parts(tmp.pw_dir as *mut u8, libc::strlen(tmp.pw_dir), libc::strlen(tmp.pw_dir)),
-				shell: String::from_raw_parts(tmp.pw_shell as *mut u8, libc::strlen(tmp.pw_shell), libc::strlen(tmp.pw_shell)),
-			}.clone())
+			reentrant_lookup(
+				libc::_SC_GETPW_R_SIZE_MAX,
+				|pass, buf, len, res| getpwuid_r(uid, pass, buf, len, res),
+				|pass| PasswdEntry::from_raw(pass)
+			)
 		}
 	}
 }
 
 #[cfg(target_os = "linux")]
 impl GroupEntry {
-	/// Get the entry from the group database
-	pub fn get_entry_from_group<T: ToString>(name: &T) -> Result<GroupEntry, i32> {
-		let groupname = match CString::new(name.to_string()) {
-			Ok(s) => s,
-			_ => return Err(-1),
-		};
+	/// Parse a group entry from a string
+	///
+	/// Returns `Err(-1)` if the line doesn't have exactly four fields or the GID isn't a number.
+	pub fn parse_entry<T: ToString>(entry: &T) -> Result<GroupEntry, i32> {
+		let entry_str = entry.to_string();
+		let tokenized_entry: Vec<_> = entry_str.split(':').collect();
 
-		unsafe {
-			let mut pass = MaybeUninit::zeroed().assume_init();
-        	let mut pass_ptr = MaybeUninit::zeroed().assume_init();
-        	let mut buf = vec![0i8; sysconf(libc::_SC_GETPW_R_SIZE_MAX) as usize];
-        	let mut res ;
-			let mut ret;
-			let tmp;
-        	res = getgrnam_r(groupname.as_ptr() as *const i8, &mut pass, buf.as_mut_ptr(), buf.len(), &mut pass_ptr);
-
-        	while res != 0 && errno() == libc::ERANGE {
-            	let mut nb = vec![0i8; sysconf(libc::_SC_GETPW_R_SIZE_MAX) as usize];
-            	buf.append(&mut nb);
-            	res = getgrnam_r(groupname.as_ptr() as *const i8, &mut pass, buf.as_mut_ptr(), buf.len(), &mut pass_ptr);
-        	}
-
-			if res != 0 {
-				return Err(errno());
-			}
+		if tokenized_entry.len() != 4 {
+			return Err(-1);
+		}
+
+		Ok(GroupEntry {
+			groupname: tokenized_entry[0].to_string(),
+			gid: tokenized_entry[2].parse::<gid_t>().map_err(|_| -1)?,
+			list: tokenized_entry[3].split(',')
+				.filter(|m| !m.is_empty())
+				.map(|m| m.to_string())
+				.collect(),
+		})
+	}
 
-			tmp = pass_ptr.as_mut().unwrap();
+	/// Parse every entry in a group formatted file, such as /etc/group
+	///
+	/// Blank lines and lines starting with `#` are skipped, any other malformed line is an error.
+	pub fn parse_file<T: ToString>(path: &T) -> Result<Vec<GroupEntry>, i32> {
+		let contents = std::fs::read_to_string(path.to_string()).map_err(io_errno)?;
 
-			ret = GroupEntry {
-				groupname: String::from_raw_parts(tmp.gr_name as *mut u8, libc::strlen(tmp.gr_name), libc::strlen(tmp.gr_name)),
-				gid: tmp.gr_gid,
-				list: Vec::new(),
-			};
+		contents.lines()
+			.filter(|l| !l.trim().is_empty() && !l.starts_with('#'))
+			.map(|l| GroupEntry::parse_entry(&l))
+			.collect()
+	}
+
+	unsafe fn from_raw(raw: &libc::group) -> GroupEntry {
+		let mut list = Vec::new();
 
+		if !raw.gr_mem.is_null() {
 			let mut i = 0;
-			while tmp.gr_mem.offset(i).read() != null_mut() {
-				let tmp_tmp = tmp.gr_mem.offset(i).read() as *mut i8;
-				ret.list.push(String::from_raw_parts(tmp_tmp as *mut u8, libc::strlen(tmp_tmp), libc::strlen(tmp_tmp)));
+			while !(*raw.gr_mem.offset(i)).is_null() {
+				list.push(copy_c_string(*raw.gr_mem.offset(i)));
 				i += 1;
 			}
+		}
+
+		GroupEntry {
+			groupname: copy_c_string(raw.gr_name),
+			gid: raw.gr_gid,
+			list,
+		}
+	}
 
-			Ok(ret.clone())
+	/// Get the entry from the group database
+	pub fn get_entry_from_group<T: ToString>(name: &T) -> Result<GroupEntry, i32> {
+		let groupname = match CString::new(name.to_string()) {
+			Ok(s) => s,
+			_ => return Err(-1),
+		};
+
+		unsafe {
+			reentrant_lookup(
+				libc::_SC_GETGR_R_SIZE_MAX,
+				|grp, buf, len, res| getgrnam_r(groupname.as_ptr(), grp, buf, len, res),
+				|grp| GroupEntry::from_raw(grp)
+			)
 		}
 	}
 
 	/// Get the entry from the group database by GID
 	pub fn get_entry_by_gid(gid: gid_t) -> Result<GroupEntry, i32> {
 		unsafe {
-			let mut pass = MaybeUninit::zeroed().assume_init();
-        	let mut pass_ptr = MaybeUninit::zeroed().assume_init();
-        	let mut buf = vec![0i8; sysconf(libc::_SC_GETPW_R_SIZE_MAX) as usize];
-        	let mut res ;
-			let mut ret;
-			let tmp;
-        	res = getgrgid_r(gid, &mut pass, buf.as_mut_ptr(), buf.len(), &mut pass_ptr);
-
-        	while res != 0 && errno() == libc::ERANGE {
-            	let mut nb = vec![0i8; sysconf(libc::_SC_GETPW_R_SIZE_MAX) as usize];
-            	buf.append(&mut nb);
-            	res = getgrgid_r(gid, &mut pass, buf.as_mut_ptr(), buf.len(), &mut pass_ptr);
-        	}
-
-			if res != 0 {
-				return Err(errno());
-			}
+			reentrant_lookup(
+				libc::_SC_GETGR_R_SIZE_MAX,
+				|grp, buf, len, res| getgrgid_r(gid, grp, buf, len, res),
+				|grp| GroupEntry::from_raw(grp)
+			)
+		}
+	}
+}
 
-			tmp = pass_ptr.as_mut().unwrap();
+/// List every user in /etc/passwd
+#[cfg(target_os = "linux")]
+pub fn list_users() -> Result<Vec<PasswdEntry>, i32> {
+	list_users_in(&"/")
+}
 
-			ret = GroupEntry {
-				groupname: String::from_raw_parts(tmp.gr_name as *mut u8, libc::strlen(tmp.gr_name), libc::strlen(tmp.gr_name)),
-				gid: tmp.gr_gid,
-				list: Vec::new(),
-			};
+/// List every user in /etc/passwd under the root directory `root`
+#[cfg(target_os = "linux")]
+pub fn list_users_in<T: ToString>(root: &T) -> Result<Vec<PasswdEntry>, i32> {
+	PasswdEntry::parse_file(&rooted_path(root, "/etc/passwd").display())
+}
 
-			let mut i = 0;
-			while tmp.gr_mem.offset(i).read() != null_mut() {
-				let tmp_tmp = tmp.gr_mem.offset(i).read() as *mut i8;
-				ret.list.push(String::from_raw_parts(tmp_tmp as *mut u8, libc::strlen(tmp_tmp), libc::strlen(tmp_tmp)));
-				i += 1;
-			}
+/// List every group in /etc/group
+#[cfg(target_os = "linux")]
+pub fn list_groups() -> Result<Vec<GroupEntry>, i32> {
+	list_groups_in(&"/")
+}
 
-			Ok(ret.clone())
-		}
+/// List every group in /etc/group under the root directory `root`
+#[cfg(target_os = "linux")]
+pub fn list_groups_in<T: ToString>(root: &T) -> Result<Vec<GroupEntry>, i32> {
+	GroupEntry::parse_file(&rooted_path(root, "/etc/group").display())
+}
+
+/// List the human users, those with a UID between UID_MIN and UID_MAX in /etc/login.defs
+#[cfg(target_os = "linux")]
+pub fn list_human_users() -> Result<Vec<PasswdEntry>, i32> {
+	list_human_users_in(&"/")
+}
+
+/// List the human users under the root directory `root`
+#[cfg(target_os = "linux")]
+pub fn list_human_users_in<T: ToString>(root: &T) -> Result<Vec<PasswdEntry>, i32> {
+	let defs = LoginDefs::load_in(root).unwrap_or_default();
+	Ok(list_users_in(root)?.into_iter().filter(|u| defs.is_human_uid(u.uid)).collect())
+}
+
+/// List the system accounts, every user outside of the human UID range, including root
+#[cfg(target_os = "linux")]
+pub fn list_system_users() -> Result<Vec<PasswdEntry>, i32> {
+	list_system_users_in(&"/")
+}
+
+/// List the system accounts under the root directory `root`
+#[cfg(target_os = "linux")]
+pub fn list_system_users_in<T: ToString>(root: &T) -> Result<Vec<PasswdEntry>, i32> {
+	let defs = LoginDefs::load_in(root).unwrap_or_default();
+	Ok(list_users_in(root)?.into_iter().filter(|u| !defs.is_human_uid(u.uid)).collect())
+}
+
+/// List every user with UID 0, which should normally only be root
+#[cfg(target_os = "linux")]
+pub fn list_root_users() -> Result<Vec<PasswdEntry>, i32> {
+	list_root_users_in(&"/")
+}
+
+/// List every user with UID 0 under the root directory `root`
+#[cfg(target_os = "linux")]
+pub fn list_root_users_in<T: ToString>(root: &T) -> Result<Vec<PasswdEntry>, i32> {
+	Ok(list_users_in(root)?.into_iter().filter(|u| u.uid == 0).collect())
+}
+
+/// Find UIDs shared by more than one user
+///
+/// Each UID is returned alongside every user that has it, in order of UID.
+/// A hidden second root account shows up here as UID 0.
+#[cfg(target_os = "linux")]
+pub fn duplicate_uids() -> Result<Vec<(uid_t, Vec<PasswdEntry>)>, i32> {
+	duplicate_uids_in(&"/")
+}
+
+/// Find UIDs shared by more than one user under the root directory `root`
+#[cfg(target_os = "linux")]
+pub fn duplicate_uids_in<T: ToString>(root: &T) -> Result<Vec<(uid_t, Vec<PasswdEntry>)>, i32> {
+	let mut by_uid: BTreeMap<uid_t, Vec<PasswdEntry>> = BTreeMap::new();
+
+	for user in list_users_in(root)? {
+		by_uid.entry(user.uid).or_default().push(user);
 	}
+
+	Ok(by_uid.into_iter().filter(|(_, users)| users.len() > 1).collect())
 }
 
-/// Checks if a user with username `name` exists on the system
-pub fn user_exists<T: ToString>(n: &T) -> Result<bool, i32> {
-	let name = n.to_string();
-	#[cfg(target_os = "linux")]
-	unsafe {
-		let username = match CString::new(name) {
-			Ok(s) => s,
-			_ => return Err(-1),
-		};
+/// Find GIDs shared by more than one group
+///
+/// Each GID is returned alongside every group that has it, in order of GID.
+#[cfg(target_os = "linux")]
+pub fn duplicate_gids() -> Result<Vec<(gid_t, Vec<GroupEntry>)>, i32> {
+	duplicate_gids_in(&"/")
+}
 
-		let mut pass = MaybeUninit::zeroed().assume_init();
-        let mut pass_ptr = MaybeUninit::zeroed().assume_init();
-    	let mut buf = vec![0i8; sysconf(libc::_SC_GETPW_R_SIZE_MAX) as usize];
-    	let mut res ;
-    	res = getpwnam_r(username.as_ptr() as *const i8, &mut pass, buf.as_mut_ptr(), buf.len(), &mut pass_ptr);
+/// Find GIDs shared by more than one group under the root directory `root`
+#[cfg(target_os = "linux")]
+pub fn duplicate_gids_in<T: ToString>(root: &T) -> Result<Vec<(gid_t, Vec<GroupEntry>)>, i32> {
+	let mut by_gid: BTreeMap<gid_t, Vec<GroupEntry>> = BTreeMap::new();
 
-        while res != 0 && errno() == libc::ERANGE {
-        	let mut nb = vec![0i8; sysconf(libc::_SC_GETPW_R_SIZE_MAX) as usize];
-            buf.append(&mut nb);
-        	res = getpwnam_r(username.as_ptr() as *const i8, &mut pass, buf.as_mut_ptr(), buf.len(), &mut pass_ptr);
-    	}
+	for group in list_groups_in(root)? {
+		by_gid.entry(group.gid).or_default().push(group);
+	}
 
-		if res == 0 {
-			Ok(true)
-		} else {
-			match errno() {
-				0 | libc::ENOENT | libc::ESRCH | libc::EBADF | libc::EPERM => Ok(false),
-				_ => Err(errno()),
-			}
+	Ok(by_gid.into_iter().filter(|(_, groups)| groups.len() > 1).collect())
+}
+
+/// Checks if a user with username `name` exists on the system
+pub fn user_exists<T: ToString>(n: &T) -> Result<bool, i32> {
+	#[cfg(target_os = "linux")]
+	{
+		match PasswdEntry::get_entry_from_passwd(n) {
+			Ok(_) => Ok(true),
+			Err(0 | libc::ENOENT | libc::ESRCH | libc::EBADF | libc::EPERM) => Ok(false),
+			Err(e) => Err(e),
 		}
 	}
 	#[cfg(target_os = "windows")]
 	unsafe {
+		let name = n.to_string();
 		let mut user: LPUSER_INFO_0 = null_mut();
 		let uname_utf16 = name.encode_utf16().collect::<Vec<u16>>();
 
@@ -285,39 +415,26 @@ pub fn user_exists<T: ToString>(n: &T) -> Result<bool, i32> {
 	}
 }
 
+/// Checks if a user with username `name` is in /etc/passwd under the root directory `root`
+#[cfg(target_os = "linux")]
+pub fn user_exists_in<A: ToString, B: ToString>(root: &A, name: &B) -> Result<bool, i32> {
+	let name = name.to_string();
+	Ok(list_users_in(root)?.iter().any(|u| u.username == name))
+}
+
 /// Checks if a group named `name` exists on the system
 pub fn group_exists<T: ToString>(n: &T) -> Result<bool, i32> {
-	let name = n.to_string();
 	#[cfg(target_os = "linux")]
-	unsafe {
-		let groupname = match CString::new(name) {
-			Ok(s) => s,
-			_ => return Err(-1),
-		};
-
-		let mut pass = MaybeUninit::zeroed().assume_init();
-        let mut pass_ptr = MaybeUninit::zeroed().assume_init();
-    	let mut buf = vec![0i8; sysconf(libc::_SC_GETPW_R_SIZE_MAX) as usize];
-    	let mut res ;
-    	res = getgrnam_r(groupname.as_ptr() as *const i8, &mut pass, buf.as_mut_ptr(), buf.len(), &mut pass_ptr);
-
-        while res != 0 && errno() == libc::ERANGE {
-        	let mut nb = vec![0i8; sysconf(libc::_SC_GETPW_R_SIZE_MAX) as usize];
-            buf.append(&mut nb);
-        	res = getgrnam_r(groupname.as_ptr() as *const i8, &mut pass, buf.as_mut_ptr(), buf.len(), &mut pass_ptr);
-    	}
-
-		if res == 0 {
-			Ok(true)
-		} else {
-			match errno() {
-				0 | libc::ENOENT | libc::ESRCH | libc::EBADF | libc::EPERM => Ok(false),
-				_ => Err(errno()),
-			}
+	{
+		match GroupEntry::get_entry_from_group(n) {
+			Ok(_) => Ok(true),
+			Err(0 | libc::ENOENT | libc::ESRCH | libc::EBADF | libc::EPERM) => Ok(false),
+			Err(e) => Err(e),
 		}
 	}
 	#[cfg(target_os = "windows")]
 	unsafe {
+		let name = n.to_string();
 		let mut group: LPGROUP_INFO_0 = null_mut();
 		let gname_utf16 = name.encode_utf16().collect::<Vec<u16>>();
 
@@ -329,20 +446,86 @@ pub fn group_exists<T: ToString>(n: &T) -> Result<bool, i32> {
 	}
 }
 
+/// Get every group the user named `name` belongs to, their primary group first, then supplementary groups
+///
+/// GIDs without a group entry are left out.
+#[cfg(target_os = "linux")]
+pub fn user_groups<T: ToString>(name: &T) -> Result<Vec<GroupEntry>, i32> {
+	let entry = PasswdEntry::get_entry_from_passwd(name)?;
+	let username = match CString::new(name.to_string()) {
+		Ok(s) => s,
+		_ => return Err(-1),
+	};
+
+	let mut gids: Vec<gid_t> = vec![0; 32];
+	loop {
+		let mut ngroups = gids.len() as c_int;
+		let res = unsafe { getgrouplist(username.as_ptr(), entry.gid, gids.as_mut_ptr(), &mut ngroups) };
+
+		if res >= 0 {
+			gids.truncate(ngroups as usize);
+			break;
+		}
+
+		gids.resize((ngroups as usize).max(gids.len() * 2), 0);
+	}
+
+	if let Some(pos) = gids.iter().position(|g| *g == entry.gid) {
+		gids.remove(pos);
+	}
+	gids.insert(0, entry.gid);
+	gids.dedup();
+
+	// A GID with no group entry, like a primary group that was deleted, is skipped as user_groups_in does
+	let mut groups = Vec::new();
+	for gid in gids {
+		match GroupEntry::get_entry_by_gid(gid) {
+			Ok(group) => groups.push(group),
+			Err(0 | libc::ENOENT | libc::ESRCH | libc::EBADF | libc::EPERM) => (),
+			Err(e) => return Err(e),
+		}
+	}
+
+	Ok(groups)
+}
+
+/// Get every group the user named `name` belongs to, using /etc/passwd and /etc/group under the root directory `root`
+///
+/// The primary group comes first, then supplementary groups in file order.
+#[cfg(target_os = "linux")]
+pub fn user_groups_in<A: ToString, B: ToString>(root: &A, name: &B) -> Result<Vec<GroupEntry>, i32> {
+	let name = name.to_string();
+	let user = list_users_in(root)?.into_iter()
+		.find(|u| u.username == name)
+		.ok_or(libc::ENOENT)?;
+
+	let (mut primary, supplementary): (Vec<GroupEntry>, Vec<GroupEntry>) = list_groups_in(root)?.into_iter()
+		.filter(|g| g.gid == user.gid || g.list.contains(&name))
+		.partition(|g| g.gid == user.gid);
+
+	primary.truncate(1);
+	primary.extend(supplementary);
+	Ok(primary)
+}
+
 /// Checks if a user named `uname` is in the group named `gname`.
 /// 
 /// If it returns an [`Ok`] value, the both the user and group exist, and the payload contains if the user is in the group.
 /// If it returns an [`Err`] value, either the user or group doesn't exist
 pub fn user_is_in_group<A: ToString, B: ToString>(u: &A, g: &B) -> Result<bool, i32> {
-	user_exists(u)?;
-	group_exists(g)?;
 	#[cfg(target_os = "linux")]
 	{
-		let group = GroupEntry::get_entry_from_group(g)?;
-		Ok(group.list.contains(&g.to_string()))
+		if !user_exists(u)? || !group_exists(g)? {
+			return Err(libc::ENOENT);
+		}
+
+		let gname = g.to_string();
+		Ok(user_groups(u)?.iter().any(|group| group.groupname == gname))
 	}
 	#[cfg(target_os = "windows")]
 	unsafe {
+		user_exists(u)?;
+		group_exists(g)?;
 		let uname = u.to_string();
 		let gname = g.to_string();
 		let mut groups: LPLOCALGROUP_USERS_INFO_0 = null_mut();
@@ -394,33 +577,137 @@ pub fn user_is_in_group<A: ToString, B: ToString>(u: &A, g: &B) -> Result<bool,
 	}
 }
 
+/// Checks if a user named `u` is in the group named `g`, using /etc/passwd and /etc/group under the root directory `root`
+///
+/// Like [`user_is_in_group`], this returns `Err(ENOENT)` if either the user or group doesn't exist.
+#[cfg(target_os = "linux")]
+pub fn user_is_in_group_in<A: ToString, B: ToString, C: ToString>(root: &A, u: &B, g: &C) -> Result<bool, i32> {
+	let gname = g.to_string();
+	if !list_groups_in(root)?.iter().any(|group| group.groupname == gname) {
+		return Err(libc::ENOENT);
+	}
+
+	Ok(user_groups_in(root, u)?.iter().any(|group| group.groupname == gname))
+}
+
 /// Checks if the user has administrator privileges. 
 /// 
-/// On Linux, it checks if the user is either root, or if they have access to sudo.
+/// On Linux, it checks if the user has UID 0, or if sudoers lets them run commands as root (see [`Sudoers`]).
 /// On Windows, it checks if the user is a member of the Administrators group.
 /// 
 /// If it returns an [`Ok`] value, the user exists and the payload contians if the user has admin privileges
-/// If it returns an [`Err`] value, the user does not exist
+/// If it returns an [`Err`] value, the user does not exist, or sudoers couldn't be read
 pub fn user_is_admin<T: ToString>(name: &T) -> Result<bool, i32> {
     #[cfg(target_os = "linux")]
     {
-        if name.to_string() == "root" {
+        let entry = match PasswdEntry::get_entry_from_passwd(name) {
+            Ok(e) => e,
+            Err(0 | libc::ESRCH | libc::EBADF | libc::EPERM) => return Err(libc::ENOENT),
+            Err(e) => return Err(e),
+        };
+
+        if entry.uid == 0 {
             Ok(true)
         } else {
-            if user_exists(name).unwrap_or(false) {
-                let cmd = Command::new("sudo")
-                    .args(&["-l", "-U", &format!("{}", name.to_string())]).stderr(Stdio::null()).stdout(Stdio::piped())
-		            .output().expect("Sudo failed to run.");
-                let mensaje = format!("User {} is not allowed to run sudo", name.to_string());
-
-                Ok(!String::from_utf8_lossy(&cmd.stdout).contains(&mensaje))
-            } else {
-                Err(errno())
-            }
+            Sudoers::load()?.can_run_as_root(name)
         }
     }
     #[cfg(target_os = "windows")]
     {
         user_is_in_group(name, &"Administrators")
     }
-}
\ No newline at end of file
+}
+
+/// Checks if the user has administrator privileges, using /etc/passwd and /etc/sudoers under the root directory `root`
+///
+/// Returns `Err(ENOENT)` if the user doesn't exist, or sudoers couldn't be found.
+#[cfg(target_os = "linux")]
+pub fn user_is_admin_in<A: ToString, B: ToString>(root: &A, name: &B) -> Result<bool, i32> {
+	let name = name.to_string();
+	let entry = list_users_in(root)?.into_iter()
+		.find(|u| u.username == name)
+		.ok_or(libc::ENOENT)?;
+
+	if entry.uid == 0 {
+		Ok(true)
+	} else {
+		Sudoers::load_in(root)?.can_run_as_root(&name)
+	}
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+	use super::*;
+	use crate::util::{test_root, write_rooted};
+
+	fn fixture(name: &str) -> std::path::PathBuf {
+		let root = test_root(&format!("user-{}", name));
+
+		write_rooted(&root, "/etc/passwd", "\
+root:x:0:0:root:/root:/bin/bash
+alice:x:1000:1000:Alice:/home/alice:/bin/bash
+bob:x:1001:100:Bob:/home/bob:/bin/bash
+");
+		write_rooted(&root, "/etc/group", "\
+root:x:0:
+users:x:100:
+sudo:x:27:alice,carol
+alice:x:1000:
+adm:x:4:bob,alice
+");
+
+		root
+	}
+
+	#[test]
+	fn groups_include_primary_first() {
+		let dir = fixture("primary");
+		let root = dir.display();
+
+		let names: Vec<String> = user_groups_in(&root, &"alice").unwrap().into_iter().map(|g| g.groupname).collect();
+		assert_eq!(names, ["alice", "sudo", "adm"]);
+
+		// bob's primary group lists nobody as a member
+		let names: Vec<String> = user_groups_in(&root, &"bob").unwrap().into_iter().map(|g| g.groupname).collect();
+		assert_eq!(names, ["users", "adm"]);
+
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn membership_by_primary_and_supplementary_group() {
+		let dir = fixture("member");
+		let root = dir.display();
+
+		assert_eq!(user_is_in_group_in(&root, &"bob", &"users"), Ok(true));
+		assert_eq!(user_is_in_group_in(&root, &"alice", &"sudo"), Ok(true));
+		assert_eq!(user_is_in_group_in(&root, &"bob", &"sudo"), Ok(false));
+		assert_eq!(user_is_in_group_in(&root, &"root", &"users"), Ok(false));
+
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn passwd_entries() {
+		let entry = PasswdEntry::parse_entry(&"alice:x:1000:1000:Alice:/home/alice:/bin/bash");
+		assert_eq!((entry.uid, entry.gid, entry.shell.as_str()), (1000, 1000, "/bin/bash"));
+		assert!(entry.password_in_shadow);
+
+		assert!(PasswdEntry::try_parse_entry(&"alice:x:1000:1000:Alice:/home/alice:/bin/bash").is_ok());
+		assert_eq!(PasswdEntry::try_parse_entry(&"alice:x:1000:1000").map(|e| e.uid), Err(-1));
+		assert_eq!(PasswdEntry::try_parse_entry(&"alice:x:abc:1000::/:/bin/sh").map(|e| e.uid), Err(-1));
+	}
+
+	#[test]
+	fn missing_user_or_group_is_enoent() {
+		let dir = fixture("missing");
+		let root = dir.display();
+
+		// carol is listed in sudo but has no account
+		assert_eq!(user_groups_in(&root, &"carol").map(|g| g.len()), Err(libc::ENOENT));
+		assert_eq!(user_is_in_group_in(&root, &"carol", &"sudo"), Err(libc::ENOENT));
+		assert_eq!(user_is_in_group_in(&root, &"alice", &"wheel"), Err(libc::ENOENT));
+
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,204 @@
+/*
+*   SPDX-License-Identifier: GPL-3.0-only
+*   A cyberpatriots scoring engine library
+*   Copyright (C) 2023 Teresa Maria Rivera
+*/
+
+use std::{
+	cmp::Ordering,
+	fmt,
+	str::FromStr,
+	string::String,
+};
+
+/// A package version, compared by the rules of Debian policy
+///
+/// A version is `[epoch:]upstream[-revision]`. The epoch wins outright, then upstream and revision are compared
+/// alternating non-digit and digit runs, where letters sort before other characters and `~` sorts before anything,
+/// even the end of the string, so `1.0~rc1` is older than `1.0`.
+///
+/// The same rules order snap and flatpak versions sensibly too, so they're parsed with this as well.
+#[derive(Clone, Debug)]
+pub struct DebVersion {
+	pub epoch: u64,
+	pub upstream: String,
+	/// The Debian revision, empty if there is none
+	pub revision: String,
+}
+
+impl DebVersion {
+	/// Parse a version string
+	///
+	/// Returns `Err(-1)` if the string is empty, the epoch isn't a number, or there is nothing but an epoch and revision.
+	pub fn parse<T: ToString>(version: &T) -> Result<DebVersion, i32> {
+		let version = version.to_string();
+		let version = version.trim();
+
+		let (epoch, rest) = match version.split_once(':') {
+			Some((e, rest)) => (e.parse::<u64>().map_err(|_| -1)?, rest),
+			None => (0, version),
+		};
+
+		let (upstream, revision) = match rest.rsplit_once('-') {
+			Some((u, r)) => (u, r),
+			None => (rest, ""),
+		};
+
+		if upstream.is_empty() {
+			return Err(-1);
+		}
+
+		Ok(DebVersion { epoch, upstream: upstream.to_string(), revision: revision.to_string() })
+	}
+}
+
+/// The sort weight of a character in the non-digit part of a version
+fn order(c: Option<u8>) -> i32 {
+	match c {
+		None => 0,
+		Some(c) if c.is_ascii_digit() => 0,
+		Some(c) if c.is_ascii_alphabetic() => c as i32,
+		Some(b'~') => -1,
+		Some(c) => c as i32 + 256,
+	}
+}
+
+/// Compare two upstream versions or revisions, as dpkg's `verrevcmp` does
+fn compare_part(a: &str, b: &str) -> Ordering {
+	let (a, b) = (a.as_bytes(), b.as_bytes());
+	let (mut i, mut j) = (0, 0);
+
+	while i < a.len() || j < b.len() {
+		while (i < a.len() && !a[i].is_ascii_digit()) || (j < b.len() && !b[j].is_ascii_digit()) {
+			let ac = order(a.get(i).copied());
+			let bc = order(b.get(j).copied());
+			if ac != bc {
+				return ac.cmp(&bc);
+			}
+			i += 1;
+			j += 1;
+		}
+
+		while i < a.len() && a[i] == b'0' {
+			i += 1;
+		}
+		while j < b.len() && b[j] == b'0' {
+			j += 1;
+		}
+
+		let mut first_diff = Ordering::Equal;
+		while i < a.len() && a[i].is_ascii_digit() && j < b.len() && b[j].is_ascii_digit() {
+			if first_diff == Ordering::Equal {
+				first_diff = a[i].cmp(&b[j]);
+			}
+			i += 1;
+			j += 1;
+		}
+
+		if i < a.len() && a[i].is_ascii_digit() {
+			return Ordering::Greater;
+		}
+		if j < b.len() && b[j].is_ascii_digit() {
+			return Ordering::Less;
+		}
+		if first_diff != Ordering::Equal {
+			return first_diff;
+		}
+	}
+
+	Ordering::Equal
+}
+
+impl Ord for DebVersion {
+	fn cmp(&self, other: &Self) -> Ordering {
+		self.epoch.cmp(&other.epoch)
+			.then_with(|| compare_part(&self.upstream, &other.upstream))
+			.then_with(|| compare_part(&self.revision, &other.revision))
+	}
+}
+
+impl PartialOrd for DebVersion {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl PartialEq for DebVersion {
+	fn eq(&self, other: &Self) -> bool {
+		self.cmp(other) == Ordering::Equal
+	}
+}
+
+impl Eq for DebVersion {}
+
+impl FromStr for DebVersion {
+	type Err = i32;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		DebVersion::parse(&s)
+	}
+}
+
+impl fmt::Display for DebVersion {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.epoch != 0 {
+			write!(f, "{}:", self.epoch)?;
+		}
+		write!(f, "{}", self.upstream)?;
+		if !self.revision.is_empty() {
+			write!(f, "-{}", self.revision)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn compare_like_dpkg() {
+		// Each expected result is what `dpkg --compare-versions a lt|eq|gt b` agrees with
+		let cases = [
+			("1.0~rc1", "1.0", Ordering::Less),
+			("1.0~~", "1.0~", Ordering::Less),
+			("1.0-1~bpo1", "1.0-1", Ordering::Less),
+			("1.0", "1.0+b1", Ordering::Less),
+			("1.0a", "1.0", Ordering::Greater),
+			("1.0a", "1.0+", Ordering::Less),
+			("1:0.1", "2.0", Ordering::Greater),
+			("1:1.0", "0:1.0", Ordering::Greater),
+			("0:1.0", "1.0", Ordering::Equal),
+			("1.0-1", "1.0-2", Ordering::Less),
+			("1.0-10", "1.0-9", Ordering::Greater),
+			("1.0-1ubuntu1", "1.0-1", Ordering::Greater),
+			("2.39-0ubuntu8", "2.39-0ubuntu8.1", Ordering::Less),
+			("1-2-3", "1-2", Ordering::Greater),
+			("1.001", "1.1", Ordering::Equal),
+			("1.2.3", "1.2.10", Ordering::Less),
+			("1.0.0", "1.0", Ordering::Greater),
+			("7.94+git20230807", "7.94", Ordering::Greater),
+		];
+
+		for (a, b, expected) in cases {
+			let (va, vb) = (DebVersion::parse(&a).unwrap(), DebVersion::parse(&b).unwrap());
+			assert_eq!(va.cmp(&vb), expected, "{} vs {}", a, b);
+			assert_eq!(vb.cmp(&va), expected.reverse(), "{} vs {}", b, a);
+		}
+	}
+
+	#[test]
+	fn parse_versions() {
+		let v = DebVersion::parse(&" 1:9.6p1-3ubuntu13 ").unwrap();
+		assert_eq!((v.epoch, v.upstream.as_str(), v.revision.as_str()), (1, "9.6p1", "3ubuntu13"));
+		assert_eq!(v.to_string(), "1:9.6p1-3ubuntu13");
+
+		let v: DebVersion = "1.2-3-4".parse().unwrap();
+		assert_eq!((v.upstream.as_str(), v.revision.as_str()), ("1.2-3", "4"));
+		assert_eq!(DebVersion::parse(&"0:2.0").unwrap().to_string(), "2.0");
+
+		for bad in ["", "   ", "x:1.0", "-1:1.0", "1:", "1:-2", "-2"] {
+			assert_eq!(DebVersion::parse(&bad).err(), Some(-1), "{:?}", bad);
+		}
+	}
+}